@@ -0,0 +1,117 @@
+use crate::color::Color;
+use crate::error::Error;
+use crate::film::Film;
+use crate::image::Image;
+use crate::options::Options;
+
+// gap between the tiles of the contact sheet, in pixels
+const SHEET_GAP: usize = 4;
+
+// prints a swept value without floating point noise, 0.1 * 3 is `0.3`
+fn format_value(value: f64) -> String {
+    let text = format!("{:.6}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+// `start..end:steps` for evenly spaced numbers including both ends, or a comma separated list
+pub fn sweep_values(spec: &str) -> Result<Vec<String>, Error> {
+    if let Some((range, steps)) = spec.split_once(':') {
+        let (start, end) = range
+            .split_once("..")
+            .ok_or(format!("expected 'start..end:steps', got '{}'", spec))?;
+        let number = |s: &str| {
+            s.trim()
+                .parse::<f64>()
+                .map_err(|_| format!("invalid number '{}'", s))
+        };
+        let (start, end) = (number(start)?, number(end)?);
+        let steps: usize = steps
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid step count '{}'", steps))?;
+        return Ok(match steps {
+            0 => Vec::new(),
+            1 => vec![format_value(start)],
+            _ => (0..steps)
+                .map(|i| format_value(start + (end - start) * i as f64 / (steps - 1) as f64))
+                .collect(),
+        });
+    }
+    Ok(spec.split(',').map(|v| v.trim().to_string()).collect())
+}
+
+// `{key}` in the template is replaced by the value of that swept key and `{index}` by the
+// index of the render; without any placeholder the index is appended to the file stem
+fn output_name(template: &str, combination: &[(&str, &str)], index: usize) -> String {
+    let mut name = template.replace("{index}", &index.to_string());
+    for (key, value) in combination {
+        name = name.replace(&format!("{{{}}}", key), value);
+    }
+    if name != template {
+        return name;
+    }
+    match template.rfind('.') {
+        Some(dot) => format!("{}_{}{}", &template[..dot], index, &template[dot..]),
+        None => format!("{}_{}", template, index),
+    }
+}
+
+// Renders every combination of the swept values on top of the options given by `args`,
+// the last sweep varies fastest. The contact sheet lays the renders out in a grid with
+// a column per value of the last sweep.
+pub fn run(args: &[String], options: &Options) -> Result<(), Error> {
+    let sweeps = &options.sweeps;
+    let total: usize = sweeps.iter().map(|(_, values)| values.len()).product();
+    let columns = sweeps.last().map_or(1, |(_, values)| values.len()).max(1);
+    let mut tiles = Vec::with_capacity(total);
+    for index in 0..total {
+        let mut rest = index;
+        let mut combination = Vec::with_capacity(sweeps.len());
+        for (key, values) in sweeps.iter().rev() {
+            combination.push((key.as_str(), values[rest % values.len()].as_str()));
+            rest /= values.len();
+        }
+        combination.reverse();
+
+        let mut options = Options::load(args.iter().cloned())?;
+        for (key, value) in combination.iter() {
+            options.set(key, value)?;
+        }
+        options.validate()?;
+        let output = output_name(&options.output, &combination, index);
+        let description: Vec<String> = combination
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        info!(
+            "[{}/{}] {} -> {}",
+            index + 1,
+            total,
+            description.join(" "),
+            output
+        );
+        let (image, stats) = crate::render_image(&options);
+        crate::output(&options, &output, &image, stats)?;
+        if options.contact_sheet.is_some() {
+            tiles.push(image);
+        }
+    }
+
+    if let Some(path) = &options.contact_sheet {
+        let tile_width = tiles.iter().map(|t| t.width()).max().unwrap_or(0);
+        let tile_height = tiles.iter().map(|t| t.height()).max().unwrap_or(0);
+        let rows = tiles.len().div_ceil(columns);
+        let width = columns * tile_width + (columns + 1) * SHEET_GAP;
+        let height = rows * tile_height + (rows + 1) * SHEET_GAP;
+        let mut sheet = Film::new(width, height, Color::BLACK);
+        for (n, tile) in tiles.iter().enumerate() {
+            let x0 = SHEET_GAP + (n % columns) * (tile_width + SHEET_GAP);
+            let y0 = SHEET_GAP + (n / columns) * (tile_height + SHEET_GAP);
+            sheet.blit(x0, y0, &tile.color);
+        }
+        let sheet = Image::new(sheet, None);
+        let path = &options.output_path(path);
+        sheet.save(path, &options.encoding(path), &[])?;
+    }
+    Ok(())
+}
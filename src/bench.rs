@@ -0,0 +1,143 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+use crate::image::{Encoding, Format, Image};
+use crate::options::Options;
+use crate::ray::Ray;
+use crate::render::render;
+use crate::scene::Scene;
+
+// The reference scene of `--bench`: the default fireball over the checker floor with
+// supersampling, so timings stay comparable between commits.
+const REFERENCE: [(&str, &str); 5] = [
+    ("width", "200"),
+    ("height", "150"),
+    ("spp", "2"),
+    ("floor", "-1.5"),
+    ("seed", "1"),
+];
+
+thread_local! {
+    static RAYS: Cell<u64> = const { Cell::new(0) };
+    static EVALUATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+// every ray cast into the scene, camera and shadow rays alike
+pub fn count_ray() {
+    RAYS.with(|c| c.set(c.get() + 1));
+}
+
+// every evaluation of a distance field or of the volume density
+pub fn count_evaluation() {
+    EVALUATIONS.with(|c| c.set(c.get() + 1));
+}
+
+// rays and evaluations of the thread since the last call
+pub fn take_counts() -> (u64, u64) {
+    (
+        RAYS.with(|c| c.replace(0)),
+        EVALUATIONS.with(|c| c.replace(0)),
+    )
+}
+
+// hands the counts of a finished render thread to the one that takes them
+pub fn add_counts((rays, evaluations): (u64, u64)) {
+    RAYS.with(|c| c.set(c.get() + rays));
+    EVALUATIONS.with(|c| c.set(c.get() + evaluations));
+}
+
+fn seconds(durations: &[Duration]) -> f64 {
+    durations.iter().map(Duration::as_secs_f64).sum::<f64>() / durations.len().max(1) as f64
+}
+
+// Renders the reference scene `bench_runs` times and reports throughput and the time
+// spent per stage: primary rays (camera rays against the scene without shading, timed
+// separately and scaled to the samples of the render), the shading on top of them, and
+// the PNG encoding. The summary goes to stdout as JSON.
+pub fn run(options: &Options) -> Result<(), Error> {
+    let mut reference = Options::default();
+    for (key, value) in REFERENCE {
+        reference.set(key, value)?;
+    }
+    let runs = options.bench_runs.max(1);
+    let camera = crate::default_camera(&reference);
+    let pixels = camera.width * camera.height;
+    let encoding = Encoding {
+        format: Format::Png,
+        ..reference.encoding(&reference.output)
+    };
+
+    let (mut primary, mut total, mut encode) = (Vec::new(), Vec::new(), Vec::new());
+    let (mut rays, mut evaluations) = (0, 0);
+    for run in 0..runs {
+        let start = Instant::now();
+        let scene = Scene::new(&reference);
+        for j in 0..camera.height {
+            for i in 0..camera.width {
+                let dir = camera.ray_dir(i as f64 + 0.5, j as f64 + 0.5);
+                std::hint::black_box(scene.intersect(Ray::new(camera.position, dir)));
+            }
+        }
+        let visibility = start.elapsed();
+        take_counts();
+
+        let start = Instant::now();
+        let frame = render(&camera, &reference);
+        total.push(start.elapsed());
+        // the render traces more than one camera ray per pixel
+        primary.push(visibility.mul_f64(frame.samples as f64 / pixels as f64));
+        (rays, evaluations) = take_counts();
+
+        let start = Instant::now();
+        let image = Image::new(frame.color, None);
+        std::hint::black_box(image.encode(&encoding, &[]));
+        encode.push(start.elapsed());
+        info!(
+            "run {}/{}: {:.3} s",
+            run + 1,
+            runs,
+            total[run].as_secs_f64()
+        );
+    }
+
+    let render_seconds = seconds(&total);
+    let best = total.iter().min().unwrap().as_secs_f64();
+    let primary_seconds = seconds(&primary);
+    let shading_seconds = (render_seconds - primary_seconds).max(0.);
+    let encode_seconds = seconds(&encode);
+    let mpixels = pixels as f64 / render_seconds / 1e6;
+    let mrays = rays as f64 / render_seconds / 1e6;
+    let evaluation_rate = evaluations as f64 / render_seconds;
+    info!(
+        "{:.3} Mpixels/s, {:.3} Mrays/s, {:.3e} SDF evaluations/s",
+        mpixels, mrays, evaluation_rate
+    );
+    info!(
+        "primary rays {:.3} s, shading {:.3} s, encode {:.3} s",
+        primary_seconds, shading_seconds, encode_seconds
+    );
+    println!(
+        "{{\"version\": \"{}\", \"width\": {}, \"height\": {}, \"spp\": {}, \"runs\": {}, \
+         \"render_seconds\": {}, \"best_render_seconds\": {}, \"primary_seconds\": {}, \
+         \"shading_seconds\": {}, \"encode_seconds\": {}, \"rays\": {}, \
+         \"sdf_evaluations\": {}, \"mpixels_per_second\": {}, \"mrays_per_second\": {}, \
+         \"sdf_evaluations_per_second\": {}}}",
+        env!("CARGO_PKG_VERSION"),
+        camera.width,
+        camera.height,
+        reference.spp,
+        runs,
+        render_seconds,
+        best,
+        primary_seconds,
+        shading_seconds,
+        encode_seconds,
+        rays,
+        evaluations,
+        mpixels,
+        mrays,
+        evaluation_rate
+    );
+    Ok(())
+}
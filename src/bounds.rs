@@ -0,0 +1,189 @@
+#![allow(dead_code)]
+use crate::ray::Ray;
+#[cfg(not(feature = "std"))]
+use crate::vec3d::Float;
+use crate::vec3d::Vec3d;
+
+// Axis-aligned box, empty while `min` exceeds `max` along any axis.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Aabb {
+    pub min: Vec3d,
+    pub max: Vec3d,
+}
+
+impl Aabb {
+    // contains nothing and is the identity of `union`
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3d::splat(f64::INFINITY),
+        max: Vec3d::splat(f64::NEG_INFINITY),
+    };
+
+    // the box spanned by two opposite corners in any order
+    pub fn new(a: Vec3d, b: Vec3d) -> Aabb {
+        Aabb {
+            min: a.min(b),
+            max: a.max(b),
+        }
+    }
+
+    pub fn around(center: Vec3d, half_size: Vec3d) -> Aabb {
+        Aabb::new(center - half_size, center + half_size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn center(&self) -> Vec3d {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3d {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.;
+        }
+        let s = self.size();
+        2. * (s.x * s.y + s.y * s.z + s.z * s.x)
+    }
+
+    // 0, 1 or 2 for the axis the box is widest along
+    pub fn longest_axis(&self) -> usize {
+        let s = self.size();
+        if s.x >= s.y && s.x >= s.z {
+            0
+        } else if s.y >= s.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn contains(&self, p: Vec3d) -> bool {
+        (self.min.x..=self.max.x).contains(&p.x)
+            && (self.min.y..=self.max.y).contains(&p.y)
+            && (self.min.z..=self.max.z).contains(&p.z)
+    }
+
+    pub fn union(self, other: Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    // the smallest box holding `self` and `p`
+    pub fn expand(self, p: Vec3d) -> Aabb {
+        Aabb {
+            min: self.min.min(p),
+            max: self.max.max(p),
+        }
+    }
+
+    // padded by `margin` on every side
+    pub fn grow(self, margin: f64) -> Aabb {
+        Aabb {
+            min: self.min - Vec3d::splat(margin),
+            max: self.max + Vec3d::splat(margin),
+        }
+    }
+
+    // Entry and exit distances along the ray, the entry clamped to its origin. Slab test,
+    // rays parallel to a slab get infinite bounds from it.
+    pub fn hit(&self, ray: Ray) -> Option<(f64, f64)> {
+        let inv = ray.dir.recip();
+        let t0 = (self.min - ray.origin).mul_elem(inv);
+        let t1 = (self.max - ray.origin).mul_elem(inv);
+        let near = t0.min(t1).max_element().max(0.);
+        let far = t0.max(t1).min_element();
+        (near <= far).then_some((near, far))
+    }
+
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere::new(self.center(), self.size().length() / 2.)
+    }
+
+    // Shrinks `self` to the cells of a `cells`^3 grid the zero set of `sdf` can pass through:
+    // a cell whose center is further from the surface than half its diagonal holds none of it.
+    // Conservative as long as `sdf` never overestimates the distance, empty when the surface
+    // misses the box.
+    pub fn fit_sdf(self, sdf: impl Fn(Vec3d) -> f64, cells: usize) -> Aabb {
+        let cell = self.size() / cells as f64;
+        let half_diagonal = cell.length() / 2.;
+        let mut fitted = Aabb::EMPTY;
+        for i in 0..cells {
+            for j in 0..cells {
+                for k in 0..cells {
+                    let corner = self.min + cell.mul_elem(Vec3d::new(i as f64, j as f64, k as f64));
+                    if sdf(corner + cell * 0.5).abs() <= half_diagonal {
+                        fitted = fitted.union(Aabb::new(corner, corner + cell));
+                    }
+                }
+            }
+        }
+        fitted
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct BoundingSphere {
+    pub center: Vec3d,
+    pub radius: f64,
+}
+
+impl BoundingSphere {
+    pub fn new(center: Vec3d, radius: f64) -> BoundingSphere {
+        BoundingSphere { center, radius }
+    }
+
+    pub fn contains(&self, p: Vec3d) -> bool {
+        (p - self.center).length_squared() <= self.radius * self.radius
+    }
+
+    // the smallest sphere holding both
+    pub fn union(self, other: BoundingSphere) -> BoundingSphere {
+        let offset = other.center - self.center;
+        let distance = offset.length();
+        if distance + other.radius <= self.radius {
+            return self;
+        }
+        if distance + self.radius <= other.radius {
+            return other;
+        }
+        let radius = (distance + self.radius + other.radius) / 2.;
+        let center = self.center + offset * ((radius - self.radius) / distance);
+        BoundingSphere { center, radius }
+    }
+
+    // the smallest sphere holding `self` and `p`
+    pub fn expand(self, p: Vec3d) -> BoundingSphere {
+        self.union(BoundingSphere::new(p, 0.))
+    }
+
+    pub fn grow(self, margin: f64) -> BoundingSphere {
+        BoundingSphere::new(self.center, self.radius + margin)
+    }
+
+    // entry and exit distances along the ray, the entry clamped to its origin
+    pub fn hit(&self, ray: Ray) -> Option<(f64, f64)> {
+        let orig = ray.origin - self.center;
+        let b = orig.dot(ray.dir);
+        let c = orig.dot(orig) - self.radius * self.radius;
+        let disc = b * b - c;
+        if disc < 0. {
+            return None;
+        }
+        let (t0, t1) = (-b - disc.sqrt(), -b + disc.sqrt());
+        if t1 <= 0. {
+            return None;
+        }
+        Some((t0.max(0.), t1))
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::around(self.center, Vec3d::splat(self.radius))
+    }
+}
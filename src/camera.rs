@@ -0,0 +1,216 @@
+use crate::matrix::Mat4;
+use crate::quat::Quat;
+use crate::ray::Ray;
+use crate::sampling::{sample_disk, sample_polygon};
+use crate::vec3d::{Vec2d, Vec3d};
+
+// Newton steps taking a distorted image point back to the pinhole one
+const UNDISTORT_STEPS: usize = 8;
+
+// Thin lens for depth of field: rays leave the aperture of `radius` around the camera and
+// meet again on the plane `focus` in front of it, so only that plane is sharp. The aperture
+// is round, or a polygon of `blades` turned by `rotation`, which out-of-focus highlights
+// take the shape of. `cat_eye` squeezes it towards the edges of the image, where the lens
+// barrel clips it, from 0 for none to 1 closing it in the corners.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Lens {
+    pub radius: f64,
+    pub focus: f64,
+    // 0 for a round aperture
+    pub blades: usize,
+    // in radians, given in degrees
+    pub rotation: f64,
+    pub cat_eye: f64,
+}
+
+impl Lens {
+    pub const PINHOLE: Lens = Lens {
+        radius: 0.,
+        focus: 3.,
+        blades: 0,
+        rotation: 0.,
+        cat_eye: 0.,
+    };
+}
+
+#[derive(Copy, Clone)]
+pub struct Camera {
+    pub position: Vec3d,
+    // from camera space, where the camera looks down -z with y up, to the world, and back
+    pub to_world: Mat4,
+    pub view: Mat4,
+    pub fov: f64,
+    pub width: usize,
+    pub height: usize,
+    // horizontal frustum shift in pixels, non-zero only for off-axis stereo eyes
+    pub shift: f64,
+    // Brown-Conrady radial distortion k1, k2: a point `r` focal lengths from the image
+    // center is seen at r (1 + k1 r^2 + k2 r^4), barrel for negative k1 and pincushion for
+    // positive, as calibrated from footage
+    pub distortion: Vec2d,
+}
+
+impl Camera {
+    pub fn new(position: Vec3d, fov: f64, width: usize, height: usize) -> Camera {
+        Camera {
+            position,
+            to_world: Mat4::translation(position),
+            view: Mat4::translation(-position),
+            fov,
+            width,
+            height,
+            shift: 0.,
+            distortion: Vec2d::zero(),
+        }
+    }
+
+    // The camera placed by the world-to-camera transform `view`, a rigid one such as
+    // `Mat4::look_at`; a singular one leaves it as it is.
+    pub fn with_view(self, view: Mat4) -> Camera {
+        match view.inverse() {
+            Some(to_world) => Camera {
+                position: to_world.transform_point(Vec3d::zero()),
+                to_world,
+                view,
+                ..self
+            },
+            None => self,
+        }
+    }
+
+    // the camera turned towards `target`, with the horizon level
+    pub fn looking_at(self, target: Vec3d) -> Camera {
+        Camera {
+            // exactly where it was, rather than as rounded by the inverse
+            position: self.position,
+            ..self.with_view(Mat4::look_at(self.position, target, Vec3d::new(0., 1., 0.)))
+        }
+    }
+
+    // The rotation swinging the camera `yaw` radians to the right about the vertical and
+    // `pitch` radians up about its own horizontal axis, for `orbit`.
+    pub fn orbit_rotation(&self, yaw: f64, pitch: f64) -> Quat {
+        let right = self.to_world.transform_vector(Vec3d::new(1., 0., 0.));
+        Quat::from_axis_angle(Vec3d::new(0., 1., 0.), yaw) * Quat::from_axis_angle(right, -pitch)
+    }
+
+    // The camera swung around `pivot` by `rotation` and turned along with it, so what it sees
+    // of the pivot stays in place. Past the poles it goes over the top, upside down.
+    pub fn orbit(self, pivot: Vec3d, rotation: Quat) -> Camera {
+        let back = Mat4::translation(pivot)
+            * Mat4::from(rotation.conjugate().to_mat3())
+            * Mat4::translation(-pivot);
+        Camera {
+            position: pivot + rotation * (self.position - pivot),
+            ..self.with_view(self.view * back)
+        }
+    }
+
+    // how far the distortion pushes out a point `r` focal lengths from the image center
+    fn distortion_scale(&self, r: f64) -> f64 {
+        let r2 = r * r;
+        1. + self.distortion.x * r2 + self.distortion.y * r2 * r2
+    }
+
+    // the pinhole image point that the distortion moves to `p`, both in pixels from the
+    // image center, by Newton's method on its distance from the center
+    fn undistort(&self, p: Vec2d) -> Vec2d {
+        let f = self.focal_length();
+        let target = p.length() / f;
+        if target == 0. {
+            return p;
+        }
+        let (k1, k2) = (self.distortion.x, self.distortion.y);
+        let mut r = target;
+        for _ in 0..UNDISTORT_STEPS {
+            let r2 = r * r;
+            let slope = 1. + 3. * k1 * r2 + 5. * k2 * r2 * r2;
+            if slope <= 0. {
+                // past the fold of a strong barrel, where the image turns back
+                break;
+            }
+            r -= (r * self.distortion_scale(r) - target) / slope;
+        }
+        p * (r / target)
+    }
+
+    fn focal_length(&self) -> f64 {
+        self.height as f64 / (2. * (self.fov / 2.).tan())
+    }
+
+    // width of a pixel at unit distance in front of the camera
+    pub fn pixel_spread(&self) -> f64 {
+        1. / self.focal_length()
+    }
+
+    // `x` and `y` are continuous pixel coordinates, (0.5, 0.5) is the center of the top-left pixel
+    pub fn ray_dir(&self, x: f64, y: f64) -> Vec3d {
+        self.to_world.transform_vector(self.local_dir(x, y))
+    }
+
+    // `ray_dir` in camera space
+    fn local_dir(&self, x: f64, y: f64) -> Vec3d {
+        let w = self.width as f64;
+        let h = self.height as f64;
+        let mut p = Vec2d::new(x - w / 2., -y + h / 2.);
+        if self.distortion != Vec2d::zero() {
+            p = self.undistort(p);
+        }
+        let dir_z = -self.focal_length();
+        Vec3d::new(p.x + self.shift, p.y, dir_z).normalized()
+    }
+
+    // The ray through the image point (x, y) from the point of the `lens` aperture picked by
+    // the sample `u`, through the same point of the focus plane as the pinhole ray.
+    pub fn lens_ray(&self, lens: &Lens, x: f64, y: f64, u: Vec2d) -> Ray {
+        let dir = self.local_dir(x, y);
+        let focus = dir * (lens.focus / -dir.z);
+        let mut aperture = match lens.blades {
+            0 => sample_disk(u.x, u.y),
+            blades => sample_polygon(blades, lens.rotation, u.x, u.y),
+        };
+        // the barrel cuts the aperture across the direction to the image center
+        let (w, h) = (self.width as f64, self.height as f64);
+        let off_center = Vec2d::new(x - w / 2., h / 2. - y) / (Vec2d::new(w, h).length() / 2.);
+        if lens.cat_eye > 0. && off_center.length() > 0. {
+            let radial = off_center.normalized();
+            let squeeze = 1. - lens.cat_eye * off_center.length().min(1.);
+            aperture += radial * (aperture.dot(radial) * (squeeze - 1.));
+        }
+        let origin = aperture.extend(0.) * lens.radius;
+        Ray::new(
+            self.to_world.transform_point(origin),
+            self.to_world
+                .transform_vector((focus - origin).normalized()),
+        )
+    }
+
+    // continuous pixel coordinates of the point `offset` away from the camera, the inverse
+    // of `ray_dir`; nothing for points behind the camera
+    pub fn project(&self, offset: Vec3d) -> Option<Vec2d> {
+        let offset = self.view.transform_vector(offset);
+        if offset.z >= 0. {
+            return None;
+        }
+        let scale = -self.focal_length() / offset.z;
+        let mut p = Vec2d::new(offset.x * scale - self.shift, offset.y * scale);
+        if self.distortion != Vec2d::zero() {
+            p = p * self.distortion_scale(p.length() / self.focal_length());
+        }
+        Some(Vec2d::new(
+            p.x + self.width as f64 / 2.,
+            self.height as f64 / 2. - p.y,
+        ))
+    }
+
+    // Off-axis (parallel) stereo eye: the eye is moved sideways by `offset` and the frustum
+    // is sheared back so that both eyes agree on the plane at `convergence` distance.
+    pub fn eye(&self, offset: f64, convergence: f64) -> Camera {
+        let sideways = Mat4::translation(Vec3d::new(-offset, 0., 0.));
+        Camera {
+            position: self.position + self.to_world.transform_vector(Vec3d::new(offset, 0., 0.)),
+            shift: self.shift - offset * self.focal_length() / convergence,
+            ..self.with_view(sideways * self.view)
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashSet;
+use std::mem::size_of;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::color::Color;
+use crate::error::Error;
+use crate::options::Options;
+use crate::render::{finish, render_pass};
+use crate::scene::{Scene, Shape};
+use crate::vec3d::Vec3d;
+
+// camera samples of the probe render timed for the estimate
+const PROBE_SAMPLES: f64 = 4096.;
+
+// The references a render only trips over once it runs: links of the light to objects the
+// scene doesn't have, and objects of a shape without the mesh, formula or plugin to trace.
+fn check_references(options: &Options, scene: &Scene) -> Result<(), Error> {
+    let count = scene.objects.len();
+    if let Some(&n) = options
+        .light
+        .objects
+        .iter()
+        .flatten()
+        .find(|&&n| n >= count)
+    {
+        return Err(Error::Invalid(format!(
+            "light-objects: there is no object {}, the scene has {}",
+            n + 1,
+            count
+        )));
+    }
+    for (n, object) in options.objects.iter().enumerate() {
+        let (shape, missing) = match object.shape {
+            Shape::Mesh if object.mesh.is_none() => ("mesh", "a mesh file"),
+            Shape::Expression if object.expression.is_none() => ("expression", "an sdf"),
+            Shape::Plugin if object.plugin.is_none() => ("plugin", "a plugin"),
+            _ => continue,
+        };
+        return Err(Error::Invalid(format!(
+            "object {}: {} shape without {}",
+            n + 1,
+            shape,
+            missing
+        )));
+    }
+    Ok(())
+}
+
+// views rendered of every frame, both eyes for stereo
+fn eyes(options: &Options) -> usize {
+    match options.stereo {
+        Some(_) => 2,
+        None => 1,
+    }
+}
+
+// frames rendered for the options, the sweeps and stereo eyes counted as frames too
+fn frames(options: &Options) -> usize {
+    let sweeps: usize = options
+        .sweeps
+        .iter()
+        .map(|(_, values)| values.len())
+        .product();
+    options.sprite_frames.max(1) * eyes(options) * sweeps
+}
+
+// Rough peak memory of the render: the films of a frame and the image made of them for
+// every pixel of the output, all the frames of a sprite sheet, and the noise table, meshes
+// and heightmaps of the scene, counted once however many objects share them.
+fn memory(options: &Options, scene: &Scene) -> usize {
+    let (width, height) = options.resolution();
+    let pixel = 2 * size_of::<Color>() + size_of::<Vec3d>() + 3 * size_of::<f64>();
+    let mut bytes = width * height * pixel * eyes(options) * options.sprite_frames.max(1);
+    let mut shared = HashSet::new();
+    let mut add = |data: *const (), size: usize| {
+        if shared.insert(data) {
+            bytes += size;
+        }
+    };
+    for object in scene.objects.iter() {
+        if let Some(table) = &object.noise_table {
+            add(Arc::as_ptr(table).cast(), table.bytes());
+        }
+        if let Some(mesh) = &object.mesh {
+            let size = mesh.vertices.len() * size_of::<Vec3d>()
+                + mesh.triangles.len() * size_of::<[usize; 3]>();
+            add(Arc::as_ptr(mesh).cast(), size);
+        }
+        if let Some(sdf) = &object.mesh_sdf {
+            add(Arc::as_ptr(sdf).cast(), sdf.bytes());
+        }
+        if let Some(map) = &object.heightmap {
+            add(Arc::as_ptr(map).cast(), map.bytes());
+        }
+    }
+    bytes
+}
+
+// Seconds the render should take: building the scene once, and a probe render of it at a
+// resolution small enough for about `PROBE_SAMPLES` camera samples, scaled up to the pixels
+// of all the frames.
+fn estimate_seconds(options: &Options, scene: &Scene, build: f64) -> f64 {
+    let (width, height) = options.resolution();
+    let pixels = (width * height) as f64;
+    let mut probe = options.clone();
+    probe.resolution_scale *= (PROBE_SAMPLES / (pixels * options.spp as f64))
+        .sqrt()
+        .min(1.);
+    let camera = crate::default_camera(&probe);
+    let start = Instant::now();
+    let mut frame = render_pass(&camera, &probe, scene, 0);
+    finish(&mut frame, &camera, &probe, scene);
+    let seconds = start.elapsed().as_secs_f64();
+    let probe_pixels = (camera.width * camera.height) as f64;
+    build + seconds * pixels / probe_pixels * frames(options) as f64
+}
+
+// `--check`: a dry run that fails fast on a broken scene. The options are read and validated
+// as for a render, the scene is built, loading its meshes and heightmaps, and the references
+// between its parts are checked. A summary with rough estimates of the memory and time the
+// render takes goes to stdout as JSON, and nothing is written.
+pub fn run(options: &Options) -> Result<(), Error> {
+    let start = Instant::now();
+    let scene = Scene::new(options);
+    let build = start.elapsed().as_secs_f64();
+    check_references(options, &scene)?;
+    let (width, height) = options.resolution();
+    let bytes = memory(options, &scene);
+    let seconds = estimate_seconds(options, &scene, build);
+    info!(
+        "scene ok: {} objects, {}x{} at {} spp, {} frames, about {:.1} MB and {:.1} s",
+        scene.objects.len(),
+        width,
+        height,
+        options.spp,
+        frames(options),
+        bytes as f64 / 1e6,
+        seconds
+    );
+    println!(
+        "{{\"objects\": {}, \"width\": {}, \"height\": {}, \"spp\": {}, \"frames\": {}, \
+         \"memory_bytes\": {}, \"estimated_seconds\": {}}}",
+        scene.objects.len(),
+        width,
+        height,
+        options.spp,
+        frames(options),
+        bytes,
+        seconds
+    );
+    Ok(())
+}
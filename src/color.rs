@@ -0,0 +1,230 @@
+#![allow(dead_code)]
+use core::fmt;
+use core::iter::Sum;
+use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
+use core::str::FromStr;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[cfg(not(feature = "std"))]
+use crate::vec3d::Float;
+
+// Linear RGB radiance or reflectance. A type of its own rather than a `Vec3d`, so a color
+// can't be normalized, crossed with a direction or handed to the geometry by mistake.
+#[derive(Copy, Clone, PartialEq, Default)]
+#[repr(C)]
+pub struct Color {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::gray(0.);
+    pub const WHITE: Color = Color::gray(1.);
+
+    pub const fn new(r: f64, g: f64, b: f64) -> Color {
+        Color { r, g, b }
+    }
+
+    pub const fn gray(v: f64) -> Color {
+        Color::new(v, v, v)
+    }
+
+    pub fn map(self, f: impl Fn(f64) -> f64) -> Color {
+        Color::new(f(self.r), f(self.g), f(self.b))
+    }
+
+    pub fn zip(self, other: Color, f: impl Fn(f64, f64) -> f64) -> Color {
+        Color::new(f(self.r, other.r), f(self.g, other.g), f(self.b, other.b))
+    }
+
+    // relative luminance with the Rec. 709 primaries
+    pub fn luminance(self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    pub fn average(self) -> f64 {
+        (self.r + self.g + self.b) / 3.
+    }
+
+    pub fn max_channel(self) -> f64 {
+        self.r.max(self.g).max(self.b)
+    }
+
+    pub fn min_channel(self) -> f64 {
+        self.r.min(self.g).min(self.b)
+    }
+
+    pub fn min(self, other: Color) -> Color {
+        self.zip(other, f64::min)
+    }
+
+    pub fn max(self, other: Color) -> Color {
+        self.zip(other, f64::max)
+    }
+
+    pub fn clamp(self, min: f64, max: f64) -> Color {
+        self.map(|v| v.max(min).min(max))
+    }
+
+    // from `a` at 0 to `b` at 1, `t` clamped to that range
+    pub fn lerp(a: Color, b: Color, t: f64) -> Color {
+        a + (b - a) * t.clamp(0., 1.)
+    }
+
+    pub fn exp(self) -> Color {
+        self.map(f64::exp)
+    }
+
+    pub fn powf(self, exponent: f64) -> Color {
+        self.map(|v| v.powf(exponent))
+    }
+
+    pub fn is_finite(self) -> bool {
+        self.r.is_finite() && self.g.is_finite() && self.b.is_finite()
+    }
+
+    // the sRGB transfer curve, for values meant to be displayed as they are stored
+    pub fn to_srgb(self) -> Color {
+        self.map(|v| match v <= 0.003_130_8 {
+            true => 12.92 * v,
+            false => 1.055 * v.powf(1. / 2.4) - 0.055,
+        })
+    }
+
+    // linear values back from sRGB encoded ones
+    pub fn from_srgb(self) -> Color {
+        self.map(|v| match v <= 0.040_45 {
+            true => v / 12.92,
+            false => ((v + 0.055) / 1.055).powf(2.4),
+        })
+    }
+
+    pub fn to_array(self) -> [f64; 3] {
+        [self.r, self.g, self.b]
+    }
+
+    // channels in [0, 1] truncated to 8 bits, out of range values clamped
+    pub fn to_u8(self) -> [u8; 3] {
+        self.to_array().map(|v| (255. * v).clamp(0., 255.) as u8)
+    }
+
+    pub fn to_u16(self) -> [u16; 3] {
+        self.to_array()
+            .map(|v| (65535. * v).clamp(0., 65535.) as u16)
+    }
+}
+
+impl From<[f64; 3]> for Color {
+    fn from([r, g, b]: [f64; 3]) -> Color {
+        Color::new(r, g, b)
+    }
+}
+
+// channel-wise with another color or with a scalar, and the assigning forms
+macro_rules! op {
+    ($op:ident, $method:ident, $assign:ident, $assign_method:ident, $sym:tt) => {
+        impl $op for Color {
+            type Output = Color;
+
+            fn $method(self, other: Color) -> Color {
+                self.zip(other, |a, b| a $sym b)
+            }
+        }
+
+        impl $op<f64> for Color {
+            type Output = Color;
+
+            fn $method(self, other: f64) -> Color {
+                self.map(|a| a $sym other)
+            }
+        }
+
+        impl $assign for Color {
+            fn $assign_method(&mut self, other: Color) {
+                *self = *self $sym other;
+            }
+        }
+
+        impl $assign<f64> for Color {
+            fn $assign_method(&mut self, other: f64) {
+                *self = *self $sym other;
+            }
+        }
+    };
+}
+
+op!(Add, add, AddAssign, add_assign, +);
+op!(Sub, sub, SubAssign, sub_assign, -);
+// filtering, e.g. light by an albedo
+op!(Mul, mul, MulAssign, mul_assign, *);
+op!(Div, div, DivAssign, div_assign, /);
+
+impl Mul<Color> for f64 {
+    type Output = Color;
+
+    fn mul(self, other: Color) -> Color {
+        other * self
+    }
+}
+
+impl Sum for Color {
+    fn sum<I: Iterator<Item = Color>>(iter: I) -> Color {
+        iter.fold(Color::BLACK, |a, b| a + b)
+    }
+}
+
+// parses the comma-separated channels, as used in command line options
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Color, String> {
+        let c = s
+            .split(',')
+            .map(|c| c.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid color '{}'", s))?;
+        match c.as_slice() {
+            [r, g, b] => Ok(Color::new(*r, *g, *b)),
+            _ => Err(format!("expected 3 channels in '{}'", s)),
+        }
+    }
+}
+
+// the channels separated by commas, the form `from_str` reads back
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{}", self.r, self.g, self.b)
+    }
+}
+
+impl fmt::Debug for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rgb({}, {}, {})", self.r, self.g, self.b)
+    }
+}
+
+// as an array of the channels
+#[cfg(feature = "serde")]
+impl serde::Serialize for Color {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_array().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        <[f64; 3]>::deserialize(deserializer).map(Color::from)
+    }
+}
+
+// three f64 without padding, like `Vec3d`
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Zeroable for Color {}
+
+#[cfg(feature = "bytemuck")]
+unsafe impl bytemuck::Pod for Color {}
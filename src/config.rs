@@ -0,0 +1,39 @@
+use crate::error::Error;
+use crate::options::Options;
+use crate::scene;
+
+// defaults of the binary, read from the working directory unless `RABOOM_CONFIG` names
+// another file
+pub const FILE: &str = "raboom.toml";
+pub const ENV_PREFIX: &str = "RABOOM_";
+const ENV_FILE: &str = "RABOOM_CONFIG";
+
+// `RABOOM_OUTPUT_DIR` -> `output-dir`
+fn env_key(name: &str) -> String {
+    name[ENV_PREFIX.len()..]
+        .to_ascii_lowercase()
+        .replace('_', "-")
+}
+
+// Applies the config file, then the `RABOOM_*` environment variables on top of it. A
+// missing default file is no error, a missing file named by `RABOOM_CONFIG` is.
+pub fn apply(options: &mut Options) -> Result<(), Error> {
+    let path = std::env::var(ENV_FILE).ok();
+    let file = path.as_deref().unwrap_or(FILE);
+    if path.is_some() || std::path::Path::new(file).exists() {
+        for (key, value) in scene::load(file)? {
+            options.set(&key, &value).map_err(|err| err.in_file(file))?;
+        }
+    }
+    // sorted, so that the overrides don't depend on the order of the environment
+    let mut vars: Vec<(String, String)> = std::env::vars()
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX) && name != ENV_FILE)
+        .collect();
+    vars.sort();
+    for (name, value) in vars {
+        options
+            .set(&env_key(&name), &value)
+            .map_err(|err| err.in_file(&name))?;
+    }
+    Ok(())
+}
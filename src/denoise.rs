@@ -0,0 +1,69 @@
+use crate::color::Color;
+use crate::film::Film;
+use crate::render::Frame;
+use crate::vec3d::Vec3d;
+
+const ITERATIONS: usize = 5;
+const KERNEL: [f64; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+const SIGMA_COLOR: f64 = 0.6;
+const NORMAL_POWER: i32 = 64;
+const SIGMA_DEPTH: f64 = 0.05;
+
+fn normal_weight(n: Vec3d, nq: Vec3d) -> f64 {
+    let background = (n.length_squared() == 0., nq.length_squared() == 0.);
+    match background {
+        (true, true) => 1.,
+        (false, false) => n.dot(nq).max(0.).powi(NORMAL_POWER),
+        _ => 0.,
+    }
+}
+
+fn distance_squared(a: Color, b: Color) -> f64 {
+    let d = a - b;
+    d.r * d.r + d.g * d.g + d.b * d.b
+}
+
+fn depth_weight(d: f64, dq: f64, step: usize) -> f64 {
+    match (d.is_finite(), dq.is_finite()) {
+        (false, false) => 1.,
+        (true, true) => (-(d - dq).abs() / (SIGMA_DEPTH * step as f64)).exp(),
+        _ => 0.,
+    }
+}
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010): a 5x5 B3-spline kernel
+// dilated by 1, 2, 4, ... pixels, with the weights cut down across color, normal and
+// depth discontinuities so the silhouette and the fireball's crevices stay sharp.
+pub fn denoise(frame: &Frame) -> Film<Color> {
+    let mut color = frame.color.clone();
+    let mut sigma_color = SIGMA_COLOR;
+    for iteration in 0..ITERATIONS {
+        let step = 1 << iteration;
+        color = Film::from_fn(frame.width(), frame.height(), |x, y| {
+            let (c, n, d) = (color[(x, y)], frame.normal[(x, y)], frame.depth[(x, y)]);
+            let mut sum = Color::BLACK;
+            let mut weight_sum = 0.;
+            for (ky, wy) in KERNEL.iter().enumerate() {
+                for (kx, wx) in KERNEL.iter().enumerate() {
+                    let qx = x as isize + (kx as isize - 2) * step;
+                    let qy = y as isize + (ky as isize - 2) * step;
+                    let cq = color.get_clamped(qx, qy);
+                    let weight = wx
+                        * wy
+                        * (-distance_squared(cq, c) / (sigma_color * sigma_color)).exp()
+                        * normal_weight(n, frame.normal.get_clamped(qx, qy))
+                        * depth_weight(d, frame.depth.get_clamped(qx, qy), step as usize);
+                    sum += cq * weight;
+                    weight_sum += weight;
+                }
+            }
+            if weight_sum > 0. {
+                sum / weight_sum
+            } else {
+                c
+            }
+        });
+        sigma_color /= 2.;
+    }
+    color
+}
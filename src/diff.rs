@@ -0,0 +1,111 @@
+use crate::color::Color;
+use crate::error::Error;
+use crate::image::Image;
+
+// side of the SSIM windows and the distance between them, in pixels
+const SSIM_WINDOW: usize = 8;
+const SSIM_STRIDE: usize = 4;
+// stabilizers of the SSIM terms for values in [0, 1]
+const SSIM_C1: f64 = 0.01 * 0.01;
+const SSIM_C2: f64 = 0.03 * 0.03;
+
+// How far two images are apart.
+#[derive(Copy, Clone)]
+pub struct Difference {
+    // largest absolute difference of every color channel
+    pub max_channel: Color,
+    // mean structural similarity of the luminance, 1 for identical images
+    pub ssim: f64,
+}
+
+// What a regression test accepts as the same image.
+#[derive(Copy, Clone)]
+pub struct Tolerance {
+    pub channel: f64,
+    pub ssim: f64,
+}
+
+impl Default for Tolerance {
+    // about two 8-bit steps, and structure that is visually the same
+    fn default() -> Tolerance {
+        Tolerance {
+            channel: 2. / 255.,
+            ssim: 0.99,
+        }
+    }
+}
+
+fn luminance(c: Color) -> f64 {
+    c.luminance().clamp(0., 1.)
+}
+
+// Mean SSIM (Wang et al. 2004) over overlapping square windows of the luminance, with
+// uniform weights inside a window. Images smaller than a window are one window.
+pub fn ssim(a: &Image, b: &Image) -> f64 {
+    let (w, h) = (a.width(), a.height());
+    let (la, lb) = (a.color.map(luminance), b.color.map(luminance));
+    let size = |n: usize| SSIM_WINDOW.min(n);
+    let starts = |n: usize| (0..=n - size(n)).step_by(SSIM_STRIDE);
+    let (mut sum, mut windows) = (0., 0);
+    for y0 in starts(h) {
+        for x0 in starts(w) {
+            let (mut ma, mut mb, mut vaa, mut vbb, mut vab) = (0., 0., 0., 0., 0.);
+            for y in y0..y0 + size(h) {
+                for x in x0..x0 + size(w) {
+                    let (p, q) = (la[(x, y)], lb[(x, y)]);
+                    ma += p;
+                    mb += q;
+                    vaa += p * p;
+                    vbb += q * q;
+                    vab += p * q;
+                }
+            }
+            let n = (size(w) * size(h)) as f64;
+            let (ma, mb) = (ma / n, mb / n);
+            let (vaa, vbb, vab) = (vaa / n - ma * ma, vbb / n - mb * mb, vab / n - ma * mb);
+            sum += (2. * ma * mb + SSIM_C1) * (2. * vab + SSIM_C2)
+                / ((ma * ma + mb * mb + SSIM_C1) * (vaa + vbb + SSIM_C2));
+            windows += 1;
+        }
+    }
+    sum / windows.max(1) as f64
+}
+
+pub fn compare(a: &Image, b: &Image) -> Result<Difference, Error> {
+    if (a.width(), a.height()) != (b.width(), b.height()) {
+        return Err(Error::Invalid(format!(
+            "image sizes differ: {}x{} and {}x{}",
+            a.width(),
+            a.height(),
+            b.width(),
+            b.height()
+        )));
+    }
+    if a.width() == 0 || a.height() == 0 {
+        return Err(Error::Invalid("empty image".to_string()));
+    }
+    let mut max_channel = Color::BLACK;
+    for (p, q) in a.color.iter().zip(b.color.iter()) {
+        let d = *p - *q;
+        max_channel = max_channel.max(d.map(f64::abs));
+    }
+    Ok(Difference {
+        max_channel,
+        ssim: ssim(a, b),
+    })
+}
+
+// Ok if `image` matches `golden` within `tolerance`, otherwise a description of how
+// far off it is, for the assertion message of a test.
+pub fn assert_similar(image: &Image, golden: &Image, tolerance: Tolerance) -> Result<(), String> {
+    let difference = compare(image, golden).map_err(|err| err.to_string())?;
+    let worst = difference.max_channel.max_channel();
+    if worst > tolerance.channel || difference.ssim < tolerance.ssim {
+        return Err(format!(
+            "image differs from the golden one: channel difference {:.4} (allowed {:.4}), \
+             SSIM {:.4} (required {:.4})",
+            worst, tolerance.channel, difference.ssim, tolerance.ssim
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,452 @@
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::ops::Range;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::error::Error;
+use crate::log;
+use crate::matrix::Mat4;
+use crate::options::Options;
+use crate::render::{render_rows, Frame};
+use crate::scene::Scene;
+use crate::vec3d::{Vec2d, Vec3d};
+
+// where a bare `--worker` listens, only reachable from the machine itself, other machines
+// need an address on one of its interfaces
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:9200";
+// rows of the image in a chunk, the unit of work sent to a worker
+const CHUNK_ROWS: usize = 32;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// first bytes of every chunk request, to fail fast on anything that isn't a coordinator of
+// the same protocol
+const MAGIC: &[u8; 8] = b"RABOOM\x00\x04";
+// largest image side a worker traces chunks of, and longest string it reads
+const MAX_SIZE: usize = 1 << 16;
+const MAX_STR: u64 = 1 << 20;
+
+// The wire format is little-endian: integers as u64, floats as the bits of an f64 so the
+// image is the same as a local render, strings as their length and UTF-8 bytes.
+fn write_u64(out: &mut impl Write, x: u64) -> std::io::Result<()> {
+    out.write_all(&x.to_le_bytes())
+}
+
+fn write_f64(out: &mut impl Write, x: f64) -> std::io::Result<()> {
+    write_u64(out, x.to_bits())
+}
+
+fn write_vec(out: &mut impl Write, v: Vec3d) -> std::io::Result<()> {
+    write_f64(out, v.x)?;
+    write_f64(out, v.y)?;
+    write_f64(out, v.z)
+}
+
+fn write_color(out: &mut impl Write, c: Color) -> std::io::Result<()> {
+    write_f64(out, c.r)?;
+    write_f64(out, c.g)?;
+    write_f64(out, c.b)
+}
+
+fn write_str(out: &mut impl Write, s: &str) -> std::io::Result<()> {
+    write_u64(out, s.len() as u64)?;
+    out.write_all(s.as_bytes())
+}
+
+fn read_u64(input: &mut impl Read) -> std::io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(input: &mut impl Read) -> std::io::Result<f64> {
+    Ok(f64::from_bits(read_u64(input)?))
+}
+
+fn read_vec(input: &mut impl Read) -> std::io::Result<Vec3d> {
+    Ok(Vec3d::new(
+        read_f64(input)?,
+        read_f64(input)?,
+        read_f64(input)?,
+    ))
+}
+
+fn read_color(input: &mut impl Read) -> std::io::Result<Color> {
+    Ok(Color::new(
+        read_f64(input)?,
+        read_f64(input)?,
+        read_f64(input)?,
+    ))
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+fn read_str(input: &mut impl Read) -> std::io::Result<String> {
+    let length = read_u64(input)?;
+    if length > MAX_STR {
+        return Err(invalid("string too long"));
+    }
+    let mut bytes = Vec::new();
+    input.take(length).read_to_end(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid("string not UTF-8"))
+}
+
+// Settings a worker never applies: scene files, plugins, meshes and heightmaps would be read
+// on its machine, and the outputs are only written by the coordinator.
+fn local_only(key: &str) -> bool {
+    match key
+        .strip_prefix("object-")
+        .or_else(|| key.strip_prefix("instance-"))
+    {
+        Some(key) => reads_file(key),
+        None => matches!(key, "scene" | "plugin") || key.starts_with("output"),
+    }
+}
+
+// the object settings loading a file, without which a worker would trace another scene
+fn reads_file(key: &str) -> bool {
+    matches!(key, "mesh" | "heightmap")
+}
+
+// Opens a connection: the magic, to fail fast on anything that isn't a coordinator of the
+// same protocol, and the token the worker was started with.
+fn write_hello(out: &mut impl Write, token: &str) -> std::io::Result<()> {
+    out.write_all(MAGIC)?;
+    write_str(out, token)
+}
+
+// whether the coordinator knows the worker's `token`
+fn read_hello(input: &mut impl Read, token: &str) -> std::io::Result<bool> {
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a rustaboom coordinator"));
+    }
+    let sent = read_str(input)?;
+    // compared in full so the time taken doesn't tell how much of it was right
+    let differences = sent
+        .bytes()
+        .zip(token.bytes())
+        .fold(0, |diff, (a, b)| diff | (a ^ b));
+    Ok(sent.len() == token.len() && differences == 0)
+}
+
+// The rows to trace, with everything that defines them: the settings in the order they
+// were applied, the camera, which may be a stereo eye, and the progressive pass.
+struct Chunk {
+    settings: Vec<(String, String)>,
+    camera: Camera,
+    pass: usize,
+    rows: Range<usize>,
+}
+
+fn write_chunk(out: &mut impl Write, chunk: &Chunk) -> std::io::Result<()> {
+    write_u64(out, chunk.settings.len() as u64)?;
+    for (key, value) in &chunk.settings {
+        write_str(out, key)?;
+        write_str(out, value)?;
+    }
+    let camera = &chunk.camera;
+    // the bottom row of the rigid view transform is always the same
+    for row in &camera.view.rows[..3] {
+        write_vec(out, row.xyz())?;
+        write_f64(out, row.w)?;
+    }
+    write_f64(out, camera.fov)?;
+    write_u64(out, camera.width as u64)?;
+    write_u64(out, camera.height as u64)?;
+    write_f64(out, camera.shift)?;
+    write_f64(out, camera.distortion.x)?;
+    write_f64(out, camera.distortion.y)?;
+    write_u64(out, chunk.pass as u64)?;
+    write_u64(out, chunk.rows.start as u64)?;
+    write_u64(out, chunk.rows.end as u64)?;
+    out.flush()
+}
+
+fn read_chunk(input: &mut impl Read) -> std::io::Result<Chunk> {
+    let count = read_u64(input)?;
+    let mut settings = Vec::new();
+    for _ in 0..count {
+        settings.push((read_str(input)?, read_str(input)?));
+    }
+    let mut view = Mat4::identity();
+    for row in &mut view.rows[..3] {
+        *row = read_vec(input)?.extend(read_f64(input)?);
+    }
+    let fov = read_f64(input)?;
+    let (width, height) = (read_u64(input)?, read_u64(input)?);
+    // checked before anything the size of the image is allocated
+    let size = 1..=MAX_SIZE as u64;
+    if !size.contains(&width) || !size.contains(&height) {
+        return Err(invalid("image size out of range"));
+    }
+    let mut camera =
+        Camera::new(Vec3d::zero(), fov, width as usize, height as usize).with_view(view);
+    camera.shift = read_f64(input)?;
+    camera.distortion = Vec2d::new(read_f64(input)?, read_f64(input)?);
+    let pass = read_u64(input)? as usize;
+    let rows = read_u64(input)? as usize..read_u64(input)? as usize;
+    Ok(Chunk {
+        settings,
+        camera,
+        pass,
+        rows,
+    })
+}
+
+// the traced rows, or the reason the worker couldn't trace them
+fn write_frame(out: &mut impl Write, frame: Result<&Frame, &str>) -> std::io::Result<()> {
+    match frame {
+        Err(message) => {
+            out.write_all(&[1])?;
+            write_str(out, message)?;
+        }
+        Ok(frame) => {
+            out.write_all(&[0])?;
+            write_u64(out, frame.samples as u64)?;
+            for (x, y, color) in frame.color.pixels() {
+                write_color(out, color)?;
+                write_vec(out, frame.normal[(x, y)])?;
+                write_f64(out, frame.depth[(x, y)])?;
+                write_f64(out, frame.alpha[(x, y)])?;
+            }
+        }
+    }
+    out.flush()
+}
+
+fn read_frame(input: &mut impl Read, width: usize, height: usize) -> Result<Frame, String> {
+    let io = |err: std::io::Error| err.to_string();
+    let mut status = [0];
+    input.read_exact(&mut status).map_err(io)?;
+    if status[0] != 0 {
+        return Err(read_str(input).map_err(io)?);
+    }
+    let mut frame = Frame::new(width, height);
+    frame.samples = read_u64(input).map_err(io)? as usize;
+    for y in 0..height {
+        for x in 0..width {
+            frame.color[(x, y)] = read_color(input).map_err(io)?;
+            frame.normal[(x, y)] = read_vec(input).map_err(io)?;
+            frame.depth[(x, y)] = read_f64(input).map_err(io)?;
+            frame.alpha[(x, y)] = read_f64(input).map_err(io)?;
+        }
+    }
+    Ok(frame)
+}
+
+// Traces the chunks taken from `queue` on the worker at `address` until none are left,
+// putting the finished ones into `done`. The first failure gives the worker up and puts
+// its chunk back for the others.
+fn drive(
+    address: &str,
+    token: &str,
+    make_chunk: impl Fn(Range<usize>) -> Chunk,
+    queue: &Mutex<Vec<Range<usize>>>,
+    done: &Mutex<Vec<(Range<usize>, Frame)>>,
+) {
+    let connect = || -> Result<TcpStream, String> {
+        let target = address
+            .to_socket_addrs()
+            .map_err(|err| err.to_string())?
+            .next()
+            .ok_or("no address")?;
+        TcpStream::connect_timeout(&target, CONNECT_TIMEOUT).map_err(|err| err.to_string())
+    };
+    let stream = match connect() {
+        Ok(stream) => stream,
+        Err(err) => {
+            eprintln!("error: worker {}: {}", address, err);
+            return;
+        }
+    };
+    let (mut input, mut output) = (BufReader::new(&stream), BufWriter::new(&stream));
+    if let Err(err) = write_hello(&mut output, token) {
+        eprintln!("error: worker {}: {}", address, err);
+        return;
+    }
+    loop {
+        let rows = match queue.lock().unwrap().pop() {
+            Some(rows) => rows,
+            None => return,
+        };
+        let chunk = make_chunk(rows.clone());
+        let frame = write_chunk(&mut output, &chunk)
+            .map_err(|err| err.to_string())
+            .and_then(|()| read_frame(&mut input, chunk.camera.width, rows.len()));
+        match frame {
+            Ok(frame) => {
+                debug!(
+                    "worker {} traced rows {}..{}",
+                    address, rows.start, rows.end
+                );
+                done.lock().unwrap().push((rows, frame));
+            }
+            Err(err) => {
+                eprintln!("error: worker {}: {}", address, err);
+                queue.lock().unwrap().push(rows);
+                return;
+            }
+        }
+    }
+}
+
+// Coordinator side of `render_pass`: the rows are split into chunks that the `workers`
+// trace as they become free. Whatever the workers fail on is traced here, so a lost
+// worker slows the render down but doesn't change it.
+pub fn render_pass(camera: &Camera, options: &Options, scene: &Scene, pass: usize) -> Frame {
+    let _span = log::span("distributed render pass");
+    if options.settings.iter().any(|(key, _)| {
+        key.strip_prefix("object-")
+            .or_else(|| key.strip_prefix("instance-"))
+            .is_some_and(reads_file)
+    }) {
+        info!("meshes and heightmaps aren't sent to workers, tracing locally");
+        return render_rows(camera, options, scene, pass, 0..camera.height);
+    }
+    let settings: Vec<_> = options
+        .settings
+        .iter()
+        .filter(|(key, _)| !local_only(key))
+        .cloned()
+        .collect();
+    // validated with the workers
+    let token = options.worker_token.as_deref().unwrap_or_default();
+    let make_chunk = |rows| Chunk {
+        settings: settings.clone(),
+        camera: *camera,
+        pass,
+        rows,
+    };
+    // popped from the back, so the top of the image comes first
+    let queue: Vec<Range<usize>> = (0..camera.height)
+        .step_by(CHUNK_ROWS)
+        .rev()
+        .map(|top| top..(top + CHUNK_ROWS).min(camera.height))
+        .collect();
+    let (queue, done) = (Mutex::new(queue), Mutex::new(Vec::new()));
+    std::thread::scope(|s| {
+        for address in &options.workers {
+            let (make_chunk, queue, done) = (&make_chunk, &queue, &done);
+            s.spawn(move || drive(address, token, make_chunk, queue, done));
+        }
+    });
+    let mut done = done.into_inner().unwrap();
+    for rows in queue.into_inner().unwrap() {
+        info!("tracing rows {}..{} locally", rows.start, rows.end);
+        let frame = render_rows(camera, options, scene, pass, rows.clone());
+        done.push((rows, frame));
+    }
+
+    let mut frame = Frame::new(camera.width, camera.height);
+    for (rows, chunk) in done {
+        frame.color.blit(0, rows.start, &chunk.color);
+        frame.normal.blit(0, rows.start, &chunk.normal);
+        frame.depth.blit(0, rows.start, &chunk.depth);
+        frame.alpha.blit(0, rows.start, &chunk.alpha);
+        frame.samples += chunk.samples;
+    }
+    frame
+}
+
+// the options of a chunk's settings, traced with the worker's own threads and without the
+// settings a worker never applies
+fn chunk_options(settings: &[(String, String)], worker: &Options) -> Result<Options, Error> {
+    let mut options = Options::default();
+    for (key, value) in settings {
+        if local_only(key) {
+            debug!("dropped '{}' from the chunk", key);
+            continue;
+        }
+        options.set(key, value)?;
+    }
+    // the chunks are traced here, whatever the coordinator's own workers
+    options.worker = None;
+    options.workers.clear();
+    options.validate()?;
+    options.threads = worker.threads;
+    Ok(options)
+}
+
+// a render of a coordinator, prepared for its chunks
+struct Render {
+    settings: Vec<(String, String)>,
+    options: Options,
+    scene: Scene,
+}
+
+// traces the chunks a coordinator sends over one connection, until it hangs up
+fn work(stream: TcpStream, worker: &Options) -> std::io::Result<()> {
+    let (mut input, mut output) = (BufReader::new(&stream), BufWriter::new(&stream));
+    // validated to be there before the worker starts
+    let token = worker.worker_token.as_deref().unwrap_or_default();
+    if !read_hello(&mut input, token)? {
+        // the coordinator learns why before it is hung up on
+        write_frame(&mut output, Err("wrong worker token"))?;
+        return Err(invalid("wrong worker token"));
+    }
+    // the last render, a coordinator sends many chunks of the same one
+    let mut render: Option<Render> = None;
+    loop {
+        let chunk = match read_chunk(&mut input) {
+            Ok(chunk) => chunk,
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        if render.as_ref().map(|r| &r.settings) != Some(&chunk.settings) {
+            render = match chunk_options(&chunk.settings, worker) {
+                Ok(options) => Some(Render {
+                    settings: chunk.settings.clone(),
+                    scene: Scene::new(&options),
+                    options,
+                }),
+                Err(err) => {
+                    write_frame(&mut output, Err(&err.to_string()))?;
+                    continue;
+                }
+            };
+        }
+        let Render { options, scene, .. } = render.as_ref().unwrap();
+        let rows = chunk.rows;
+        if rows.start >= rows.end || rows.end > chunk.camera.height {
+            let message = format!("rows {}..{} outside the image", rows.start, rows.end);
+            write_frame(&mut output, Err(&message))?;
+            continue;
+        }
+        let frame = render_rows(&chunk.camera, options, scene, chunk.pass, rows);
+        write_frame(&mut output, Ok(&frame))?;
+    }
+}
+
+// Worker of distributed renders: traces the chunks of every coordinator that connects,
+// until the process is stopped. Only coordinators sending the worker's token are served, and
+// the settings they send never make the worker read or write files.
+pub fn serve(options: &Options) -> Result<(), Error> {
+    let address = options.worker.as_deref().unwrap_or(DEFAULT_ADDRESS);
+    let listener = TcpListener::bind(address).map_err(|err| Error::io(address, err))?;
+    info!("worker listening on {}", address);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map_or("?".to_string(), |peer| peer.to_string());
+        info!("coordinator {} connected", peer);
+        let worker = options.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = work(stream, &worker) {
+                eprintln!("error: coordinator {}: {}", peer, err);
+            }
+        });
+    }
+    Ok(())
+}
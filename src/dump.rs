@@ -0,0 +1,227 @@
+use std::str::FromStr;
+
+use crate::camera::Camera;
+use crate::error::Error;
+use crate::metadata::quote;
+use crate::options::Options;
+use crate::scene::{Object, Scene, Shading, Shape};
+use crate::vec3d::Vec3d;
+
+// How `--dump-scene` prints the scene.
+#[derive(Copy, Clone, PartialEq)]
+pub enum DumpFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DumpFormat, String> {
+        match s {
+            "text" => Ok(DumpFormat::Text),
+            "json" => Ok(DumpFormat::Json),
+            _ => Err(format!("unknown dump format '{}'", s)),
+        }
+    }
+}
+
+enum Value {
+    Number(f64),
+    Name(String),
+    Vector(Vec<f64>),
+    Flag(bool),
+}
+
+impl Value {
+    fn vector(v: Vec3d) -> Value {
+        Value::Vector(vec![v.x, v.y, v.z])
+    }
+
+    fn text(&self) -> String {
+        match self {
+            Value::Number(x) => x.to_string(),
+            Value::Name(name) => name.clone(),
+            Value::Vector(v) => v.iter().map(f64::to_string).collect::<Vec<_>>().join(", "),
+            Value::Flag(flag) => flag.to_string(),
+        }
+    }
+
+    fn json(&self) -> String {
+        match self {
+            Value::Name(name) => quote(name),
+            Value::Vector(_) => format!("[{}]", self.text()),
+            _ => self.text(),
+        }
+    }
+}
+
+type Fields = Vec<(&'static str, Value)>;
+
+fn camera_fields(camera: &Camera, options: &Options) -> Fields {
+    let mut fields = vec![
+        ("position", Value::vector(camera.position)),
+        (
+            "direction",
+            Value::vector(camera.to_world.transform_vector(Vec3d::new(0., 0., -1.))),
+        ),
+        ("fov", Value::Number(camera.fov.to_degrees())),
+        ("width", Value::Number(camera.width as f64)),
+        ("height", Value::Number(camera.height as f64)),
+    ];
+    if options.lens.radius > 0. {
+        fields.push(("lens-radius", Value::Number(options.lens.radius)));
+        fields.push(("lens-focus", Value::Number(options.lens.focus)));
+    }
+    fields
+}
+
+fn light_fields(options: &Options) -> Fields {
+    let light = &options.light;
+    let objects = match &light.objects {
+        None => Value::Name("all".to_string()),
+        Some(objects) => Value::Vector(objects.iter().map(|&n| (n + 1) as f64).collect()),
+    };
+    vec![
+        ("shape", Value::Name(light.shape.name().to_string())),
+        ("position", Value::vector(light.position)),
+        (
+            "color",
+            Value::Vector(vec![light.color.r, light.color.g, light.color.b]),
+        ),
+        ("intensity", Value::Number(light.intensity)),
+        ("shadows", Value::Flag(light.shadows)),
+        ("objects", objects),
+    ]
+}
+
+// Where the object is and what it looks like once the scene is built, with where its
+// center lands in the image, to tell objects out of view from ones that aren't there.
+fn object_fields(object: &Object, index: usize, camera: &Camera, options: &Options) -> Fields {
+    let name = |name: &str| Value::Name(name.to_string());
+    let mut fields = vec![("shape", name(object.shape.name()))];
+    match object.shape {
+        Shape::Plane => fields.push(("height", Value::Number(object.height))),
+        Shape::Terrain => {
+            fields.push(("height", Value::Number(object.height)));
+            fields.push(("relief", Value::Number(object.noise_amplitude)));
+            fields.push(("heightmap", Value::Flag(object.heightmap.is_some())));
+        }
+        _ => {
+            fields.push(("center", Value::vector(object.center)));
+            fields.push(("radius", Value::Number(object.radius)));
+            let rotation = object.rotation;
+            fields.push((
+                "rotation",
+                Value::Vector(vec![rotation.x, rotation.y, rotation.z, rotation.w]),
+            ));
+            fields.push(("noise-amplitude", Value::Number(object.noise_amplitude)));
+            fields.push((
+                "seed",
+                Value::Number(f64::from(object.seed.unwrap_or(options.seed))),
+            ));
+            if let Some(bounds) = object.bounds() {
+                fields.push(("bounds", Value::Number(bounds.radius)));
+            }
+            let screen = match camera.project(object.center - camera.position) {
+                Some(p) => Value::Vector(vec![p.x, p.y]),
+                None => name("behind the camera"),
+            };
+            fields.push(("screen", screen));
+        }
+    }
+    match object.shape {
+        Shape::Fireball => {
+            if let Some(ground) = object.ground {
+                fields.push(("ground", Value::Number(ground)));
+            }
+        }
+        Shape::Mandelbulb | Shape::Menger | Shape::Julia => {
+            fields.push(("iterations", Value::Number(object.iterations as f64)));
+        }
+        Shape::Mesh => {
+            let (vertices, triangles) = object
+                .mesh
+                .as_ref()
+                .map_or((0, 0), |mesh| (mesh.vertices.len(), mesh.triangles.len()));
+            fields.push(("vertices", Value::Number(vertices as f64)));
+            fields.push(("triangles", Value::Number(triangles as f64)));
+        }
+        Shape::Expression => fields.push(("sdf", Value::Flag(object.expression.is_some()))),
+        Shape::Plugin => fields.push(("plugin", Value::Flag(object.plugin.is_some()))),
+        _ => {}
+    }
+    let material = &object.material;
+    fields.push(("shading", name(material.shading.name())));
+    match material.shading {
+        Shading::Diffuse => {
+            let albedo = material.albedo;
+            fields.push(("albedo", Value::Vector(vec![albedo.r, albedo.g, albedo.b])));
+        }
+        _ => fields.push(("palette", name(material.palette.name()))),
+    }
+    if material.shading == Shading::Fire {
+        fields.push(("heat-gain", Value::Number(material.heat.gain)));
+        if let Some(smoke) = material.smoke.palette {
+            fields.push(("smoke", name(smoke.name())));
+            fields.push(("smoke-level", Value::Number(material.smoke.level)));
+        }
+    }
+    if let Some(texture) = material.texture {
+        fields.push(("texture", name(texture.name())));
+    }
+    fields.push(("lit", Value::Flag(options.light.lights(index))));
+    fields
+}
+
+fn text(title: &str, fields: &Fields) -> String {
+    let mut text = format!("{}\n", title);
+    for (key, value) in fields {
+        text += &format!("  {}: {}\n", key, value.text());
+    }
+    text
+}
+
+fn json(fields: &Fields, indent: &str) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("{}  {}: {}", indent, quote(key), value.json()))
+        .collect();
+    format!("{{\n{}\n{}}}", fields.join(",\n"), indent)
+}
+
+// `--dump-scene`: prints the scene as it is rendered, once the scene files, their includes
+// and the command line are applied and the defaults filled in, the default fireball and
+// the floor included. Objects are numbered from 1, like in `light-objects` and errors.
+pub fn run(options: &Options, format: DumpFormat) -> Result<(), Error> {
+    let scene = Scene::new(options);
+    let camera = crate::default_camera(options);
+    let objects: Vec<Fields> = scene
+        .objects
+        .iter()
+        .enumerate()
+        .map(|(n, object)| object_fields(object, n, &camera, options))
+        .collect();
+    match format {
+        DumpFormat::Text => {
+            print!("{}", text("camera", &camera_fields(&camera, options)));
+            print!("{}", text("light", &light_fields(options)));
+            for (n, fields) in objects.iter().enumerate() {
+                print!("{}", text(&format!("object {}", n + 1), fields));
+            }
+        }
+        DumpFormat::Json => {
+            let objects: Vec<String> = objects
+                .iter()
+                .map(|fields| format!("    {}", json(fields, "    ")))
+                .collect();
+            println!(
+                "{{\n  \"camera\": {},\n  \"light\": {},\n  \"objects\": [\n{}\n  ]\n}}",
+                json(&camera_fields(&camera, options), "  "),
+                json(&light_fields(options), "  "),
+                objects.join(",\n")
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::noise::curl_noise;
+use crate::options::Options;
+use crate::render::Frame;
+use crate::rng::Rng;
+use crate::sampling::sample_uniform_sphere;
+use crate::scene::{Object, Scene, Shape};
+use crate::vec3d::Vec3d;
+
+// seconds over which the burst an ember is thrown out with dies down
+const EMBER_DRAG: f64 = 0.4;
+// scale of the curl noise the embers drift in, and the draft lifting them, in units of
+// their speed
+const EMBER_NOISE_SCALE: f64 = 1.5;
+const EMBER_LIFT: f64 = 0.4;
+// Euler steps from an ember's birth to its age
+const EMBER_STEPS: usize = 32;
+// sprites reach this many of their radii
+const SPRITE_REACH: f64 = 2.5;
+
+struct Ember {
+    position: Vec3d,
+    // fraction of its life gone
+    age: f64,
+    // index of the fireball that threw it
+    fireball: usize,
+}
+
+// Ember `i` at the scene time of the options. Every ember lives through a cycle of
+// `ember_lifetime` seconds, started at its own offset: it is thrown out of a random point of
+// a fireball's surface, carried by the curl noise and a rising draft, and born again
+// elsewhere when the cycle ends. Its state at any time follows from the seed, so frames of
+// an animation can be rendered in any order.
+fn ember(options: &Options, fireballs: &[&Object], i: usize) -> Ember {
+    let mut rng = Rng::new(u64::from(options.seed), i as u64);
+    let cycles = options.time / options.ember_lifetime + rng.next_f64();
+    let age = cycles.fract();
+    let mut rng = Rng::new(
+        u64::from(options.seed) ^ cycles.floor() as i64 as u64,
+        i as u64,
+    );
+    let fireball = rng.below(fireballs.len() as u32) as usize;
+    let object = fireballs[fireball];
+    let out = sample_uniform_sphere(rng.next_f64(), rng.next_f64());
+    let mut position = object.center + out * object.radius;
+    let seconds = age * options.ember_lifetime;
+    let dt = seconds / EMBER_STEPS as f64;
+    for step in 0..EMBER_STEPS {
+        let burst = out * (-(step as f64 * dt) / EMBER_DRAG).exp();
+        let drift = curl_noise(position * EMBER_NOISE_SCALE) + Vec3d::new(0., EMBER_LIFT, 0.);
+        position += (burst + drift) * (options.ember_speed * dt);
+    }
+    Ember {
+        position,
+        age,
+        fireball,
+    }
+}
+
+// Draws the embers of the fireballs of the scene over the traced frame, as glowing round
+// sprites of their projected size hidden behind the scene. They cool down along the palette
+// of their fireball from its hottest color to black over their life.
+pub fn draw(frame: &mut Frame, camera: &Camera, options: &Options, scene: &Scene) {
+    let fireballs: Vec<&Object> = scene
+        .objects
+        .iter()
+        .filter(|object| object.shape == Shape::Fireball)
+        .collect();
+    if fireballs.is_empty() {
+        return;
+    }
+    let (width, height) = (frame.color.width(), frame.color.height());
+    for i in 0..options.embers {
+        let ember = ember(options, &fireballs, i);
+        let offset = ember.position - camera.position;
+        let center = match camera.project(offset) {
+            Some(center) => center,
+            None => continue,
+        };
+        let distance = offset.length();
+        let radius = (options.ember_size / (distance * camera.pixel_spread())).max(0.5);
+        let glow = fireballs[ember.fireball]
+            .material
+            .palette
+            .color(1. - ember.age)
+            * options.ember_intensity;
+        let reach = radius * SPRITE_REACH;
+        let x0 = (center.x - reach).floor().max(0.) as usize;
+        let y0 = (center.y - reach).floor().max(0.) as usize;
+        let x1 = ((center.x + reach).ceil().max(0.) as usize).min(width);
+        let y1 = ((center.y + reach).ceil().max(0.) as usize).min(height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                if frame.depth[(x, y)] < distance {
+                    continue;
+                }
+                let (dx, dy) = (x as f64 + 0.5 - center.x, y as f64 + 0.5 - center.y);
+                let weight = (-(dx * dx + dy * dy) / (2. * radius * radius)).exp();
+                splat(frame, options.alpha, x, y, glow, weight);
+            }
+        }
+    }
+}
+
+// adds the light of a sprite to a pixel, whose color is straight with `alpha`
+fn splat(frame: &mut Frame, alpha: bool, x: usize, y: usize, glow: Color, weight: f64) {
+    if !alpha {
+        frame.color[(x, y)] += glow * weight;
+        return;
+    }
+    let coverage = frame.alpha[(x, y)];
+    let covered = coverage + weight * (1. - coverage);
+    if covered > 0. {
+        frame.color[(x, y)] = (frame.color[(x, y)] * coverage + glow * weight) / covered;
+    }
+    frame.alpha[(x, y)] = covered;
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+
+// Everything that can go wrong between reading the settings and writing the image.
+#[derive(Debug)]
+pub enum Error {
+    // a file that couldn't be read or written
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    // malformed settings, scene files, expressions, meshes or images
+    Parse(String),
+    // well-formed settings with values the renderer can't work with
+    Invalid(String),
+}
+
+impl Error {
+    pub fn io(path: &str, source: std::io::Error) -> Error {
+        Error::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    // the message prefixed with the file it is about
+    pub fn in_file(self, path: &str) -> Error {
+        match self {
+            Error::Parse(message) => Error::Parse(format!("{}: {}", path, message)),
+            Error::Invalid(message) => Error::Invalid(format!("{}: {}", path, message)),
+            io => io,
+        }
+    }
+
+    // exit status of the binary, following sysexits(3)
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io { .. } => 74,
+            Error::Parse(_) => 65,
+            Error::Invalid(_) => 64,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "can't access '{}': {}", path, source),
+            Error::Parse(message) | Error::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// the `FromStr` implementations and parsers report plain messages
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::Parse(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Error {
+        Error::Parse(message.to_string())
+    }
+}
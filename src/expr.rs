@@ -0,0 +1,389 @@
+use std::f64::consts::PI;
+
+use crate::error::Error;
+use crate::noise::{fractal_brownian_motion, noise};
+use crate::shaping::{bias, gain, remap, smax, smin, smoothstep};
+use crate::vec3d::{Vec2d, Vec3d};
+
+// Small expression language for distance functions written in scene files, e.g.
+// `length(x, y, z) - 1 + 0.3 * fbm(x * 3, y * 3, z * 3 + t)`. Expressions are scalar,
+// with the variables `x`, `y`, `z` (object space position), `t` (time), `mx`, `my` (mouse
+// position of the live preview, 0 to 1 from the bottom-left corner) and `pi`, the
+// operators `+ - * / ^`, and the functions listed in `Func::parse`.
+
+#[derive(Copy, Clone, Debug)]
+enum Var {
+    X,
+    Y,
+    Z,
+    T,
+    MouseX,
+    MouseY,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+// the most arguments a function takes, those of `remap`
+const MAX_ARGS: usize = 5;
+
+#[derive(Copy, Clone, Debug)]
+enum Func {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
+    Abs,
+    Sqrt,
+    Exp,
+    Ln,
+    Floor,
+    Fract,
+    Mod,
+    Min,
+    Max,
+    Clamp,
+    Mix,
+    Smoothstep,
+    // polynomial smooth minimum and maximum of two distances with blend radius k
+    Smin,
+    Smax,
+    Remap,
+    Bias,
+    Gain,
+    Length,
+    Noise,
+    Fbm,
+}
+
+impl Func {
+    fn parse(name: &str) -> Option<Func> {
+        Some(match name {
+            "sin" => Func::Sin,
+            "cos" => Func::Cos,
+            "tan" => Func::Tan,
+            "asin" => Func::Asin,
+            "acos" => Func::Acos,
+            "atan" => Func::Atan,
+            "atan2" => Func::Atan2,
+            "abs" => Func::Abs,
+            "sqrt" => Func::Sqrt,
+            "exp" => Func::Exp,
+            "ln" => Func::Ln,
+            "floor" => Func::Floor,
+            "fract" => Func::Fract,
+            "mod" => Func::Mod,
+            "min" => Func::Min,
+            "max" => Func::Max,
+            "clamp" => Func::Clamp,
+            "mix" => Func::Mix,
+            "smoothstep" => Func::Smoothstep,
+            "smin" => Func::Smin,
+            "smax" => Func::Smax,
+            "remap" => Func::Remap,
+            "bias" => Func::Bias,
+            "gain" => Func::Gain,
+            "length" => Func::Length,
+            "noise" => Func::Noise,
+            "fbm" => Func::Fbm,
+            _ => return None,
+        })
+    }
+
+    fn accepts(self, args: usize) -> bool {
+        match self {
+            Func::Atan2 | Func::Mod | Func::Min | Func::Max | Func::Bias | Func::Gain => args == 2,
+            Func::Clamp | Func::Mix | Func::Smoothstep | Func::Smin | Func::Smax => args == 3,
+            Func::Remap => args == 5,
+            Func::Noise | Func::Fbm => args == 3,
+            Func::Length => args == 2 || args == 3,
+            _ => args == 1,
+        }
+    }
+
+    fn call(self, a: &[f64]) -> f64 {
+        match self {
+            Func::Sin => a[0].sin(),
+            Func::Cos => a[0].cos(),
+            Func::Tan => a[0].tan(),
+            Func::Asin => a[0].asin(),
+            Func::Acos => a[0].acos(),
+            Func::Atan => a[0].atan(),
+            Func::Atan2 => a[0].atan2(a[1]),
+            Func::Abs => a[0].abs(),
+            Func::Sqrt => a[0].sqrt(),
+            Func::Exp => a[0].exp(),
+            Func::Ln => a[0].ln(),
+            Func::Floor => a[0].floor(),
+            Func::Fract => a[0] - a[0].floor(),
+            Func::Mod => a[0].rem_euclid(a[1]),
+            Func::Min => a[0].min(a[1]),
+            Func::Max => a[0].max(a[1]),
+            Func::Clamp => a[0].clamp(a[1], a[2]),
+            Func::Mix => a[0] + (a[1] - a[0]) * a[2],
+            Func::Smoothstep => smoothstep(a[0], a[1], a[2]),
+            Func::Smin => smin(a[0], a[1], a[2]),
+            Func::Smax => smax(a[0], a[1], a[2]),
+            Func::Remap => remap(a[0], a[1], a[2], a[3], a[4]),
+            Func::Bias => bias(a[0], a[1]),
+            Func::Gain => gain(a[0], a[1]),
+            Func::Length => a.iter().map(|v| v * v).sum::<f64>().sqrt(),
+            Func::Noise => noise(Vec3d::new(a[0], a[1], a[2])),
+            Func::Fbm => fractal_brownian_motion(Vec3d::new(a[0], a[1], a[2])),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Number(f64),
+    Var(Var),
+    Neg(Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+    Call(Func, Vec<Node>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Expr {
+    root: Node,
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            while i < chars.len()
+                && (chars[i].is_ascii_digit()
+                    || chars[i] == '.'
+                    || chars[i] == 'e'
+                    || ((chars[i] == '-' || chars[i] == '+') && chars[i - 1] == 'e'))
+            {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            let value = number
+                .parse()
+                .map_err(|_| format!("invalid number '{}' at {}", number, start))?;
+            tokens.push((start, Token::Number(value)));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((start, Token::Ident(chars[start..i].iter().collect())));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push((start, Token::Symbol(c)));
+            i += 1;
+        } else {
+            return Err(format!("unexpected '{}' at {}", c, start));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.len, |(o, _)| *o)
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at {}", symbol, self.offset()))
+        }
+    }
+
+    // sum := product (('+' | '-') product)*
+    fn sum(&mut self) -> Result<Node, String> {
+        let mut lhs = self.product()?;
+        loop {
+            let op = if self.eat('+') {
+                Op::Add
+            } else if self.eat('-') {
+                Op::Sub
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(self.product()?));
+        }
+    }
+
+    // product := unary (('*' | '/') unary)*
+    fn product(&mut self) -> Result<Node, String> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Op::Mul
+            } else if self.eat('/') {
+                Op::Div
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    // unary := '-' unary | power
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.eat('-') {
+            Ok(Node::Neg(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    // power := atom ('^' unary)?, right associative and binding tighter than negation
+    fn power(&mut self) -> Result<Node, String> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            Ok(Node::Binary(
+                Op::Pow,
+                Box::new(base),
+                Box::new(self.unary()?),
+            ))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let offset = self.offset();
+        let token = self.peek().cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(Node::Number(value)),
+            Some(Token::Symbol('(')) => {
+                let inner = self.sum()?;
+                self.expect(')')?;
+                Ok(inner)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "x" => Ok(Node::Var(Var::X)),
+                "y" => Ok(Node::Var(Var::Y)),
+                "z" => Ok(Node::Var(Var::Z)),
+                "t" => Ok(Node::Var(Var::T)),
+                "mx" => Ok(Node::Var(Var::MouseX)),
+                "my" => Ok(Node::Var(Var::MouseY)),
+                "pi" => Ok(Node::Number(PI)),
+                _ => {
+                    let func = Func::parse(&name)
+                        .ok_or(format!("unknown name '{}' at {}", name, offset))?;
+                    self.expect('(')?;
+                    let mut args = Vec::new();
+                    if !self.eat(')') {
+                        loop {
+                            args.push(self.sum()?);
+                            if self.eat(')') {
+                                break;
+                            }
+                            self.expect(',')?;
+                        }
+                    }
+                    if !func.accepts(args.len()) {
+                        return Err(format!(
+                            "wrong number of arguments to '{}' at {}",
+                            name, offset
+                        ));
+                    }
+                    Ok(Node::Call(func, args))
+                }
+            },
+            _ => Err(format!("expected a value at {}", offset)),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, Error> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            pos: 0,
+            len: text.len(),
+        };
+        let root = parser.sum()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(Error::Parse(format!(
+                "unexpected input at {}",
+                parser.offset()
+            )));
+        }
+        Ok(Expr { root })
+    }
+
+    pub fn eval(&self, p: Vec3d, t: f64, mouse: Vec2d) -> f64 {
+        self.root.eval(p, t, mouse)
+    }
+}
+
+impl Node {
+    fn eval(&self, p: Vec3d, t: f64, mouse: Vec2d) -> f64 {
+        match self {
+            Node::Number(value) => *value,
+            Node::Var(Var::X) => p.x,
+            Node::Var(Var::Y) => p.y,
+            Node::Var(Var::Z) => p.z,
+            Node::Var(Var::T) => t,
+            Node::Var(Var::MouseX) => mouse.x,
+            Node::Var(Var::MouseY) => mouse.y,
+            Node::Neg(e) => -e.eval(p, t, mouse),
+            Node::Binary(op, a, b) => {
+                let (a, b) = (a.eval(p, t, mouse), b.eval(p, t, mouse));
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                    Op::Pow => a.powf(b),
+                }
+            }
+            Node::Call(func, args) => {
+                let mut values = [0.; MAX_ARGS];
+                for (v, arg) in values.iter_mut().zip(args.iter()) {
+                    *v = arg.eval(p, t, mouse);
+                }
+                func.call(&values[..args.len()])
+            }
+        }
+    }
+}
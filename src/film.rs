@@ -0,0 +1,392 @@
+#![allow(dead_code)]
+use std::ops::{Add, Index, IndexMut, Mul};
+use std::str::FromStr;
+
+use crate::color::Color;
+
+// A `width` x `height` grid of pixels stored row by row from the top, the one place that
+// knows where pixel (x, y) lives.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Film<T> {
+    width: usize,
+    height: usize,
+    pixels: Vec<T>,
+}
+
+impl<T: Copy> Film<T> {
+    pub fn new(width: usize, height: usize, fill: T) -> Film<T> {
+        Film {
+            width,
+            height,
+            pixels: vec![fill; width * height],
+        }
+    }
+
+    // the pixels given row by row, as many as the film has
+    pub fn from_vec(width: usize, height: usize, pixels: Vec<T>) -> Film<T> {
+        assert_eq!(pixels.len(), width * height, "pixel count of a film");
+        Film {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn from_fn(width: usize, height: usize, mut f: impl FnMut(usize, usize) -> T) -> Film<T> {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| f(x, y))
+            .collect();
+        Film::from_vec(width, height, pixels)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    // position of pixel (x, y) in the rows laid end to end
+    pub fn index(&self, x: usize, y: usize) -> usize {
+        debug_assert!(x < self.width && y < self.height);
+        x + y * self.width
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> T {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: T) {
+        let i = self.index(x, y);
+        self.pixels[i] = value;
+    }
+
+    // the border pixels extend outwards
+    pub fn get_clamped(&self, x: isize, y: isize) -> T {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.get(x, y)
+    }
+
+    pub fn row(&self, y: usize) -> &[T] {
+        &self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [T] {
+        &mut self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[T]> {
+        self.pixels.chunks(self.width.max(1))
+    }
+
+    // Bands of `rows` rows from the top, the last one shorter if they don't divide the
+    // height. They don't overlap, so they can be filled from different threads.
+    pub fn bands_mut(&mut self, rows: usize) -> impl Iterator<Item = Band<'_, T>> {
+        let width = self.width;
+        self.pixels
+            .chunks_mut((rows * width).max(1))
+            .enumerate()
+            .map(move |(n, pixels)| Band {
+                top: n * rows,
+                width,
+                pixels,
+            })
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.pixels.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.pixels.iter_mut()
+    }
+
+    // every pixel with its coordinates, row by row
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize, T)> + '_ {
+        let width = self.width.max(1);
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(move |(i, p)| (i % width, i / width, *p))
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.pixels
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.pixels
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.pixels
+    }
+
+    pub fn map<U: Copy>(&self, f: impl Fn(T) -> U) -> Film<U> {
+        Film::from_vec(
+            self.width,
+            self.height,
+            self.pixels.iter().map(|p| f(*p)).collect(),
+        )
+    }
+
+    // pixel by pixel with a film of the same size
+    pub fn zip<U: Copy, V: Copy>(&self, other: &Film<U>, f: impl Fn(T, U) -> V) -> Film<V> {
+        assert_eq!(
+            (self.width, self.height),
+            (other.width, other.height),
+            "size of zipped films"
+        );
+        let pixels = self.iter().zip(other.iter()).map(|(a, b)| f(*a, *b));
+        Film::from_vec(self.width, self.height, pixels.collect())
+    }
+
+    // copies `src` with its top left corner at (x, y), clipped to the film
+    pub fn blit(&mut self, x: usize, y: usize, src: &Film<T>) {
+        let w = src.width.min(self.width.saturating_sub(x));
+        for (sy, row) in src.rows().take(self.height.saturating_sub(y)).enumerate() {
+            self.row_mut(y + sy)[x..x + w].copy_from_slice(&row[..w]);
+        }
+    }
+
+    // the `width` x `height` part with its top left corner at (x, y)
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Film<T> {
+        Film::from_fn(width, height, |i, j| self.get(x + i, y + j))
+    }
+}
+
+impl<T: Copy + Add<Output = T> + Mul<f64, Output = T>> Film<T> {
+    // bilinear lookup at (u, v) in [0, 1]^2, the border pixels extend outwards
+    pub fn sample_bilinear(&self, u: f64, v: f64) -> T {
+        let x = (u * self.width as f64 - 0.5).clamp(0., (self.width - 1) as f64);
+        let y = (v * self.height as f64 - 0.5).clamp(0., (self.height - 1) as f64);
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+        let top = self.get(x0, y0) * (1. - fx) + self.get(x1, y0) * fx;
+        let bottom = self.get(x0, y1) * (1. - fx) + self.get(x1, y1) * fx;
+        top * (1. - fy) + bottom * fy
+    }
+}
+
+impl<T: Copy> Index<(usize, usize)> for Film<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        &self.pixels[Film::index(self, x, y)]
+    }
+}
+
+impl<T: Copy> IndexMut<(usize, usize)> for Film<T> {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+        let i = Film::index(self, x, y);
+        &mut self.pixels[i]
+    }
+}
+
+// Rows `top..top + height()` of a film, borrowed to be written. Coordinates are relative
+// to the band.
+pub struct Band<'a, T> {
+    top: usize,
+    width: usize,
+    pixels: &'a mut [T],
+}
+
+impl<T: Copy> Band<'_, T> {
+    // the first row of the band in the film
+    pub fn top(&self) -> usize {
+        self.top
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.pixels.len() / self.width.max(1)
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> T {
+        self.pixels[x + y * self.width]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: T) {
+        self.pixels[x + y * self.width] = value;
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [T] {
+        &mut self.pixels[y * self.width..(y + 1) * self.width]
+    }
+}
+
+// Shape of the reconstruction filter spreading a sample over the pixels around it.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Filter {
+    // every pixel within the radius alike, with the default radius the plain average of the
+    // samples in the pixel
+    Box,
+    // linear falloff
+    Tent,
+    // cut off at 3 sigma, softer than the tent
+    Gaussian,
+    // Mitchell-Netravali cubic with B = C = 1/3, sharper with a little ringing
+    Mitchell,
+    // windowed sinc with as many lobes as the radius, the sharpest with the most ringing
+    Lanczos,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Filter, String> {
+        match s {
+            "box" => Ok(Filter::Box),
+            "tent" => Ok(Filter::Tent),
+            "gaussian" => Ok(Filter::Gaussian),
+            "mitchell" => Ok(Filter::Mitchell),
+            "lanczos" => Ok(Filter::Lanczos),
+            _ => Err(format!("unknown filter '{}'", s)),
+        }
+    }
+}
+
+impl Filter {
+    // radius in pixels when none is given
+    pub fn default_radius(self) -> f64 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell | Filter::Lanczos => 2.,
+        }
+    }
+
+    // weight at distance `d` along one axis, 0 from `radius` on
+    fn profile(self, d: f64, radius: f64) -> f64 {
+        let gaussian = |d: f64| (-4.5 * d * d / (radius * radius)).exp();
+        let sinc = |x: f64| match x.abs() < 1e-6 {
+            true => 1.,
+            false => (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x),
+        };
+        match self {
+            // the half-open interval, so a sample on the edge of a pixel counts once
+            Filter::Box if (-radius..radius).contains(&d) => 1.,
+            Filter::Box => 0.,
+            Filter::Tent => (1. - d.abs() / radius).max(0.),
+            // sigma a third of the radius, shifted down to reach 0 there
+            Filter::Gaussian => (gaussian(d) - gaussian(radius)).max(0.),
+            Filter::Mitchell => {
+                let x = 2. * d.abs() / radius;
+                if x < 1. {
+                    (7. * x * x * x - 12. * x * x + 16. / 3.) / 6.
+                } else if x < 2. {
+                    (-7. / 3. * x * x * x + 12. * x * x - 20. * x + 32. / 3.) / 6.
+                } else {
+                    0.
+                }
+            }
+            Filter::Lanczos if d.abs() < radius => sinc(d) * sinc(d / radius),
+            Filter::Lanczos => 0.,
+        }
+    }
+}
+
+// A reconstruction filter of the given radius in pixels.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PixelFilter {
+    pub filter: Filter,
+    pub radius: f64,
+}
+
+impl PixelFilter {
+    // every sample only counts towards the pixel it was taken in, equally
+    pub const BOX: PixelFilter = PixelFilter {
+        filter: Filter::Box,
+        radius: 0.5,
+    };
+
+    pub fn new(filter: Filter, radius: Option<f64>) -> PixelFilter {
+        PixelFilter {
+            filter,
+            radius: radius.unwrap_or_else(|| filter.default_radius()),
+        }
+    }
+
+    // Separable weight of a sample at offset (dx, dy) from a pixel center. Mitchell and
+    // Lanczos weights go negative away from the center.
+    pub fn weight(self, dx: f64, dy: f64) -> f64 {
+        self.filter.profile(dx, self.radius) * self.filter.profile(dy, self.radius)
+    }
+
+    // rows or columns of pixels next to the one a sample is taken in that it can reach
+    pub fn margin(self) -> usize {
+        (self.radius - 0.5).ceil().max(0.) as usize
+    }
+}
+
+// Weighted sums of the samples splatted onto a pixel.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct Splat {
+    pub sum: Color,
+    // of the fractions of the samples that hit the scene
+    pub coverage: f64,
+    pub weight: f64,
+}
+
+impl Splat {
+    // the filtered color, black where no sample landed and clamped to 0 where negative
+    // lobes outweigh the rest
+    pub fn color(self) -> Color {
+        match self.weight > 0. {
+            true => (self.sum / self.weight).max(Color::BLACK),
+            false => Color::BLACK,
+        }
+    }
+
+    pub fn coverage(self) -> f64 {
+        match self.weight > 0. {
+            true => (self.coverage / self.weight).clamp(0., 1.),
+            false => 0.,
+        }
+    }
+}
+
+impl Film<Splat> {
+    // Adds a sample taken at the image point (x, y), `coverage` of it on the scene, to the
+    // pixels its filter reaches, pixel (i, j) having its center at (i + 0.5, j + 0.5).
+    pub fn splat(&mut self, x: f64, y: f64, color: Color, coverage: f64, filter: PixelFilter) {
+        let r = filter.radius;
+        // the pixels with their centers within the radius
+        let span = |c: f64, n: usize| {
+            let first = (c - 0.5 - r).ceil().max(0.) as usize;
+            let end = ((c - 0.5 + r).floor() + 1.).clamp(0., n as f64) as usize;
+            first..end
+        };
+        for j in span(y, self.height) {
+            for i in span(x, self.width) {
+                let weight = filter.weight(x - (i as f64 + 0.5), y - (j as f64 + 0.5));
+                if weight != 0. {
+                    let pixel = &mut self[(i, j)];
+                    pixel.sum += color * weight;
+                    pixel.coverage += coverage * weight;
+                    pixel.weight += weight;
+                }
+            }
+        }
+    }
+
+    pub fn resolve(&self) -> Film<Color> {
+        self.map(Splat::color)
+    }
+}
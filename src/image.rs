@@ -0,0 +1,819 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use crate::color::Color;
+use crate::error::Error;
+use crate::film::Film;
+use crate::sampler::{blue_noise, white_noise};
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Format {
+    Ppm,
+    Pam,
+    Png,
+    Qoi,
+    Tga,
+    // 32-bit float formats that keep the HDR values as they are
+    Pfm,
+    Tiff,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "ppm" => Ok(Format::Ppm),
+            "pam" => Ok(Format::Pam),
+            "png" => Ok(Format::Png),
+            "qoi" => Ok(Format::Qoi),
+            "tga" => Ok(Format::Tga),
+            "pfm" => Ok(Format::Pfm),
+            "tif" | "tiff" => Ok(Format::Tiff),
+            _ => Err(format!("unknown image format '{}'", s)),
+        }
+    }
+}
+
+impl Format {
+    // picked by the file extension, PPM unless it is known
+    pub fn from_path(path: &str) -> Format {
+        path.rsplit_once('.')
+            .and_then(|(_, extension)| extension.to_ascii_lowercase().parse().ok())
+            .unwrap_or(Format::Ppm)
+    }
+
+    // Content-Type of the served images
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Format::Ppm => "image/x-portable-pixmap",
+            Format::Pam => "image/x-portable-arbitrarymap",
+            Format::Png => "image/png",
+            Format::Qoi => "image/qoi",
+            Format::Tga => "image/x-tga",
+            Format::Pfm => "image/x-portable-floatmap",
+            Format::Tiff => "image/tiff",
+        }
+    }
+}
+
+// Noise added before rounding to 8 bits, to break up banding in smooth gradients.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Dither {
+    None,
+    // independent per sample
+    White,
+    // 8x8 ordered Bayer matrix
+    Bayer,
+    // tiled void-and-cluster mask of the sampler
+    BlueNoise,
+}
+
+impl FromStr for Dither {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Dither, String> {
+        match s {
+            "none" | "false" => Ok(Dither::None),
+            // a bare `--dither` keeps meaning white noise
+            "white" | "true" => Ok(Dither::White),
+            "bayer" => Ok(Dither::Bayer),
+            "blue-noise" => Ok(Dither::BlueNoise),
+            _ => Err(format!("unknown dither '{}'", s)),
+        }
+    }
+}
+
+// threshold in (0, 1) of the 8x8 Bayer matrix, built by interleaving the bits of
+// x ^ y and y in reverse order
+fn bayer(x: usize, y: usize) -> f64 {
+    let (mut a, mut b, mut index) = (x ^ y, y, 0);
+    for _ in 0..3 {
+        index = index << 2 | (a & 1) << 1 | (b & 1);
+        a >>= 1;
+        b >>= 1;
+    }
+    (index as f64 + 0.5) / 64.
+}
+
+impl Dither {
+    // offset added to a sample of channel `k` at pixel (x, y) before it is truncated
+    fn offset(self, x: usize, y: usize, k: usize) -> f64 {
+        match self {
+            Dither::None => 0.,
+            Dither::White => white_noise(x, y, k),
+            // the ordered patterns share their threshold between channels, keeping hues
+            Dither::Bayer => bayer(x, y),
+            Dither::BlueNoise => blue_noise(x, y),
+        }
+    }
+}
+
+// How the samples of an image are stored: 8 or 16 bits per sample, with the rounding to
+// 8 bits dithered.
+#[derive(Copy, Clone)]
+pub struct Encoding {
+    pub format: Format,
+    pub depth: u32,
+    pub dither: Dither,
+}
+
+// Final image in linear [0, 1] color with optional straight alpha of the same size.
+pub struct Image {
+    pub color: Film<Color>,
+    pub alpha: Option<Film<f64>>,
+}
+
+impl Image {
+    pub fn new(color: Film<Color>, alpha: Option<Film<f64>>) -> Image {
+        if let Some(alpha) = &alpha {
+            assert_eq!(
+                (alpha.width(), alpha.height()),
+                (color.width(), color.height()),
+                "size of the alpha channel"
+            );
+        }
+        Image { color, alpha }
+    }
+
+    pub fn width(&self) -> usize {
+        self.color.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.color.height()
+    }
+
+    // The file contents of the image, `text` is embedded as metadata by the formats that
+    // have room for it, currently PNG.
+    pub fn encode(&self, encoding: &Encoding, text: &[(String, String)]) -> Vec<u8> {
+        encode(encoding, self, text)
+    }
+
+    pub fn save(
+        &self,
+        path: &str,
+        encoding: &Encoding,
+        text: &[(String, String)],
+    ) -> Result<(), Error> {
+        let data = self.encode(encoding, text);
+        let mut file = std::fs::File::create(path).map_err(|err| Error::io(path, err))?;
+        file.write_all(&data).map_err(|err| Error::io(path, err))
+    }
+
+    // Reads back a binary PPM or a PFM as written by `save`, e.g. the golden images of
+    // regression tests. PFM keeps the HDR values exactly.
+    pub fn load(path: &str) -> Result<Image, Error> {
+        load(path)
+    }
+}
+
+fn quantize(c: f64, max: f64, offset: f64) -> u16 {
+    ((max * c + offset) as i64).clamp(0, max as i64) as u16
+}
+
+// interleaved big-endian samples, RGBA if the image has alpha and it is asked for
+fn samples(image: &Image, with_alpha: bool, encoding: &Encoding) -> Vec<u8> {
+    let alpha = image.alpha.as_ref().filter(|_| with_alpha);
+    let channels = if alpha.is_some() { 4 } else { 3 };
+    let wide = encoding.depth == 16;
+    let max = if wide { 65535. } else { 255. };
+    let mut data =
+        Vec::with_capacity(image.width() * image.height() * channels * (1 + wide as usize));
+    for (x, y, c) in image.color.pixels() {
+        let values = [c.r, c.g, c.b, alpha.map_or(0., |a| a[(x, y)])];
+        for (k, value) in values.iter().take(channels).enumerate() {
+            // without dithering the values are truncated as they always were
+            let offset = if wide {
+                0.
+            } else {
+                encoding.dither.offset(x, y, k)
+            };
+            let sample = quantize(*value, max, offset);
+            if wide {
+                data.extend_from_slice(&sample.to_be_bytes());
+            } else {
+                data.push(sample as u8);
+            }
+        }
+    }
+    data
+}
+
+fn maxval(encoding: &Encoding) -> u32 {
+    (1 << encoding.depth) - 1
+}
+
+fn encode(encoding: &Encoding, image: &Image, text: &[(String, String)]) -> Vec<u8> {
+    match encoding.format {
+        Format::Ppm => samples_with_header(
+            format!(
+                "P6\n{} {}\n{}\n",
+                image.width(),
+                image.height(),
+                maxval(encoding)
+            ),
+            image,
+            false,
+            encoding,
+        ),
+        Format::Pam => {
+            let tuple_type = if image.alpha.is_some() {
+                "RGB_ALPHA"
+            } else {
+                "RGB"
+            };
+            let depth = if image.alpha.is_some() { 4 } else { 3 };
+            samples_with_header(
+                format!(
+                    "P7\nWIDTH {}\nHEIGHT {}\nDEPTH {}\nMAXVAL {}\nTUPLTYPE {}\nENDHDR\n",
+                    image.width(),
+                    image.height(),
+                    depth,
+                    maxval(encoding),
+                    tuple_type
+                ),
+                image,
+                true,
+                encoding,
+            )
+        }
+        Format::Png => png(image, encoding, text),
+        Format::Qoi => qoi(image, encoding),
+        Format::Tga => tga(image, encoding),
+        Format::Pfm => pfm(image),
+        Format::Tiff => tiff(image),
+    }
+}
+
+fn samples_with_header(
+    header: String,
+    image: &Image,
+    with_alpha: bool,
+    encoding: &Encoding,
+) -> Vec<u8> {
+    let mut data = header.into_bytes();
+    data.extend_from_slice(&samples(image, with_alpha, encoding));
+    data
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += u32::from(*byte);
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    // least significant bit first, as deflate packs everything but Huffman codes
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are packed starting from their most significant bit
+    fn write_code(&mut self, code: u32, length: u32) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.write(reversed, length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+const LENGTH_BASE: [u32; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u32; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u32; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u32; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const WINDOW: usize = 32768;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+// literal/length symbol with the fixed Huffman code of RFC 1951
+fn write_literal(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let (length, distance) = (length as u32, distance as u32);
+    let l = LENGTH_BASE.iter().rposition(|b| *b <= length).unwrap();
+    write_literal(writer, 257 + l as u32);
+    writer.write(length - LENGTH_BASE[l], LENGTH_EXTRA[l]);
+    let d = DISTANCE_BASE.iter().rposition(|b| *b <= distance).unwrap();
+    writer.write_code(d as u32, 5);
+    writer.write(distance - DISTANCE_BASE[d], DISTANCE_EXTRA[d]);
+}
+
+// zlib stream of a single fixed-Huffman deflate block, with greedy LZ77 over hash chains
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        out: vec![0x78, 0x01],
+        bits: 0,
+        count: 0,
+    };
+    writer.write(1, 1); // final block
+    writer.write(1, 2); // fixed Huffman codes
+
+    let hash = |i: usize| {
+        let v = u32::from(data[i]) | u32::from(data[i + 1]) << 8 | u32::from(data[i + 2]) << 16;
+        (v.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    };
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+    let insert = |i: usize, head: &mut Vec<usize>, prev: &mut Vec<usize>| {
+        if i + 2 < data.len() {
+            let h = hash(i);
+            prev[i] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let (mut best_length, mut best_distance) = (0, 0);
+        if i + 2 < data.len() {
+            let mut candidate = head[hash(i)];
+            let max_length = MAX_MATCH.min(data.len() - i);
+            let mut chain = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW && chain < MAX_CHAIN {
+                let length = (0..max_length)
+                    .take_while(|k| data[candidate + k] == data[i + k])
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = i - candidate;
+                    if length == max_length {
+                        break;
+                    }
+                }
+                candidate = prev[candidate];
+                chain += 1;
+            }
+        }
+        if best_length >= 3 {
+            write_match(&mut writer, best_length, best_distance);
+            for k in i..i + best_length {
+                insert(k, &mut head, &mut prev);
+            }
+            i += best_length;
+        } else {
+            write_literal(&mut writer, u32::from(data[i]));
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+    write_literal(&mut writer, 256);
+
+    let mut out = writer.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let (pa, pb, pc) = (
+        (p - i16::from(a)).abs(),
+        (p - i16::from(b)).abs(),
+        (p - i16::from(c)).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Every scanline takes the filter with the smallest sum of absolute residuals,
+// the heuristic recommended by the PNG specification.
+fn filter_scanlines(data: &[u8], stride: usize, bpp: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / stride);
+    let zero = vec![0; stride];
+    let mut candidate = vec![0; stride];
+    let mut best = vec![0; stride];
+    for (y, row) in data.chunks(stride).enumerate() {
+        let up = if y == 0 {
+            &zero[..]
+        } else {
+            &data[(y - 1) * stride..y * stride]
+        };
+        let mut best_filter = 0;
+        let mut best_cost = u64::MAX;
+        for filter in 0..5u8 {
+            for x in 0..stride {
+                let a = if x >= bpp { row[x - bpp] } else { 0 };
+                let c = if x >= bpp { up[x - bpp] } else { 0 };
+                let predicted = match filter {
+                    0 => 0,
+                    1 => a,
+                    2 => up[x],
+                    3 => ((u16::from(a) + u16::from(up[x])) / 2) as u8,
+                    _ => paeth(a, up[x], c),
+                };
+                candidate[x] = row[x].wrapping_sub(predicted);
+            }
+            let cost = candidate
+                .iter()
+                .map(|v| u64::from((*v as i8).unsigned_abs()))
+                .sum();
+            if cost < best_cost {
+                best_cost = cost;
+                best_filter = filter;
+                best.copy_from_slice(&candidate);
+            }
+        }
+        out.push(best_filter);
+        out.extend_from_slice(&best);
+    }
+    out
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+// tEXt holds Latin-1, anything else goes into an uncompressed international iTXt chunk
+fn png_text(out: &mut Vec<u8>, keyword: &str, text: &str) {
+    let mut data = keyword.as_bytes().to_vec();
+    data.push(0);
+    if text.is_ascii() {
+        data.extend_from_slice(text.as_bytes());
+        png_chunk(out, b"tEXt", &data);
+    } else {
+        // no compression, empty language tag and translated keyword
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(text.as_bytes());
+        png_chunk(out, b"iTXt", &data);
+    }
+}
+
+fn png(image: &Image, encoding: &Encoding, text: &[(String, String)]) -> Vec<u8> {
+    let channels = if image.alpha.is_some() { 4 } else { 3 };
+    let bytes = encoding.depth as usize / 8;
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(image.width() as u32).to_be_bytes());
+    header.extend_from_slice(&(image.height() as u32).to_be_bytes());
+    // truecolor with or without alpha, no interlacing
+    let color_type = if channels == 4 { 6 } else { 2 };
+    header.extend_from_slice(&[encoding.depth as u8, color_type, 0, 0, 0]);
+
+    let scanlines = filter_scanlines(
+        &samples(image, true, encoding),
+        image.width() * channels * bytes,
+        channels * bytes,
+    );
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    png_chunk(&mut out, b"IHDR", &header);
+    for (keyword, value) in text {
+        png_text(&mut out, keyword, value);
+    }
+    png_chunk(&mut out, b"IDAT", &zlib(&scanlines));
+    png_chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+// the 8-bit formats keep their depth whatever is asked for
+fn samples_8bit(image: &Image, encoding: &Encoding) -> Vec<u8> {
+    let encoding = Encoding {
+        depth: 8,
+        ..*encoding
+    };
+    samples(image, true, &encoding)
+}
+
+// "Quite OK Image" format, https://qoiformat.org/qoi-specification.pdf
+fn qoi(image: &Image, encoding: &Encoding) -> Vec<u8> {
+    let channels = if image.alpha.is_some() { 4 } else { 3 };
+    let data = samples_8bit(image, encoding);
+    let mut out = b"qoif".to_vec();
+    out.extend_from_slice(&(image.width() as u32).to_be_bytes());
+    out.extend_from_slice(&(image.height() as u32).to_be_bytes());
+    // sRGB color with linear alpha
+    out.extend_from_slice(&[channels as u8, 0]);
+
+    let mut seen = [[0u8; 4]; 64];
+    let mut previous = [0, 0, 0, 255];
+    let mut run = 0;
+    for sample in data.chunks(channels) {
+        let pixel = [
+            sample[0],
+            sample[1],
+            sample[2],
+            *sample.get(3).unwrap_or(&255),
+        ];
+        if pixel == previous {
+            run += 1;
+            if run == 62 {
+                out.push(0xc0 | (run - 1));
+                run = 0;
+            }
+            continue;
+        }
+        if run > 0 {
+            out.push(0xc0 | (run - 1));
+            run = 0;
+        }
+        let [r, g, b, a] = pixel;
+        let index =
+            (usize::from(r) * 3 + usize::from(g) * 5 + usize::from(b) * 7 + usize::from(a) * 11)
+                % 64;
+        if seen[index] == pixel {
+            out.push(index as u8);
+        } else if a != previous[3] {
+            out.extend_from_slice(&[0xff, r, g, b, a]);
+        } else {
+            let dr = r.wrapping_sub(previous[0]) as i8;
+            let dg = g.wrapping_sub(previous[1]) as i8;
+            let db = b.wrapping_sub(previous[2]) as i8;
+            let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+            if (-2..2).contains(&dr) && (-2..2).contains(&dg) && (-2..2).contains(&db) {
+                out.push(0x40 | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8);
+            } else if (-32..32).contains(&dg)
+                && (-8..8).contains(&dr_dg)
+                && (-8..8).contains(&db_dg)
+            {
+                out.push(0x80 | (dg + 32) as u8);
+                out.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+            } else {
+                out.extend_from_slice(&[0xfe, r, g, b]);
+            }
+        }
+        seen[index] = pixel;
+        previous = pixel;
+    }
+    if run > 0 {
+        out.push(0xc0 | (run - 1));
+    }
+    out.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+    out
+}
+
+// Run-length encoded truecolor Truevision TGA with the origin at the top left
+fn tga(image: &Image, encoding: &Encoding) -> Vec<u8> {
+    let channels = if image.alpha.is_some() { 4 } else { 3 };
+    let mut out = vec![0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    out.extend_from_slice(&(image.width() as u16).to_le_bytes());
+    out.extend_from_slice(&(image.height() as u16).to_le_bytes());
+    let alpha_bits = if channels == 4 { 8 } else { 0 };
+    out.extend_from_slice(&[8 * channels as u8, 0x20 | alpha_bits]);
+
+    // BGR(A) pixels, packets never cross scanlines
+    let pixels: Vec<Vec<u8>> = samples_8bit(image, encoding)
+        .chunks(channels)
+        .map(|p| {
+            let mut bgr = vec![p[2], p[1], p[0]];
+            bgr.extend_from_slice(&p[3..]);
+            bgr
+        })
+        .collect();
+    for row in pixels.chunks(image.width().max(1)) {
+        let mut i = 0;
+        while i < row.len() {
+            let repeats = row[i..]
+                .iter()
+                .take(128)
+                .take_while(|p| **p == row[i])
+                .count();
+            if repeats > 1 {
+                out.push(0x80 | (repeats - 1) as u8);
+                out.extend_from_slice(&row[i]);
+                i += repeats;
+                continue;
+            }
+            // raw packet up to the next run of at least two equal pixels
+            let mut end = i + 1;
+            while end < row.len()
+                && end - i < 128
+                && (end + 1 == row.len() || row[end] != row[end + 1])
+            {
+                end += 1;
+            }
+            out.push((end - i - 1) as u8);
+            for pixel in &row[i..end] {
+                out.extend_from_slice(pixel);
+            }
+            i = end;
+        }
+    }
+    out
+}
+
+// Portable float map: little-endian RGB floats from the bottom row up, alpha is dropped
+fn pfm(image: &Image) -> Vec<u8> {
+    let mut out = format!("PF\n{} {}\n-1.0\n", image.width(), image.height()).into_bytes();
+    for row in image.color.rows().rev() {
+        for c in row {
+            for value in c.to_array() {
+                out.extend_from_slice(&(value as f32).to_le_bytes());
+            }
+        }
+    }
+    out
+}
+
+// Little-endian baseline TIFF of a single uncompressed strip of float samples,
+// with straight alpha as an extra sample
+fn tiff(image: &Image) -> Vec<u8> {
+    let channels: u16 = if image.alpha.is_some() { 4 } else { 3 };
+    let mut out = b"II*\0".to_vec();
+    let strip = 8;
+    let strip_bytes = image.width() * image.height() * channels as usize * 4;
+    let ifd = strip + strip_bytes;
+    out.extend_from_slice(&(ifd as u32).to_le_bytes());
+    for (x, y, c) in image.color.pixels() {
+        let values = [
+            c.r,
+            c.g,
+            c.b,
+            image.alpha.as_ref().map_or(0., |a| a[(x, y)]),
+        ];
+        for value in values.iter().take(channels as usize) {
+            out.extend_from_slice(&(*value as f32).to_le_bytes());
+        }
+    }
+
+    const SHORT: u16 = 3;
+    const LONG: u16 = 4;
+    // (tag, type, count, value or offset)
+    let mut entries: Vec<(u16, u16, u32, u32)> = vec![
+        (256, LONG, 1, image.width() as u32),
+        (257, LONG, 1, image.height() as u32),
+        (258, SHORT, u32::from(channels), 0),
+        (259, SHORT, 1, 1),
+        (262, SHORT, 1, 2),
+        (273, LONG, 1, strip as u32),
+        (277, SHORT, 1, u32::from(channels)),
+        (278, LONG, 1, image.height() as u32),
+        (279, LONG, 1, strip_bytes as u32),
+        (284, SHORT, 1, 1),
+        (339, SHORT, u32::from(channels), 0),
+    ];
+    if channels == 4 {
+        entries.insert(10, (338, SHORT, 1, 2));
+    }
+    // the per-sample arrays don't fit into their entries and follow the directory
+    let arrays = ifd + 2 + entries.len() * 12 + 4;
+    let per_sample =
+        |value: u16| -> Vec<u8> { (0..channels).flat_map(|_| value.to_le_bytes()).collect() };
+    let mut extra = Vec::new();
+    for entry in entries.iter_mut().filter(|e| e.2 > 2) {
+        entry.3 = (arrays + extra.len()) as u32;
+        // 32 bits per sample, IEEE floating point
+        extra.extend(per_sample(if entry.0 == 258 { 32 } else { 3 }));
+    }
+
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, kind, count, value) in entries {
+        out.extend_from_slice(&tag.to_le_bytes());
+        out.extend_from_slice(&kind.to_le_bytes());
+        out.extend_from_slice(&count.to_le_bytes());
+        if kind == SHORT && count == 1 {
+            out.extend_from_slice(&(value as u16).to_le_bytes());
+            out.extend_from_slice(&[0, 0]);
+        } else {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&extra);
+    out
+}
+
+// whitespace separated header fields of a Netpbm-style file and the offset of the raster
+fn header_fields(data: &[u8], count: usize) -> Result<(Vec<String>, usize), Error> {
+    let mut fields = Vec::with_capacity(count);
+    let mut pos = 0;
+    while fields.len() < count {
+        while pos < data.len() && data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err("truncated header".into());
+        }
+        fields.push(String::from_utf8_lossy(&data[start..pos]).into_owned());
+    }
+    // a single whitespace byte separates the header from the raster
+    Ok((fields, pos + 1))
+}
+
+fn load(path: &str) -> Result<Image, Error> {
+    let data = std::fs::read(path).map_err(|err| Error::io(path, err))?;
+    let (fields, start) = header_fields(&data, 4).map_err(|err| err.in_file(path))?;
+    let number = |s: &str| {
+        s.parse::<f64>()
+            .map_err(|_| format!("{}: invalid header value '{}'", path, s))
+    };
+    let (width, height) = (number(&fields[1])? as usize, number(&fields[2])? as usize);
+    let scale = number(&fields[3])?;
+    let count = width * height * 3;
+    let color = match fields[0].as_str() {
+        "P6" => {
+            let bytes = if scale > 255. { 2 } else { 1 };
+            let raster = data
+                .get(start..start + count * bytes)
+                .ok_or(format!("{}: truncated raster", path))?;
+            let values: Vec<f64> = raster
+                .chunks(bytes)
+                .map(|b| b.iter().fold(0., |v, byte| v * 256. + f64::from(*byte)) / scale)
+                .collect();
+            values
+                .chunks(3)
+                .map(|c| Color::new(c[0], c[1], c[2]))
+                .collect()
+        }
+        "PF" => {
+            let raster = data
+                .get(start..start + count * 4)
+                .ok_or(format!("{}: truncated raster", path))?;
+            let value = |b: &[u8]| {
+                let bytes = [b[0], b[1], b[2], b[3]];
+                // a negative scale marks little-endian samples
+                f64::from(if scale < 0. {
+                    f32::from_le_bytes(bytes)
+                } else {
+                    f32::from_be_bytes(bytes)
+                })
+            };
+            let rows: Vec<Vec<Color>> = raster
+                .chunks(width.max(1) * 12)
+                .map(|row| {
+                    row.chunks(12)
+                        .map(|p| Color::new(value(&p[0..4]), value(&p[4..8]), value(&p[8..12])))
+                        .collect()
+                })
+                .collect();
+            // the rows are stored from the bottom up
+            rows.into_iter().rev().flatten().collect()
+        }
+        magic => {
+            return Err(Error::Parse(format!(
+                "{}: unsupported image type '{}'",
+                path, magic
+            )))
+        }
+    };
+    Ok(Image::new(Film::from_vec(width, height, color), None))
+}
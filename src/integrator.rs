@@ -0,0 +1,362 @@
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use crate::color::Color;
+use crate::options::Options;
+use crate::qmc::radical_inverse;
+use crate::ray::{Hit, Ray};
+use crate::sampler::PixelSampler;
+use crate::sampling::{around, cosine_hemisphere_pdf, sample_cosine_hemisphere};
+use crate::scene::{Object, Scene, Shading, Shape};
+use crate::shaping::smoothstep;
+use crate::sky::{background, fog, Sky};
+use crate::vec3d::{Vec2d, Vec3d};
+use crate::volume;
+
+// secondary rays start this far off the surface by default, so they don't hit the surface
+// they leave within the marcher's epsilon
+pub const NORMAL_OFFSET: f64 = 0.05;
+const RUSSIAN_ROULETTE_DEPTH: usize = 3;
+// how much sky radiance the direct integrator lets through as ambient light
+const SKY_AMBIENT: f64 = 1.;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Integrator {
+    Direct,
+    Path,
+    Volume,
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Integrator, String> {
+        match s {
+            "direct" => Ok(Integrator::Direct),
+            "path" => Ok(Integrator::Path),
+            "volume" => Ok(Integrator::Volume),
+            _ => Err(format!("unknown integrator '{}'", s)),
+        }
+    }
+}
+
+// Auxiliary buffers of the primary hit, guides for image-space filters.
+#[derive(Copy, Clone)]
+pub struct Aov {
+    pub normal: Vec3d,
+    pub depth: f64,
+    // how much of the background the scene hides, 1 for surfaces
+    pub opacity: f64,
+}
+
+impl Default for Aov {
+    fn default() -> Aov {
+        Aov {
+            normal: Vec3d::zero(),
+            depth: f64::INFINITY,
+            opacity: 0.,
+        }
+    }
+}
+
+fn albedo(object: &Object, hit: Vec3d, normal: Vec3d) -> Color {
+    let material = &object.material;
+    match (material.shading, object.smoke(hit)) {
+        (Shading::Trap, _) => return material.palette.color(object.orbit(hit).trap),
+        (Shading::Iterations, _) => return material.palette.color(object.orbit(hit).iterations),
+        (Shading::Fire, Some((cover, smoke))) => return Color::lerp(material.albedo, smoke, cover),
+        _ => {}
+    }
+    match material.texture {
+        Some(texture) => {
+            texture.triplanar(hit, normal, material.texture_scale) * (material.albedo * 2.)
+        }
+        None => material.albedo,
+    }
+}
+
+pub fn emission(object: &Object, hit: Vec3d) -> Color {
+    let material = &object.material;
+    match material.shading {
+        Shading::Fire => {
+            // the smoke shell covers the glow
+            let glow = material.palette.color(object.temperature(hit)) * material.heat.gain;
+            match object.smoke(hit) {
+                Some((cover, _)) => glow * (1. - cover),
+                None => glow,
+            }
+        }
+        Shading::Diffuse | Shading::Trap | Shading::Iterations => Color::BLACK,
+    }
+}
+
+pub fn direct(
+    options: &Options,
+    scene: &Scene,
+    orig: Vec3d,
+    dir: Vec3d,
+    sampler: &mut PixelSampler,
+    aov: &mut Aov,
+) -> Color {
+    match scene.intersect(Ray::new(orig, dir)) {
+        Some(hit) => shade(options, scene, &hit, dir, sampler, aov),
+        None => background(options, dir),
+    }
+}
+
+// A camera ray that missed the scene: the surface it passes closest to is shaded there and
+// laid over the background by the part of the pixel it covers, for pixels `spread` wide at
+// unit distance. Silhouettes come out anti-aliased with a single ray per pixel.
+pub fn silhouette(
+    options: &Options,
+    scene: &Scene,
+    ray: Ray,
+    spread: f64,
+    sampler: &mut PixelSampler,
+    aov: &mut Aov,
+) -> Color {
+    let background = background(options, ray.dir);
+    match scene.silhouette(ray, spread) {
+        Some((hit, coverage)) => {
+            let color = shade(options, scene, &hit, ray.dir, sampler, aov);
+            aov.opacity = coverage;
+            background * (1. - coverage) + color * coverage
+        }
+        None => background,
+    }
+}
+
+// Direct lighting of a surface point seen along `dir`, fogged over the distance the ray
+// traveled to it. The soft shadows of several samples per pixel are drawn from `sampler`.
+pub fn shade(
+    options: &Options,
+    scene: &Scene,
+    hit: &Hit,
+    dir: Vec3d,
+    sampler: &mut PixelSampler,
+    aov: &mut Aov,
+) -> Color {
+    let object = scene.object(hit);
+    let position = hit.position;
+    let normal = object.normal(position);
+    let depth = hit.distance;
+    *aov = Aov {
+        normal,
+        depth,
+        opacity: 1.,
+    };
+
+    let to_light = options.light.position - position;
+    let light_dir = to_light.normalized();
+    // objects the light isn't linked to only get the ambient light
+    let linked = options.light.lights(hit.object);
+    let mut visibility = || match linked {
+        true => {
+            let p = position + normal * options.normal_offset;
+            light_visibility(options, scene, p, light_dir, to_light.length(), sampler)
+        }
+        false => 0.,
+    };
+    let style = object.material.style;
+    // the ambient floor is the sky seen by the surface, or a constant for the flat one
+    let ambient = match options.sky {
+        Sky::Flat => Color::gray(0.4),
+        _ => background(options, normal) * SKY_AMBIENT,
+    } * style.ambient;
+    let illumination = options.light.illumination(position);
+    let diffuse = (light_dir.dot(normal) + style.wrap) / (1. + style.wrap);
+    let light_intensity =
+        |shadow: f64| (illumination * toon(options, diffuse * shadow)).max(ambient);
+    // fire is shaded by its palette color without shadows, its smoke shell and everything
+    // else by their albedo
+    let color = match object.material.shading {
+        Shading::Fire => {
+            let fire = emission(object, position) * light_intensity(if linked { 1. } else { 0. });
+            match object.smoke(position) {
+                Some((cover, smoke)) if cover > 0. => {
+                    fire + smoke * cover * light_intensity(visibility())
+                }
+                _ => fire,
+            }
+        }
+        Shading::Diffuse | Shading::Trap | Shading::Iterations => {
+            albedo(object, position, normal) * light_intensity(visibility())
+        }
+    };
+    let tint = Color::lerp(style.tint_bottom, style.tint_top, 0.5 + 0.5 * normal.y);
+    let rim = match style.rim > 0. && linked {
+        true => {
+            let grazing = 1. - (-dir.dot(normal)).max(0.);
+            illumination * (style.rim * smoothstep(1. - style.rim_width, 1., grazing))
+        }
+        false => Color::BLACK,
+    };
+    let color = color * tint + rim;
+    let (transmittance, inscatter) = fog(options, depth);
+    color * transmittance + inscatter
+}
+
+// the light reaching a surface in the bands of cel shading, unchanged without
+fn toon(options: &Options, light: f64) -> f64 {
+    match options.toon {
+        0 => light,
+        n => (light.clamp(0., 1.) * n as f64).ceil() / n as f64,
+    }
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    pdf * pdf / (pdf * pdf + other_pdf * other_pdf)
+}
+
+// Fraction of the light seen from `p`, which is `distance` away towards `dir`: one shadow
+// ray to its center, or `shadow_samples` rays to points on it. A single sample per pixel
+// takes them in a stratified pattern, the same at every point so soft shadows come out
+// smooth rather than noisy. Several take them from the pixel's `sampler`, so the penumbra is
+// integrated over the samples, which adaptive sampling spends where it is noisy.
+fn light_visibility(
+    options: &Options,
+    scene: &Scene,
+    p: Vec3d,
+    dir: Vec3d,
+    distance: f64,
+    sampler: &mut PixelSampler,
+) -> f64 {
+    let n = options.shadow_samples;
+    if !options.light.shadows {
+        return 1.;
+    }
+    if n <= 1 {
+        return shadow_ray(options, scene, p, dir, distance);
+    }
+    let visible: f64 = (0..n)
+        .map(|k| {
+            let u = match options.spp > 1 {
+                true => sampler.next_2d(),
+                false => Vec2d::new((k as f64 + 0.5) / n as f64, radical_inverse(2, k as u32)),
+            };
+            let sample = options.light.sample(p, u.x, u.y);
+            shadow_ray(options, scene, p, sample.dir, sample.distance)
+        })
+        .sum();
+    visible / n as f64
+}
+
+// Light let through along one shadow ray: all of it or none past surfaces, and with the
+// volume integrator, whose fireballs are smoke rather than surfaces, what the smoke between
+// them and the light doesn't absorb.
+fn shadow_ray(options: &Options, scene: &Scene, p: Vec3d, dir: Vec3d, distance: f64) -> f64 {
+    match options.integrator {
+        Integrator::Volume => {
+            let blocker = scene
+                .intersect_filtered(Ray::new(p, dir), |object| object.shape != Shape::Fireball);
+            match blocker {
+                Some(hit) if hit.distance <= distance => 0.,
+                _ => volume::shadow_transmittance(options, scene, p, dir, distance),
+            }
+        }
+        _ if unoccluded(scene, p, dir, distance) => 1.,
+        _ => 0.,
+    }
+}
+
+fn unoccluded(scene: &Scene, orig: Vec3d, dir: Vec3d, distance: f64) -> bool {
+    match scene.intersect(Ray::new(orig, dir)) {
+        Some(hit) => hit.distance > distance,
+        None => true,
+    }
+}
+
+// Unidirectional path tracer: fire materials emit their palette color, and all surfaces
+// diffusely reflect light coming from the fire, the light and the sky.
+// With `nee` the light is also sampled explicitly at every vertex, and both
+// strategies are combined with multiple importance sampling.
+pub fn path(
+    options: &Options,
+    scene: &Scene,
+    orig: Vec3d,
+    dir: Vec3d,
+    sampler: &mut PixelSampler,
+    aov: &mut Aov,
+) -> Color {
+    let light = &options.light;
+    let mut radiance = Color::BLACK;
+    let mut throughput = Color::WHITE;
+    let mut orig = orig;
+    let mut dir = dir;
+    // solid angle pdf of the direction `dir` was sampled with, unused for camera rays
+    let mut dir_pdf = 0.;
+    // whether the light reaches the vertex `dir` leaves, the camera sees it anyway
+    let mut linked = true;
+    for bounce in 0..=options.max_bounces {
+        let intersection = scene.intersect(Ray::new(orig, dir));
+        if let Some(t) = light.intersect(orig, dir) {
+            if intersection.is_none_or(|hit| hit.distance > t) {
+                let weight = if bounce == 0 || !options.nee {
+                    1.
+                } else {
+                    power_heuristic(dir_pdf, light.pdf(orig, dir))
+                };
+                if linked {
+                    radiance += throughput * light.radiance() * (light.profile(orig) * weight);
+                }
+                break;
+            }
+        }
+        let (object, hit, depth) = match intersection {
+            Some(hit) => {
+                linked = light.lights(hit.object);
+                (scene.object(&hit), hit.position, hit.distance)
+            }
+            None => {
+                radiance += throughput * background(options, dir);
+                break;
+            }
+        };
+        let normal = object.normal(hit);
+        if bounce == 0 {
+            *aov = Aov {
+                normal,
+                depth,
+                opacity: 1.,
+            };
+        }
+        let (transmittance, inscatter) = fog(options, depth);
+        radiance += throughput * inscatter;
+        throughput *= transmittance;
+        radiance += throughput * emission(object, hit);
+        if bounce == options.max_bounces {
+            break;
+        }
+
+        let p = hit + normal * options.normal_offset;
+        let albedo = albedo(object, hit, normal);
+        if options.nee {
+            let u = sampler.next_2d();
+            let sample = light.sample(p, u.x, u.y);
+            let cos = normal.dot(sample.dir);
+            let visible = || !light.shadows || unoccluded(scene, p, sample.dir, sample.distance);
+            if linked && cos > 0. && sample.pdf > 0. && visible() {
+                let weight = power_heuristic(sample.pdf, cos / PI);
+                let f = albedo / PI;
+                let emitted = light.radiance() * light.profile(p);
+                radiance += throughput * emitted * (f * (cos * weight / sample.pdf));
+            }
+        }
+
+        let u = sampler.next_2d();
+        let rr = sampler.next_2d().x;
+        // the cosine term cancels against the cosine-weighted pdf
+        throughput *= albedo;
+        if bounce >= RUSSIAN_ROULETTE_DEPTH {
+            let survive = throughput.max_channel().min(0.95);
+            if rr >= survive {
+                break;
+            }
+            throughput /= survive;
+        }
+        orig = p;
+        dir = around(normal, sample_cosine_hemisphere(u.x, u.y));
+        dir_pdf = cosine_hemisphere_pdf(normal.dot(dir));
+    }
+    radiance
+}
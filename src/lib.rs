@@ -0,0 +1,224 @@
+// The math, noise and distance fields build without `std` as well, with `libm` for the
+// float functions, so they can run on embedded targets and in WASM without an OS.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("without the `std` feature the `libm` feature is needed for the float functions");
+
+extern crate alloc;
+
+pub mod bounds;
+pub mod color;
+pub mod matrix;
+pub mod noise;
+pub mod qmc;
+pub mod quat;
+pub mod ray;
+pub mod rng;
+pub mod sampling;
+pub mod sdf;
+pub mod shaping;
+pub mod vec2d;
+pub mod vec3d;
+pub mod vec4d;
+
+// files, threads, options and the renderer
+#[cfg(feature = "std")]
+#[macro_use]
+pub mod log;
+
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod bench;
+#[cfg(feature = "std")]
+pub mod camera;
+#[cfg(feature = "std")]
+pub mod check;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod denoise;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod distributed;
+#[cfg(feature = "std")]
+pub mod dump;
+#[cfg(feature = "std")]
+pub mod embers;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "std")]
+pub mod expr;
+#[cfg(feature = "std")]
+pub mod film;
+#[cfg(feature = "std")]
+pub mod image;
+#[cfg(feature = "std")]
+pub mod integrator;
+#[cfg(feature = "std")]
+pub mod json;
+#[cfg(feature = "std")]
+pub mod light;
+#[cfg(feature = "std")]
+pub mod mesh;
+#[cfg(feature = "std")]
+pub mod metadata;
+#[cfg(feature = "std")]
+pub mod options;
+#[cfg(feature = "std")]
+pub mod palette;
+#[cfg(feature = "std")]
+pub mod plugin;
+#[cfg(feature = "std")]
+pub mod post;
+#[cfg(feature = "std")]
+pub mod progressive;
+#[cfg(feature = "std")]
+pub mod render;
+#[cfg(feature = "std")]
+pub mod sampler;
+#[cfg(feature = "std")]
+pub mod scene;
+#[cfg(feature = "std")]
+pub mod server;
+#[cfg(feature = "std")]
+pub mod sky;
+#[cfg(feature = "std")]
+pub mod sprite;
+#[cfg(feature = "std")]
+pub mod terrain;
+#[cfg(feature = "std")]
+pub mod texture;
+#[cfg(feature = "std")]
+pub mod volume;
+
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+#[cfg(feature = "std")]
+use camera::Camera;
+#[cfg(feature = "std")]
+use color::Color;
+#[cfg(feature = "std")]
+pub use error::Error;
+#[cfg(feature = "std")]
+use film::Film;
+#[cfg(feature = "std")]
+use image::Image;
+#[cfg(feature = "std")]
+use metadata::Stats;
+#[cfg(feature = "std")]
+use options::{Options, StereoMode};
+#[cfg(feature = "std")]
+use render::render;
+#[cfg(feature = "std")]
+use vec3d::{Vec2d, Vec3d};
+
+#[cfg(feature = "std")]
+// writes the image in the format of the options, and its sidecar if asked for
+pub fn output(options: &Options, path: &str, image: &Image, stats: Stats) -> Result<(), Error> {
+    let _span = log::span("encode");
+    let start = Instant::now();
+    if let Some(dir) = &options.output_dir {
+        std::fs::create_dir_all(dir).map_err(|err| Error::io(dir, err))?;
+    }
+    let path = &options.output_path(path);
+    image.save(path, &options.encoding(path), &metadata::text(options))?;
+    if options.metadata {
+        let stats = Stats {
+            encode: start.elapsed(),
+            ..stats
+        };
+        metadata::write(path, options, image, &stats)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+pub fn default_camera(options: &Options) -> Camera {
+    let (width, height) = options.resolution();
+    let mut camera = Camera::new(options.camera_position, options.fov, width, height);
+    if let Some(target) = options.camera_target {
+        camera = camera.looking_at(target);
+    }
+    if options.camera_orbit != Vec2d::zero() || options.camera_orbit_end.is_some() {
+        let rotation = |angles: Vec2d| camera.orbit_rotation(angles.x, angles.y);
+        let mut orbit = rotation(options.camera_orbit);
+        if let Some(end) = options.camera_orbit_end {
+            orbit = orbit.slerp(rotation(end), options.progress());
+        }
+        let pivot = options.camera_target.unwrap_or(Vec3d::zero());
+        camera = camera.orbit(pivot, orbit);
+    }
+    camera.distortion = options.distortion;
+    camera
+}
+
+#[cfg(feature = "std")]
+// `left` with `right` to the right of it, both of the same size
+fn side_by_side<T: Copy + Default>(left: &Film<T>, right: &Film<T>) -> Film<T> {
+    let mut both = Film::new(2 * left.width(), left.height(), T::default());
+    both.blit(0, 0, left);
+    both.blit(left.width(), 0, right);
+    both
+}
+
+#[cfg(feature = "std")]
+// the final image of the options, with both eyes composed for stereo
+pub fn render_image(options: &Options) -> (Image, Stats) {
+    let _span = log::span("render");
+    let start = Instant::now();
+    let camera = default_camera(options);
+    let mut samples = 0;
+    let mut image = |frame: render::Frame| {
+        samples += frame.samples;
+        frame.image(options.alpha)
+    };
+
+    let image = match options.stereo {
+        None => image(render(&camera, options)),
+        Some(mode) => {
+            let half = options.interocular / 2.;
+            let left = image(render(&camera.eye(-half, options.convergence), options));
+            let right = image(render(&camera.eye(half, options.convergence), options));
+            match mode {
+                StereoMode::SideBySide => Image::new(
+                    side_by_side(&left.color, &right.color),
+                    left.alpha
+                        .zip(right.alpha)
+                        .map(|(l, r)| side_by_side(&l, &r)),
+                ),
+                StereoMode::Anaglyph => {
+                    // red channel from the left eye, green and blue from the right one
+                    let color = left
+                        .color
+                        .zip(&right.color, |l, r| Color::new(l.r, r.g, r.b));
+                    let alpha = left
+                        .alpha
+                        .zip(right.alpha)
+                        .map(|(l, r)| l.zip(&r, f64::max));
+                    Image::new(color, alpha)
+                }
+            }
+        }
+    };
+    let stats = Stats {
+        samples,
+        render: start.elapsed(),
+        ..Stats::default()
+    };
+    (image, stats)
+}
+
+#[cfg(feature = "std")]
+// Deterministic render of a scene file for golden-image tests: the settings of the file on
+// top of the defaults, with the given seed and without writing anything.
+pub fn render_reference(scene: &str, seed: u32) -> Result<Image, Error> {
+    let mut options = Options::default();
+    options.set("scene", scene)?;
+    options.set("seed", &seed.to_string())?;
+    options.validate()?;
+    Ok(render_image(&options).0)
+}
@@ -0,0 +1,265 @@
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use crate::color::Color;
+use crate::sampling::{around, cone_pdf, sample_cone};
+use crate::shaping::smoothstep;
+use crate::vec3d::{Vec2d, Vec3d};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum LightShape {
+    // a ball of `radius`
+    Sphere,
+    // a one-sided rectangle of `size` facing its `target`
+    Rect,
+}
+
+impl FromStr for LightShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LightShape, String> {
+        match s {
+            "sphere" => Ok(LightShape::Sphere),
+            "rect" => Ok(LightShape::Rect),
+            _ => Err(format!("unknown light shape '{}'", s)),
+        }
+    }
+}
+
+impl LightShape {
+    // the name `from_str` reads
+    pub fn name(self) -> &'static str {
+        match self {
+            LightShape::Sphere => "sphere",
+            LightShape::Rect => "rect",
+        }
+    }
+}
+
+// How direct shading dims the light with distance. The path tracer and the volume always
+// divide its intensity by the squared distance.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Falloff {
+    // full strength everywhere, whatever the intensity
+    None,
+    // the intensity over the squared distance, no closer than the light's radius
+    InverseSquare,
+}
+
+impl FromStr for Falloff {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Falloff, String> {
+        match s {
+            "none" => Ok(Falloff::None),
+            "inverse-square" => Ok(Falloff::InverseSquare),
+            _ => Err(format!("unknown light falloff '{}'", s)),
+        }
+    }
+}
+
+// Area light source, a sphere or a rectangle. `intensity` is the radiant intensity seen from
+// afar, along the normal of the rectangle, the emitted radiance is normalized by the projected
+// area so resizing the light only changes the penumbra, not the brightness. Aimed at `target`
+// with a `spot_angle` below 180 degrees it is a spot light, fading out over the outer
+// `spot_blend` of its cone.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AreaLight {
+    pub shape: LightShape,
+    pub position: Vec3d,
+    pub radius: f64,
+    // width and height of the rectangle
+    pub size: Vec2d,
+    pub color: Color,
+    pub intensity: f64,
+    pub falloff: Falloff,
+    pub target: Vec3d,
+    // half the opening of the cone in degrees
+    pub spot_angle: f64,
+    pub spot_blend: f64,
+    // whether surfaces cast shadows from it, and the objects it lights by their index in the
+    // scene, all of them without
+    pub shadows: bool,
+    pub objects: Option<Vec<usize>>,
+}
+
+pub struct LightSample {
+    pub dir: Vec3d,
+    pub distance: f64,
+    pub pdf: f64,
+}
+
+impl AreaLight {
+    // whether the light is linked to the object at `index`
+    pub fn lights(&self, index: usize) -> bool {
+        self.objects
+            .as_ref()
+            .is_none_or(|objects| objects.contains(&index))
+    }
+
+    pub fn radiance(&self) -> Color {
+        let area = match self.shape {
+            LightShape::Sphere => PI * self.radius * self.radius,
+            LightShape::Rect => self.size.x * self.size.y,
+        };
+        self.color * (self.intensity / area)
+    }
+
+    // the normal of the rectangle, towards the target
+    fn facing(&self) -> Vec3d {
+        (self.target - self.position).normalized()
+    }
+
+    // Fraction of the light emitted towards `p` by the spot's angular profile, 1 all around
+    // for omnidirectional lights.
+    pub fn profile(&self, p: Vec3d) -> f64 {
+        if self.spot_angle >= 180. {
+            return 1.;
+        }
+        let axis = (self.target - self.position).normalized();
+        let cos = axis.dot((p - self.position).normalized());
+        let outer = self.spot_angle.to_radians();
+        let inner = outer * (1. - self.spot_blend);
+        smoothstep(outer.cos(), inner.cos(), cos)
+    }
+
+    // the light direct shading receives at `p` from straight above, before shadows
+    pub fn illumination(&self, p: Vec3d) -> Color {
+        let strength = match self.falloff {
+            Falloff::None => 1.,
+            Falloff::InverseSquare => {
+                let distance_squared = (self.position - p).length_squared();
+                self.intensity / distance_squared.max(self.radius * self.radius)
+            }
+        };
+        // a rectangle shines like a Lambertian emitter, to its front only
+        let emitter = match self.shape {
+            LightShape::Sphere => 1.,
+            LightShape::Rect => self.facing().dot((p - self.position).normalized()).max(0.),
+        };
+        self.color * (strength * emitter * self.profile(p))
+    }
+
+    pub fn intersect(&self, orig: Vec3d, dir: Vec3d) -> Option<f64> {
+        if self.shape == LightShape::Rect {
+            return self.intersect_rect(orig, dir);
+        }
+        let oc = orig - self.position;
+        let b = oc.dot(dir);
+        let c = oc.dot(oc) - self.radius * self.radius;
+        let disc = b * b - c;
+        if disc < 0. {
+            return None;
+        }
+        let t = -b - disc.sqrt();
+        if t > 0. {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    // hits of the front of the rectangle
+    fn intersect_rect(&self, orig: Vec3d, dir: Vec3d) -> Option<f64> {
+        let normal = self.facing();
+        let denom = dir.dot(normal);
+        if denom >= 0. {
+            return None;
+        }
+        let t = (self.position - orig).dot(normal) / denom;
+        let q = orig + dir * t - self.position;
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        let inside =
+            q.dot(tangent).abs() <= self.size.x / 2. && q.dot(bitangent).abs() <= self.size.y / 2.;
+        (t > 0. && inside).then_some(t)
+    }
+
+    fn cos_theta_max(&self, p: Vec3d) -> f64 {
+        let dist2 = (self.position - p).length_squared();
+        (1. - self.radius * self.radius / dist2).max(0.).sqrt()
+    }
+
+    // Solid angle pdf of `sample` drawing `dir` from `p`, zero when `p` is inside the sphere or
+    // behind the rectangle. The sphere's cone is sampled uniformly and the rectangle by its
+    // area.
+    pub fn pdf(&self, p: Vec3d, dir: Vec3d) -> f64 {
+        if self.shape == LightShape::Rect {
+            return match self.intersect_rect(p, dir) {
+                Some(t) => self.area_pdf(t, -dir.dot(self.facing())),
+                None => 0.,
+            };
+        }
+        let cos_max = self.cos_theta_max(p);
+        if cos_max <= 0. {
+            return 0.;
+        }
+        cone_pdf(cos_max)
+    }
+
+    // the solid angle pdf of a point drawn uniformly on the rectangle, `distance` away and seen
+    // `cos_light` off its normal
+    fn area_pdf(&self, distance: f64, cos_light: f64) -> f64 {
+        if cos_light <= 0. {
+            return 0.;
+        }
+        distance * distance / (self.size.x * self.size.y * cos_light)
+    }
+
+    // Samples the light as seen from `p`: the sphere uniformly over the cone of directions it
+    // subtends, the rectangle uniformly over its area.
+    pub fn sample(&self, p: Vec3d, u1: f64, u2: f64) -> LightSample {
+        if self.shape == LightShape::Rect {
+            let normal = self.facing();
+            let (tangent, bitangent) = normal.orthonormal_basis();
+            let q = self.position
+                + tangent * ((u1 - 0.5) * self.size.x)
+                + bitangent * ((u2 - 0.5) * self.size.y);
+            let to_light = q - p;
+            let distance = to_light.length();
+            let dir = to_light / distance;
+            return LightSample {
+                dir,
+                distance,
+                pdf: self.area_pdf(distance, -dir.dot(normal)),
+            };
+        }
+        let to_light = self.position - p;
+        let center_distance = to_light.length();
+        let dir = around(
+            to_light / center_distance,
+            sample_cone(self.cos_theta_max(p), u1, u2),
+        );
+        let distance = self
+            .intersect(p, dir)
+            .unwrap_or(center_distance - self.radius);
+        LightSample {
+            dir,
+            distance,
+            pdf: self.pdf(p, dir),
+        }
+    }
+}
+
+impl Default for AreaLight {
+    fn default() -> AreaLight {
+        AreaLight {
+            shape: LightShape::Sphere,
+            position: Vec3d::new(10., 10., 10.),
+            radius: 0.5,
+            size: Vec2d::new(1., 1.),
+            color: Color::WHITE,
+            intensity: 500.,
+            falloff: Falloff::None,
+            target: Vec3d::zero(),
+            spot_angle: 180.,
+            spot_blend: 0.2,
+            shadows: true,
+            objects: None,
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Instant;
+
+// How much the renderer tells on stderr. Errors are always reported by the binary.
+#[derive(Copy, Clone, PartialEq, PartialOrd)]
+pub enum Level {
+    // nothing but errors
+    Quiet,
+    // progress of batch, sprite and progressive renders
+    Info,
+    // timings of every stage and per-tile events, for diagnosing long renders
+    Debug,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    LEVEL.load(Ordering::Relaxed) >= level as u8
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Info) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Debug) {
+            eprintln!("[{:>9.3}] {}", $crate::log::uptime(), format_args!($($arg)*));
+        }
+    };
+}
+
+// seconds since the first log message, the timestamp of debug events
+pub fn uptime() -> f64 {
+    static START: std::sync::OnceLock<Instant> = std::sync::OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_secs_f64()
+}
+
+// A named stage of the work, its duration is logged at the debug level when it ends.
+pub struct Span {
+    name: &'static str,
+    start: Instant,
+}
+
+pub fn span(name: &'static str) -> Span {
+    debug!("{} started", name);
+    Span {
+        name,
+        start: Instant::now(),
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        debug!(
+            "{} finished in {:.3} s",
+            self.name,
+            self.start.elapsed().as_secs_f64()
+        );
+    }
+}
@@ -1,149 +1,65 @@
-mod vec3d;
-use vec3d::Vec3d;
-
-const SPHERE_RADIUS: f64 = 1.5;
-const NOISE_AMPLITUDE: f64 = 1.;
-
-fn palette_fire(d: f64) -> Vec3d {
-    let yellow = Vec3d::new(1.7, 1.3, 1.0); // note that the color is "hot", i.e. has components >1
-    let orange = Vec3d::new(1.0, 0.6, 0.0);
-    let red = Vec3d::new(1.0, 0.0, 0.0);
-    let darkgray = Vec3d::new(0.2, 0.2, 0.2);
-    let gray = Vec3d::new(0.4, 0.4, 0.4);
-
-    let x = d.max(0.).min(1.);
-    if x < 0.25 {
-        return vec3d::lerp(gray, darkgray, x * 4.);
-    } else if x < 0.5 {
-        return vec3d::lerp(darkgray, red, x * 4. - 1.);
-    } else if x < 0.75 {
-        return vec3d::lerp(red, orange, x * 4. - 2.);
-    }
-
-    vec3d::lerp(orange, yellow, x * 4. - 3.)
-}
-
-fn lerp(v0: f64, v1: f64, d: f64) -> f64 {
-    v0 + (v1 - v0) * d.max(0.).min(1.)
-}
-
-fn hash(n: f64) -> f64 {
-    let x = n.sin() * 43758.5453;
-    x - x.floor()
-}
-
-fn noise(x: Vec3d) -> f64 {
-    let p = Vec3d::new(x.x.floor(), x.y.floor(), x.z.floor());
-    let mut f = Vec3d::new(x.x - p.x, x.y - p.y, x.z - p.z);
-    f = f * (f * (Vec3d::new(3., 3., 3.) - f * 2.));
-    let n = p * Vec3d::new(1., 57., 113.);
-    lerp(
-        lerp(
-            lerp(hash(n + 0.), hash(n + 1.), f.x),
-            lerp(hash(n + 57.), hash(n + 58.), f.x),
-            f.y,
-        ),
-        lerp(
-            lerp(hash(n + 113.), hash(n + 114.), f.x),
-            lerp(hash(n + 170.), hash(n + 171.), f.x),
-            f.y,
-        ),
-        f.z,
-    )
-}
-
-fn rotate(v: Vec3d) -> Vec3d {
-    Vec3d::new(
-        Vec3d::new(0., 0.8, 0.6) * v,
-        Vec3d::new(-0.80, 0.36, -0.48) * v,
-        Vec3d::new(-0.60, -0.48, 0.64) * v,
-    )
-}
-
-fn fractal_brownian_motion(x: Vec3d) -> f64 {
-    let mut p = rotate(x);
-    let mut f = 0.;
-    f += 0.5000 * noise(p);
-    p = p * 2.32;
-    f += 0.2500 * noise(p);
-    p = p * 3.03;
-    f += 0.1250 * noise(p);
-    p = p * 2.61;
-    f += 0.0625 * noise(p);
-    f / 0.9375
-}
-
-fn signed_distance(p: Vec3d) -> f64 {
-    let displacement = -fractal_brownian_motion(p * 3.4) * NOISE_AMPLITUDE;
-    p.length() - (SPHERE_RADIUS + displacement)
-}
-
-fn sphere_trace(orig: Vec3d, dir: Vec3d, pos: &mut Vec3d) -> bool {
-    if orig * orig - (orig * dir).powi(2) > SPHERE_RADIUS.powi(2) {
-        return false;
-    } // early discard
-
-    *pos = orig;
-    for _i in 0..128 {
-        let d = signed_distance(*pos);
-        if d < 0. {
-            return true;
+use rustaboom::options::Options;
+use rustaboom::{
+    batch, bench, check, distributed, dump, log, output, plugin, progressive, render_image, server,
+    sprite, Error,
+};
+
+// Loads the libraries of the `--plugin` arguments and returns the others. Plugins run native
+// code, so only the command line loads them, never the settings of scene files, the
+// environment, posted scenes or chunks.
+fn load_plugins(args: Vec<String>) -> Result<Vec<String>, Error> {
+    let mut rest = Vec::with_capacity(args.len());
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg != "--plugin" {
+            rest.push(arg);
+            continue;
         }
-        *pos += dir * (d * 0.1).max(0.01);
+        let path = args
+            .next()
+            .filter(|path| !path.starts_with("--"))
+            .ok_or("--plugin needs the path of a library")?;
+        plugin::load(&path)?;
     }
-    false
+    Ok(rest)
 }
 
-fn distance_field_normal(pos: Vec3d) -> Vec3d {
-    let eps = 0.1;
-    let d = signed_distance(pos);
-    let nx = signed_distance(pos + Vec3d::new(eps, 0., 0.)) - d;
-    let ny = signed_distance(pos + Vec3d::new(0., eps, 0.)) - d;
-    let nz = signed_distance(pos + Vec3d::new(0., 0., eps)) - d;
-    Vec3d::new(nx, ny, nz).normalized()
+// runs the mode picked by the options
+fn run(args: &[String]) -> Result<(), Error> {
+    let options = Options::load(args.iter().cloned())?;
+    log::set_level(options.log_level());
+    if let Some(format) = options.dump_scene {
+        return dump::run(&options, format);
+    }
+    if options.check {
+        return check::run(&options);
+    }
+    if options.bench {
+        return bench::run(&options);
+    }
+    if options.worker.is_some() {
+        return distributed::serve(&options);
+    }
+    if options.serve.is_some() {
+        return server::run(&options);
+    }
+    if options.progressive {
+        return progressive::run(args, options);
+    }
+    if options.sprite_frames > 0 {
+        return sprite::run(&options);
+    }
+    if !options.sweeps.is_empty() {
+        return batch::run(args, &options);
+    }
+    let (image, stats) = render_image(&options);
+    output(&options, &options.output, &image, stats)
 }
 
 fn main() {
-    const WIDTH: usize = 640;
-    const HEIGHT: usize = 480;
-    let fov = std::f64::consts::PI / 3.;
-    let framebuffer = &mut vec![Vec3d::new(0., 0., 0.); WIDTH * HEIGHT];
-
-    let w = WIDTH as f64;
-    let h = HEIGHT as f64;
-
-    for j in 0..HEIGHT {
-        for i in 0..WIDTH {
-            let id = i as f64;
-            let jd = j as f64;
-
-            let dir_x: f64 = (id + 0.5) - w / 2.;
-            let dir_y: f64 = -(jd + 0.5) + h / 2.;
-            let dir_z: f64 = -h / (2. * (fov / 2.).tan());
-            let mut hit = Vec3d::new(0., 0., 0.);
-            if sphere_trace(
-                [0., 0., 3.].into(),
-                Vec3d::new(dir_x, dir_y, dir_z).normalized(),
-                &mut hit,
-            ) {
-                let noise_level = (SPHERE_RADIUS - hit.length()) / NOISE_AMPLITUDE;
-                let light_dir = (Vec3d::new(10., 10., 10.) - hit).normalized();
-                let light_intensity = (light_dir * distance_field_normal(hit)).max(0.4);
-                framebuffer[i + j * WIDTH] =
-                    palette_fire((-0.2 + noise_level) * 2.) * light_intensity;
-            } else {
-                framebuffer[i + j * WIDTH] = Vec3d::new(0.2, 0.7, 0.8);
-            }
-        }
-        use std::io::prelude::Write;
-        let mut file = std::io::BufWriter::new(std::fs::File::create("out_r.ppm").unwrap());
-        file.write_all(format!("P6\n{} {}\n255\n", WIDTH, HEIGHT).as_bytes())
-            .unwrap();
-        for frame in framebuffer.iter() {
-            for j in 0..3 {
-                let pixel = ((255. * frame[j]) as i64).min(255).max(0) as u8;
-                file.write_all(&[pixel]).unwrap();
-            }
-        }
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = load_plugins(args).and_then(|args| run(&args)) {
+        eprintln!("error: {}", err);
+        std::process::exit(err.exit_code());
     }
 }
@@ -0,0 +1,350 @@
+use core::ops::{Mul, MulAssign};
+
+#[cfg(not(feature = "std"))]
+use crate::vec3d::Float;
+use crate::vec3d::{Vec3d, Vec4d};
+
+// 3x3 matrix of rows, multiplied with column vectors on the right
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Mat3 {
+    pub rows: [Vec3d; 3],
+}
+
+impl Mat3 {
+    pub const IDENTITY: Mat3 = Mat3 {
+        rows: [
+            Vec3d::new(1., 0., 0.),
+            Vec3d::new(0., 1., 0.),
+            Vec3d::new(0., 0., 1.),
+        ],
+    };
+
+    pub fn from_rows(r0: Vec3d, r1: Vec3d, r2: Vec3d) -> Mat3 {
+        Mat3 { rows: [r0, r1, r2] }
+    }
+
+    pub fn from_cols(c0: Vec3d, c1: Vec3d, c2: Vec3d) -> Mat3 {
+        Mat3::from_rows(c0, c1, c2).transpose()
+    }
+
+    pub fn col(&self, i: usize) -> Vec3d {
+        Vec3d::new(self.rows[0][i], self.rows[1][i], self.rows[2][i])
+    }
+
+    pub fn transpose(&self) -> Mat3 {
+        Mat3::from_rows(self.col(0), self.col(1), self.col(2))
+    }
+
+    pub fn determinant(&self) -> f64 {
+        let [r0, r1, r2] = self.rows;
+        r0.dot(r1.cross(r2))
+    }
+
+    // nothing for singular matrices
+    pub fn inverse(&self) -> Option<Mat3> {
+        let det = self.determinant();
+        if det == 0. || !det.is_finite() {
+            return None;
+        }
+        let [r0, r1, r2] = self.rows;
+        // the columns of the adjugate are the cross products of the rows
+        Some(Mat3::from_cols(r1.cross(r2), r2.cross(r0), r0.cross(r1)) * (1. / det))
+    }
+
+    pub fn scale(s: Vec3d) -> Mat3 {
+        Mat3::from_rows(
+            Vec3d::new(s.x, 0., 0.),
+            Vec3d::new(0., s.y, 0.),
+            Vec3d::new(0., 0., s.z),
+        )
+    }
+
+    // counterclockwise by `angle` radians looking down the axis towards the origin
+    pub fn rotation_x(angle: f64) -> Mat3 {
+        let (s, c) = angle.sin_cos();
+        Mat3::from_rows(
+            Vec3d::new(1., 0., 0.),
+            Vec3d::new(0., c, -s),
+            Vec3d::new(0., s, c),
+        )
+    }
+
+    pub fn rotation_y(angle: f64) -> Mat3 {
+        let (s, c) = angle.sin_cos();
+        Mat3::from_rows(
+            Vec3d::new(c, 0., s),
+            Vec3d::new(0., 1., 0.),
+            Vec3d::new(-s, 0., c),
+        )
+    }
+
+    pub fn rotation_z(angle: f64) -> Mat3 {
+        let (s, c) = angle.sin_cos();
+        Mat3::from_rows(
+            Vec3d::new(c, -s, 0.),
+            Vec3d::new(s, c, 0.),
+            Vec3d::new(0., 0., 1.),
+        )
+    }
+
+    // Rodrigues' rotation by `angle` radians about the unit `axis`
+    pub fn rotation(axis: Vec3d, angle: f64) -> Mat3 {
+        let (s, c) = angle.sin_cos();
+        let t = 1. - c;
+        let Vec3d { x, y, z } = axis;
+        Mat3::from_rows(
+            Vec3d::new(t * x * x + c, t * x * y - s * z, t * x * z + s * y),
+            Vec3d::new(t * x * y + s * z, t * y * y + c, t * y * z - s * x),
+            Vec3d::new(t * x * z - s * y, t * y * z + s * x, t * z * z + c),
+        )
+    }
+}
+
+impl Default for Mat3 {
+    fn default() -> Mat3 {
+        Mat3::IDENTITY
+    }
+}
+
+impl Mul<Vec3d> for Mat3 {
+    type Output = Vec3d;
+
+    fn mul(self, v: Vec3d) -> Vec3d {
+        Vec3d::new(
+            self.rows[0].dot(v),
+            self.rows[1].dot(v),
+            self.rows[2].dot(v),
+        )
+    }
+}
+
+impl Mul for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, other: Mat3) -> Mat3 {
+        let t = other.transpose();
+        Mat3::from_rows(t * self.rows[0], t * self.rows[1], t * self.rows[2])
+    }
+}
+
+impl Mul<f64> for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, s: f64) -> Mat3 {
+        Mat3::from_rows(self.rows[0] * s, self.rows[1] * s, self.rows[2] * s)
+    }
+}
+
+impl MulAssign for Mat3 {
+    fn mul_assign(&mut self, other: Mat3) {
+        *self = *self * other;
+    }
+}
+
+// 4x4 matrix of rows for affine and projective transforms of column vectors
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Mat4 {
+    pub rows: [Vec4d; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Mat4 {
+        Mat4::from(Mat3::IDENTITY)
+    }
+
+    pub fn from_rows(r0: Vec4d, r1: Vec4d, r2: Vec4d, r3: Vec4d) -> Mat4 {
+        Mat4 {
+            rows: [r0, r1, r2, r3],
+        }
+    }
+
+    pub fn from_cols(c0: Vec4d, c1: Vec4d, c2: Vec4d, c3: Vec4d) -> Mat4 {
+        Mat4::from_rows(c0, c1, c2, c3).transpose()
+    }
+
+    pub fn col(&self, i: usize) -> Vec4d {
+        Vec4d::new(
+            self.rows[0][i],
+            self.rows[1][i],
+            self.rows[2][i],
+            self.rows[3][i],
+        )
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        Mat4::from_rows(self.col(0), self.col(1), self.col(2), self.col(3))
+    }
+
+    // the upper-left 3x3 block, the linear part of an affine transform
+    pub fn linear(&self) -> Mat3 {
+        Mat3::from_rows(self.rows[0].xyz(), self.rows[1].xyz(), self.rows[2].xyz())
+    }
+
+    pub fn translation(t: Vec3d) -> Mat4 {
+        let mut m = Mat4::identity();
+        m.rows[0].w = t.x;
+        m.rows[1].w = t.y;
+        m.rows[2].w = t.z;
+        m
+    }
+
+    // View transform of a camera at `eye` looking at `target`, right-handed: the camera
+    // looks down -z with y up, like the renderer's camera.
+    pub fn look_at(eye: Vec3d, target: Vec3d, up: Vec3d) -> Mat4 {
+        let back = (eye - target).normalized();
+        let right = up.cross(back).normalized();
+        let up = back.cross(right);
+        Mat4::from_rows(
+            right.extend(-right.dot(eye)),
+            up.extend(-up.dot(eye)),
+            back.extend(-back.dot(eye)),
+            Vec4d::new(0., 0., 0., 1.),
+        )
+    }
+
+    // the point `p` transformed, divided by w for projections
+    pub fn transform_point(&self, p: Vec3d) -> Vec3d {
+        (*self * p.extend(1.)).project()
+    }
+
+    // the direction `v` transformed, without the translation
+    pub fn transform_vector(&self, v: Vec3d) -> Vec3d {
+        (*self * v.extend(0.)).xyz()
+    }
+
+    pub fn determinant(&self) -> f64 {
+        let (s, c) = self.minors();
+        s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+    }
+
+    // nothing for singular matrices
+    pub fn inverse(&self) -> Option<Mat4> {
+        let det = self.determinant();
+        if det == 0. || !det.is_finite() {
+            return None;
+        }
+        let (s, c) = self.minors();
+        let [a0, a1, a2, a3] = self.rows[0].as_slice();
+        let [b0, b1, b2, b3] = self.rows[1].as_slice();
+        let [c0, c1, c2, c3] = self.rows[2].as_slice();
+        let [d0, d1, d2, d3] = self.rows[3].as_slice();
+        let adjugate = Mat4::from_rows(
+            Vec4d::new(
+                b1 * c[5] - b2 * c[4] + b3 * c[3],
+                -a1 * c[5] + a2 * c[4] - a3 * c[3],
+                d1 * s[5] - d2 * s[4] + d3 * s[3],
+                -c1 * s[5] + c2 * s[4] - c3 * s[3],
+            ),
+            Vec4d::new(
+                -b0 * c[5] + b2 * c[2] - b3 * c[1],
+                a0 * c[5] - a2 * c[2] + a3 * c[1],
+                -d0 * s[5] + d2 * s[2] - d3 * s[1],
+                c0 * s[5] - c2 * s[2] + c3 * s[1],
+            ),
+            Vec4d::new(
+                b0 * c[4] - b1 * c[2] + b3 * c[0],
+                -a0 * c[4] + a1 * c[2] - a3 * c[0],
+                d0 * s[4] - d1 * s[2] + d3 * s[0],
+                -c0 * s[4] + c1 * s[2] - c3 * s[0],
+            ),
+            Vec4d::new(
+                -b0 * c[3] + b1 * c[1] - b2 * c[0],
+                a0 * c[3] - a1 * c[1] + a2 * c[0],
+                -d0 * s[3] + d1 * s[1] - d2 * s[0],
+                c0 * s[3] - c1 * s[1] + c2 * s[0],
+            ),
+        );
+        Some(adjugate * (1. / det))
+    }
+
+    // 2x2 determinants of the top two rows (`s`) and of the bottom two (`c`), from which
+    // the cofactors are built (Laplace expansion by complementary minors)
+    fn minors(&self) -> ([f64; 6], [f64; 6]) {
+        let [a0, a1, a2, a3] = self.rows[0].as_slice();
+        let [b0, b1, b2, b3] = self.rows[1].as_slice();
+        let [c0, c1, c2, c3] = self.rows[2].as_slice();
+        let [d0, d1, d2, d3] = self.rows[3].as_slice();
+        (
+            [
+                a0 * b1 - b0 * a1,
+                a0 * b2 - b0 * a2,
+                a0 * b3 - b0 * a3,
+                a1 * b2 - b1 * a2,
+                a1 * b3 - b1 * a3,
+                a2 * b3 - b2 * a3,
+            ],
+            [
+                c0 * d1 - d0 * c1,
+                c0 * d2 - d0 * c2,
+                c0 * d3 - d0 * c3,
+                c1 * d2 - d1 * c2,
+                c1 * d3 - d1 * c3,
+                c2 * d3 - d2 * c3,
+            ],
+        )
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Mat4 {
+        Mat4::identity()
+    }
+}
+
+// the linear transform `m` without translation
+impl From<Mat3> for Mat4 {
+    fn from(m: Mat3) -> Mat4 {
+        Mat4::from_rows(
+            m.rows[0].extend(0.),
+            m.rows[1].extend(0.),
+            m.rows[2].extend(0.),
+            Vec4d::new(0., 0., 0., 1.),
+        )
+    }
+}
+
+impl Mul<Vec4d> for Mat4 {
+    type Output = Vec4d;
+
+    fn mul(self, v: Vec4d) -> Vec4d {
+        Vec4d::new(
+            self.rows[0].dot(v),
+            self.rows[1].dot(v),
+            self.rows[2].dot(v),
+            self.rows[3].dot(v),
+        )
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let t = other.transpose();
+        Mat4::from_rows(
+            t * self.rows[0],
+            t * self.rows[1],
+            t * self.rows[2],
+            t * self.rows[3],
+        )
+    }
+}
+
+impl Mul<f64> for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, s: f64) -> Mat4 {
+        Mat4::from_rows(
+            self.rows[0] * s,
+            self.rows[1] * s,
+            self.rows[2] * s,
+            self.rows[3] * s,
+        )
+    }
+}
+
+impl MulAssign for Mat4 {
+    fn mul_assign(&mut self, other: Mat4) {
+        *self = *self * other;
+    }
+}
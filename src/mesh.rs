@@ -0,0 +1,237 @@
+use crate::error::Error;
+use crate::vec3d::Vec3d;
+
+// distance, in grid cells, up to which the baked field is exact
+const BAND: f64 = 3.;
+
+pub struct Mesh {
+    pub vertices: Vec<Vec3d>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+// Wavefront OBJ subset: `v` positions and `f` faces (polygons are fanned into
+// triangles, texture and normal indices are ignored), everything else is skipped.
+pub fn parse_obj(text: &str) -> Result<Mesh, Error> {
+    let mut vertices = Vec::new();
+    let mut triangles = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        let error = |what: &str| format!("line {}: invalid {}", n + 1, what);
+        match tokens.next() {
+            Some("v") => {
+                let mut coords = [0.; 3];
+                for c in coords.iter_mut() {
+                    *c = tokens
+                        .next()
+                        .and_then(|t| t.parse().ok())
+                        .ok_or_else(|| error("vertex"))?;
+                }
+                vertices.push(Vec3d::from(coords));
+            }
+            Some("f") => {
+                let indices = tokens
+                    .map(|t| {
+                        let index: isize = t
+                            .split('/')
+                            .next()
+                            .and_then(|i| i.parse().ok())
+                            .ok_or_else(|| error("face"))?;
+                        // OBJ indices are 1-based, negative ones count back from the last vertex
+                        let index = if index < 0 {
+                            vertices.len() as isize + index
+                        } else {
+                            index - 1
+                        };
+                        if index < 0 || index as usize >= vertices.len() {
+                            return Err(error("face index"));
+                        }
+                        Ok(index as usize)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if indices.len() < 3 {
+                    return Err(error("face").into());
+                }
+                for i in 1..indices.len() - 1 {
+                    triangles.push([indices[0], indices[i], indices[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+    if triangles.is_empty() {
+        return Err("mesh has no faces".into());
+    }
+    Ok(Mesh {
+        vertices,
+        triangles,
+    })
+}
+
+pub fn load_obj(path: &str) -> Result<Mesh, Error> {
+    let text = std::fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+    parse_obj(&text).map_err(|err| err.in_file(path))
+}
+
+// closest point on the triangle to `p`, from Ericson's "Real-Time Collision Detection"
+fn closest_point_on_triangle(p: Vec3d, a: Vec3d, b: Vec3d, c: Vec3d) -> Vec3d {
+    let (ab, ac, ap) = (b - a, c - a, p - a);
+    let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+    if d1 <= 0. && d2 <= 0. {
+        return a;
+    }
+    let bp = p - b;
+    let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+    if d3 >= 0. && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0. && d1 >= 0. && d3 <= 0. {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = p - c;
+    let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+    if d6 >= 0. && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0. && d2 >= 0. && d6 <= 0. {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0. && d4 - d3 >= 0. && d5 - d6 >= 0. {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denom = 1. / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+// Signed distance of a mesh baked into a regular grid around the unit sphere the mesh
+// is normalized into. Distances are exact in a narrow band around the triangles and
+// clamped to the band width elsewhere, which keeps the field a valid bound to march.
+pub struct MeshSdf {
+    resolution: usize,
+    extent: f64,
+    cell: f64,
+    values: Vec<f64>,
+}
+
+impl MeshSdf {
+    pub fn bake(mesh: &Mesh, resolution: usize) -> MeshSdf {
+        let n = resolution.max(4);
+        // fit the mesh's bounding box into the unit sphere
+        let (mut lo, mut hi) = (mesh.vertices[0], mesh.vertices[0]);
+        for v in mesh.vertices.iter() {
+            lo = lo.min(*v);
+            hi = hi.max(*v);
+        }
+        let center = (lo + hi) / 2.;
+        let scale = ((hi - lo).length() / 2.).max(1e-9);
+        let vertices: Vec<Vec3d> = mesh
+            .vertices
+            .iter()
+            .map(|v| (*v - center) / scale)
+            .collect();
+
+        // the grid has a band's worth of padding around the unit cube
+        let extent = 1. / (1. - 2. * BAND / (n - 1) as f64).max(0.5);
+        let cell = 2. * extent / (n - 1) as f64;
+        let band = BAND * cell;
+        let node = |i: usize| -extent + i as f64 * cell;
+        let index = |i: usize, j: usize, k: usize| i + (j + k * n) * n;
+
+        let mut values = vec![band; n * n * n];
+        for t in mesh.triangles.iter() {
+            let (a, b, c) = (vertices[t[0]], vertices[t[1]], vertices[t[2]]);
+            let (tlo, thi) = (a.min(b).min(c) - band, a.max(b).max(c) + band);
+            let range = |lo: f64, hi: f64| {
+                let first = ((lo + extent) / cell).floor().max(0.) as usize;
+                let last = (((hi + extent) / cell).ceil() as usize).min(n - 1);
+                first..=last
+            };
+            for k in range(tlo.z, thi.z) {
+                for j in range(tlo.y, thi.y) {
+                    for i in range(tlo.x, thi.x) {
+                        let p = Vec3d::new(node(i), node(j), node(k));
+                        let d = (p - closest_point_on_triangle(p, a, b, c)).length();
+                        let value = &mut values[index(i, j, k)];
+                        *value = value.min(d);
+                    }
+                }
+            }
+        }
+
+        // inside/outside by the parity of crossings along +x through each grid row, the
+        // row is nudged off the lattice so it doesn't run exactly through mesh edges
+        let mut crossings = Vec::new();
+        for k in 0..n {
+            for j in 0..n {
+                let (y, z) = (node(j) + cell * 1.3e-4, node(k) + cell * 0.7e-4);
+                crossings.clear();
+                for t in mesh.triangles.iter() {
+                    let (a, b, c) = (vertices[t[0]], vertices[t[1]], vertices[t[2]]);
+                    // barycentric coordinates of (y, z) in the triangle's yz projection
+                    let det = (b.y - a.y) * (c.z - a.z) - (c.y - a.y) * (b.z - a.z);
+                    if det.abs() < 1e-12 {
+                        continue;
+                    }
+                    let u = ((y - a.y) * (c.z - a.z) - (c.y - a.y) * (z - a.z)) / det;
+                    let v = ((b.y - a.y) * (z - a.z) - (y - a.y) * (b.z - a.z)) / det;
+                    if u >= 0. && v >= 0. && u + v <= 1. {
+                        crossings.push(a.x + (b.x - a.x) * u + (c.x - a.x) * v);
+                    }
+                }
+                crossings.sort_by(f64::total_cmp);
+                let mut passed = 0;
+                for i in 0..n {
+                    let x = node(i);
+                    while passed < crossings.len() && crossings[passed] < x {
+                        passed += 1;
+                    }
+                    if passed % 2 == 1 {
+                        values[index(i, j, k)] *= -1.;
+                    }
+                }
+            }
+        }
+
+        MeshSdf {
+            resolution: n,
+            extent,
+            cell,
+            values,
+        }
+    }
+
+    // grid spacing in the normalized space of the mesh
+    pub fn cell(&self) -> f64 {
+        self.cell
+    }
+
+    // memory the grid takes
+    pub fn bytes(&self) -> usize {
+        self.values.len() * std::mem::size_of::<f64>()
+    }
+
+    // trilinear interpolation of the grid, points off the grid add their distance to it
+    pub fn distance(&self, p: Vec3d) -> f64 {
+        let n = self.resolution;
+        let max = (n - 1) as f64;
+        let g = (p + self.extent) / self.cell;
+        let clamped = g.clamp(0., max - 1e-9);
+        let outside = (g - clamped).length() * self.cell;
+        let (i, j, k) = (clamped.x as usize, clamped.y as usize, clamped.z as usize);
+        let (fx, fy, fz) = (
+            clamped.x - i as f64,
+            clamped.y - j as f64,
+            clamped.z - k as f64,
+        );
+        let at =
+            |di: usize, dj: usize, dk: usize| self.values[(i + di) + ((j + dj) + (k + dk) * n) * n];
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let x00 = lerp(at(0, 0, 0), at(1, 0, 0), fx);
+        let x10 = lerp(at(0, 1, 0), at(1, 1, 0), fx);
+        let x01 = lerp(at(0, 0, 1), at(1, 0, 1), fx);
+        let x11 = lerp(at(0, 1, 1), at(1, 1, 1), fx);
+        lerp(lerp(x00, x10, fy), lerp(x01, x11, fy), fz) + outside
+    }
+}
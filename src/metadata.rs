@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::image::Image;
+use crate::options::Options;
+
+// What it took to produce an image.
+#[derive(Copy, Clone, Default)]
+pub struct Stats {
+    pub samples: usize,
+    pub render: Duration,
+    pub encode: Duration,
+}
+
+// `out.png` -> `out.json`
+pub fn sidecar_path(path: &str) -> String {
+    let stem = match path.rfind('.') {
+        Some(dot) if !path[dot..].contains('/') => &path[..dot],
+        _ => path,
+    };
+    format!("{}.json", stem)
+}
+
+// `s` as a JSON string
+pub fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// FNV-1a over every setting, two renders with the same hash saw the same options
+pub fn settings_hash(options: &Options) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for (key, value) in &options.settings {
+        for byte in key.bytes().chain([0]).chain(value.bytes()).chain([0]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+// a single shell word, quoted if it has to be
+fn shell_word(s: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_.,/:=+@%".contains(c);
+    if !s.is_empty() && s.chars().all(plain) {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+// the settings as arguments that reproduce the options
+pub fn command_line(options: &Options) -> String {
+    let mut words = Vec::with_capacity(2 * options.settings.len());
+    for (key, value) in &options.settings {
+        words.push(format!("--{}", key));
+        // switches and `object` markers go without a value
+        if !value.is_empty() {
+            words.push(shell_word(value));
+        }
+    }
+    words.join(" ")
+}
+
+// Key/value pairs embedded in the outputs that can carry text, enough to render the image
+// again without its sidecar.
+pub fn text(options: &Options) -> Vec<(String, String)> {
+    vec![
+        (
+            "Software".to_string(),
+            format!("rustaboom {}", env!("CARGO_PKG_VERSION")),
+        ),
+        ("Seed".to_string(), options.seed.to_string()),
+        (
+            "Scene hash".to_string(),
+            format!("{:016x}", settings_hash(options)),
+        ),
+        ("Settings".to_string(), command_line(options)),
+    ]
+}
+
+// Everything needed to reproduce and audit the render written to `output`. `settings`
+// lists the options in the order they were applied, with the scene files expanded, so
+// replaying them as `--key value` arguments gives the same image.
+pub fn json(options: &Options, output: &str, image: &Image, stats: &Stats) -> String {
+    let camera = crate::default_camera(options);
+    let settings: Vec<String> = options
+        .settings
+        .iter()
+        .map(|(key, value)| format!("    [{}, {}]", quote(key), quote(value)))
+        .collect();
+    let scene_files: Vec<String> = options.scene_files.iter().map(|f| quote(f)).collect();
+    let pixels = (image.width() * image.height()).max(1);
+    format!(
+        "{{
+  \"version\": {},
+  \"output\": {},
+  \"width\": {},
+  \"height\": {},
+  \"seed\": {},
+  \"time\": {},
+  \"camera\": {{ \"position\": [{}, {}, {}], \"fov\": {} }},
+  \"scene_files\": [{}],
+  \"scene_hash\": \"{:016x}\",
+  \"samples\": {},
+  \"samples_per_pixel\": {},
+  \"timings\": {{ \"render\": {}, \"encode\": {} }},
+  \"settings\": [
+{}
+  ]
+}}
+",
+        quote(env!("CARGO_PKG_VERSION")),
+        quote(output),
+        image.width(),
+        image.height(),
+        options.seed,
+        options.time,
+        camera.position.x,
+        camera.position.y,
+        camera.position.z,
+        camera.fov,
+        scene_files.join(", "),
+        settings_hash(options),
+        stats.samples,
+        stats.samples as f64 / pixels as f64,
+        stats.render.as_secs_f64(),
+        stats.encode.as_secs_f64(),
+        settings.join(",\n"),
+    )
+}
+
+// writes the sidecar of the image at `output`
+pub fn write(output: &str, options: &Options, image: &Image, stats: &Stats) -> Result<(), Error> {
+    let path = sidecar_path(output);
+    std::fs::write(&path, json(options, output, image, stats)).map_err(|err| Error::io(&path, err))
+}
@@ -0,0 +1,327 @@
+use core::str::FromStr;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::matrix::Mat3;
+#[cfg(not(feature = "std"))]
+use crate::vec3d::Float;
+use crate::vec3d::Vec3d;
+
+// How the displacement noise gets the values at the lattice points.
+#[derive(Copy, Clone, PartialEq)]
+pub enum NoiseMode {
+    // hashed for every lookup
+    Exact,
+    // read from a baked `NoiseTable`
+    Cached,
+}
+
+impl FromStr for NoiseMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<NoiseMode, String> {
+        match s {
+            "exact" => Ok(NoiseMode::Exact),
+            "cached" => Ok(NoiseMode::Cached),
+            _ => Err(format!("unknown noise mode '{}'", s)),
+        }
+    }
+}
+
+pub fn lerp(v0: f64, v1: f64, d: f64) -> f64 {
+    v0 + (v1 - v0) * d.clamp(0., 1.)
+}
+
+pub fn hash(n: f64) -> f64 {
+    let x = n.sin() * 43758.5453;
+    x - x.floor()
+}
+
+// The lattice values of `noise` baked into a table of `size`³ entries that wraps around, so
+// a lookup reads eight of them instead of hashing eight. The noise repeats every `size`
+// units, and its values differ from the exact noise, with the same look.
+pub struct NoiseTable {
+    size: usize,
+    values: Vec<f32>,
+}
+
+impl NoiseTable {
+    // `size` is rounded up to a power of two, to wrap by masking
+    pub fn new(size: usize) -> NoiseTable {
+        let size = size.max(2).next_power_of_two();
+        let mut values = Vec::with_capacity(size * size * size);
+        for k in 0..size {
+            for j in 0..size {
+                for i in 0..size {
+                    values.push(hash(i as f64 + 57. * j as f64 + 113. * k as f64) as f32);
+                }
+            }
+        }
+        NoiseTable { size, values }
+    }
+
+    // memory the table takes
+    pub fn bytes(&self) -> usize {
+        self.values.len() * core::mem::size_of::<f32>()
+    }
+
+    fn at(&self, i: i64, j: i64, k: i64) -> f64 {
+        let mask = self.size as i64 - 1;
+        let (i, j, k) = (
+            (i & mask) as usize,
+            (j & mask) as usize,
+            (k & mask) as usize,
+        );
+        f64::from(self.values[i + self.size * (j + self.size * k)])
+    }
+
+    // `smooth_noise` with the lattice values of the table
+    pub fn noise(&self, x: Vec3d) -> f64 {
+        let p = x.floor();
+        let (i, j, k) = (p.x as i64, p.y as i64, p.z as i64);
+        let v = |di, dj, dk| self.at(i + di, j + dj, k + dk);
+        let values = [
+            v(0, 0, 0),
+            v(1, 0, 0),
+            v(0, 1, 0),
+            v(1, 1, 0),
+            v(0, 0, 1),
+            v(1, 0, 1),
+            v(0, 1, 1),
+            v(1, 1, 1),
+        ];
+        lattice_noise(values, smooth_fade(x.fract()))
+    }
+}
+
+// smoothstep weights of the lattice interpolation
+fn fade(f: Vec3d) -> Vec3d {
+    f * f.dot(Vec3d::new(3., 3., 3.) - f * 2.)
+}
+
+pub fn noise(x: Vec3d) -> f64 {
+    let n = x.floor().dot(Vec3d::new(1., 57., 113.));
+    lattice_noise(CORNERS.map(|corner| hash(n + corner)), fade(x.fract()))
+}
+
+// smoothstep weights along each axis apart
+fn smooth_fade(f: Vec3d) -> Vec3d {
+    f.map(|t| t * t * (3. - 2. * t))
+}
+
+// `noise` with the smoothstep along each axis apart, which keeps it and its gradient
+// continuous across the lattice faces, as the derivatives of `curl_noise` and the bound
+// `NOISE_LIPSCHITZ` need. `fade` weights all three axes by one sum, so `noise` jumps there.
+// The sines of the hashes come from one sine and cosine by the angle sum formula, which
+// changes them by rounding only.
+pub fn smooth_noise(x: Vec3d) -> f64 {
+    let n = x.floor().dot(Vec3d::new(1., 57., 113.));
+    let (sin, cos) = n.sin_cos();
+    let hashes = CORNER_ANGLES.map(|(sin_corner, cos_corner)| {
+        let x = (sin * cos_corner + cos * sin_corner) * 43758.5453;
+        x - x.floor()
+    });
+    lattice_noise(hashes, smooth_fade(x.fract()))
+}
+
+// offsets of the hashes of the corners of a lattice cell from its first one
+const CORNERS: [f64; 8] = [0., 1., 57., 58., 113., 114., 170., 171.];
+
+// sines and cosines of `CORNERS`
+const CORNER_ANGLES: [(f64, f64); 8] = [
+    (0., 1.),
+    (0.841_470_984_807_896_5, 0.540_302_305_868_139_8),
+    (0.436_164_755_247_824_94, 0.899_866_826_969_193_7),
+    (0.992_872_648_084_537_1, 0.119_180_135_448_819_28),
+    (-0.097_181_905_893_209_02, 0.995_266_636_217_131_3),
+    (0.784_980_388_681_310_5, 0.619_520_612_559_209_9),
+    (0.346_649_455_497_030_3, 0.937_994_752_119_441_5),
+    (0.976_590_867_943_565_8, 0.215_105_268_762_141_17),
+];
+
+// the lattice values `v` at the corners of a cell, in the order of `CORNERS`, interpolated
+// with the weights `f`
+fn lattice_noise(v: [f64; 8], f: Vec3d) -> f64 {
+    lerp(
+        lerp(lerp(v[0], v[1], f.x), lerp(v[2], v[3], f.x), f.y),
+        lerp(lerp(v[4], v[5], f.x), lerp(v[6], v[7], f.x), f.y),
+        f.z,
+    )
+}
+
+// offsets of the noise fields making up the vector potential of `curl_noise`
+const CURL_OFFSETS: [Vec3d; 3] = [
+    Vec3d::new(0., 0., 0.),
+    Vec3d::new(31.4, -7.2, 12.9),
+    Vec3d::new(-18.6, 23.1, -5.3),
+];
+const CURL_EPSILON: f64 = 1e-3;
+
+// Bound on the gradient of `curl_noise`. The second derivatives of `smooth_noise` are at
+// most 6 along an axis, where the smoothstep bends the most, and 1.5 × 1.5 × 2 across two,
+// so a row of its Hessian is at most sqrt(6^2 + 2 × 4.5^2) long. A component of the curl
+// is the difference of two such rows, and there are three of them.
+pub const CURL_LIPSCHITZ: f64 = 2. * 8.746_427_842_267_95 * 1.732_050_807_568_877_2;
+
+// Divergence-free flow: the curl of a vector potential of three offset noise fields, by
+// central differences. Particles carried by it swirl around without bunching up.
+pub fn curl_noise(p: Vec3d) -> Vec3d {
+    let potential = |p: Vec3d| CURL_OFFSETS.map(|offset| smooth_noise(p + offset));
+    let derivative = |axis: Vec3d| {
+        let (a, b) = (
+            potential(p + axis * CURL_EPSILON),
+            potential(p - axis * CURL_EPSILON),
+        );
+        [0, 1, 2].map(|i| (a[i] - b[i]) / (2. * CURL_EPSILON))
+    };
+    let (dx, dy, dz) = (
+        derivative(Vec3d::new(1., 0., 0.)),
+        derivative(Vec3d::new(0., 1., 0.)),
+        derivative(Vec3d::new(0., 0., 1.)),
+    );
+    Vec3d::new(dy[2] - dz[1], dz[0] - dx[2], dx[1] - dy[0])
+}
+
+// orthogonal, so the octaves of the FBM don't line up along the axes
+const ROTATION: Mat3 = Mat3 {
+    rows: [
+        Vec3d::new(0., 0.8, 0.6),
+        Vec3d::new(-0.80, 0.36, -0.48),
+        Vec3d::new(-0.60, -0.48, 0.64),
+    ],
+};
+
+pub fn rotate(v: Vec3d) -> Vec3d {
+    ROTATION * v
+}
+
+// amplitude of every octave of the FBM and the factor to the frequency of the next one
+const OCTAVES: [(f64, f64); 4] = [(0.5, 2.32), (0.25, 3.03), (0.125, 2.61), (0.0625, 1.)];
+
+pub const FBM_OCTAVES: usize = OCTAVES.len();
+
+// Bound on the gradient of `smooth_noise`. It is largest for corner values of 0 and 1, as
+// it is linear in them, and in all 128 such cells, up to swapping 0 and 1, a grid of 321³
+// points finds it at most 1.5, the slope of the smoothstep. Every point is within
+// sqrt(3) / 640 of the grid, over which the gradient changes by at most that times the
+// Hessian's bound of 6 + 2 × 4.5, so by 0.041.
+pub const NOISE_LIPSCHITZ: f64 = 1.55;
+
+// Bound on the gradient of the FBM of `smooth_noise` of its first `octaves` octaves, their
+// bounds scaled by their amplitudes and frequencies. The rotation between them keeps
+// lengths.
+pub const fn fbm_lipschitz(octaves: usize) -> f64 {
+    let mut bound = 0.;
+    let mut frequency = 1.;
+    let mut i = 0;
+    while i < octaves && i < OCTAVES.len() {
+        bound += OCTAVES[i].0 * frequency * NOISE_LIPSCHITZ;
+        frequency *= OCTAVES[i].1;
+        i += 1;
+    }
+    bound / 0.9375
+}
+
+pub const FBM_LIPSCHITZ: f64 = fbm_lipschitz(FBM_OCTAVES);
+
+// Most the octaves from `first` up to `octaves` can add to the FBM or change it by, the sum
+// of their amplitudes as the noise is in [0, 1].
+pub const fn fbm_range(first: usize, octaves: usize) -> f64 {
+    let mut range = 0.;
+    let mut i = first;
+    while i < octaves && i < OCTAVES.len() {
+        range += OCTAVES[i].0;
+        i += 1;
+    }
+    range / 0.9375
+}
+
+pub fn fractal_brownian_motion(x: Vec3d) -> f64 {
+    Octaves::new(x, 0., FBM_OCTAVES, noise).sum()
+}
+
+// The octaves of the FBM of `smooth_noise`, whose bound `fbm_lipschitz` is, for surfaces
+// displaced by it. With a `table` the noise is looked up in it.
+pub fn smooth_octaves(
+    x: Vec3d,
+    footprint: f64,
+    octaves: usize,
+    table: Option<&NoiseTable>,
+) -> Octaves<impl Fn(Vec3d) -> f64 + '_> {
+    let noise = move |p| match table {
+        Some(table) => table.noise(p),
+        None => smooth_noise(p),
+    };
+    Octaves::new(x, footprint, octaves, noise)
+}
+
+// The FBM of `noise` summed an octave at a time, for callers that can tell when the octaves
+// left no longer matter. An octave is kept while its period spans two `footprint`s and fades
+// to its average noise value until it spans one, so dropping it neither shifts the surface
+// nor pops. Only the first `octaves` octaves are kept at all.
+pub struct Octaves<N> {
+    noise: N,
+    p: Vec3d,
+    frequency: f64,
+    footprint: f64,
+    octaves: usize,
+    added: usize,
+    sum: f64,
+}
+
+impl<N: Fn(Vec3d) -> f64> Octaves<N> {
+    pub fn new(x: Vec3d, footprint: f64, octaves: usize, noise: N) -> Self {
+        Octaves {
+            noise,
+            p: rotate(x),
+            frequency: 1.,
+            footprint,
+            octaves,
+            added: 0,
+            sum: 0.,
+        }
+    }
+
+    // how many octaves are in `value`, up to `FBM_OCTAVES`
+    pub fn added(&self) -> usize {
+        self.added
+    }
+
+    // the FBM of the octaves added so far, the rest add between 0 and `fbm_range` of them
+    pub fn value(&self) -> f64 {
+        self.sum / 0.9375
+    }
+
+    // adds the next octave, false once all of them are in
+    pub fn add(&mut self) -> bool {
+        let Some(&(amplitude, lacunarity)) = OCTAVES.get(self.added) else {
+            return false;
+        };
+        let keep = match self.added < self.octaves {
+            true => (2. - 2. * self.footprint * self.frequency).clamp(0., 1.),
+            false => 0.,
+        };
+        self.sum += amplitude
+            * match keep {
+                k if k >= 1. => (self.noise)(self.p),
+                k if k <= 0. => 0.5,
+                k => lerp(0.5, (self.noise)(self.p), k),
+            };
+        self.p *= lacunarity;
+        self.frequency *= lacunarity;
+        self.added += 1;
+        true
+    }
+
+    pub fn sum(mut self) -> f64 {
+        while self.add() {}
+        self.value()
+    }
+}
+
+// planar slice of the FBM, for height fields
+pub fn fractal_brownian_motion_2d(x: f64, z: f64) -> f64 {
+    fractal_brownian_motion(Vec3d::new(x, 0., z))
+}
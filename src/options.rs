@@ -0,0 +1,979 @@
+use std::str::FromStr;
+
+use crate::batch;
+use crate::camera::Lens;
+use crate::color::Color;
+use crate::config;
+use crate::distributed;
+use crate::dump::DumpFormat;
+use crate::error::Error;
+use crate::film::{Filter, PixelFilter};
+use crate::image::{Dither, Encoding, Format};
+use crate::integrator::{self, Integrator};
+use crate::light::{AreaLight, LightShape};
+use crate::log::Level;
+use crate::noise::{NoiseMode, FBM_OCTAVES};
+use crate::sampler::SamplerKind;
+use crate::scene::{self, Object};
+use crate::sdf::MARCH_STEPS;
+use crate::server;
+use crate::sky::{Backdrop, Sky};
+use crate::texture::Texture;
+use crate::vec3d::{Vec2d, Vec3d};
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum StereoMode {
+    SideBySide,
+    Anaglyph,
+}
+
+impl FromStr for StereoMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<StereoMode, String> {
+        match s {
+            "sbs" | "side-by-side" => Ok(StereoMode::SideBySide),
+            "anaglyph" => Ok(StereoMode::Anaglyph),
+            _ => Err(format!("unknown stereo mode '{}'", s)),
+        }
+    }
+}
+
+// Named sets of the settings that trade quality for speed: the image size, samples, noise
+// octaves, march steps and shadow rays. `preview` is what the defaults are.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Quality {
+    Draft,
+    Preview,
+    Final,
+}
+
+impl FromStr for Quality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Quality, String> {
+        match s {
+            "draft" => Ok(Quality::Draft),
+            "preview" => Ok(Quality::Preview),
+            "final" => Ok(Quality::Final),
+            _ => Err(format!("unknown quality '{}'", s)),
+        }
+    }
+}
+
+impl Quality {
+    // sets the options of the preset, the ones given after it override them
+    fn apply(self, options: &mut Options) {
+        let (scale, spp, octaves, steps, shadow_samples) = match self {
+            Quality::Draft => (0.5, 1, 2, MARCH_STEPS / 2, 1),
+            Quality::Preview => (1., 1, FBM_OCTAVES, MARCH_STEPS, 1),
+            Quality::Final => (1., 16, FBM_OCTAVES, MARCH_STEPS * 2, 4),
+        };
+        options.resolution_scale = scale;
+        options.spp = spp;
+        options.noise_octaves = octaves;
+        options.march_steps = steps;
+        options.shadow_samples = shadow_samples;
+    }
+}
+
+#[derive(Clone)]
+pub struct Options {
+    pub width: usize,
+    pub height: usize,
+    // the image is rendered at `width` and `height` times this
+    pub resolution_scale: f64,
+    // vertical field of view in radians, given in degrees
+    pub fov: f64,
+    // the camera looks down -z from there, or at `camera_target`
+    pub camera_position: Vec3d,
+    pub camera_target: Option<Vec3d>,
+    // yaw and pitch in radians, given in degrees, the camera is swung around its target by,
+    // turning to `camera_orbit_end` over the `duration` with it
+    pub camera_orbit: Vec2d,
+    pub camera_orbit_end: Option<Vec2d>,
+    // depth of field, a pinhole with a zero radius
+    pub lens: Lens,
+    // radial distortion coefficients k1, k2 of the camera
+    pub distortion: Vec2d,
+    pub output: String,
+    // directory relative output paths are written to
+    pub output_dir: Option<String>,
+    // worker threads tracing the tiles of a render, 0 for one per core
+    pub threads: usize,
+    // image format of the outputs, by their file extension if not given
+    pub format: Option<Format>,
+    // JSON sidecar with the settings and statistics next to every render
+    pub metadata: bool,
+    // checks the scene and estimates what rendering it takes instead of rendering
+    pub check: bool,
+    // prints the scene as it would be rendered instead of rendering
+    pub dump_scene: Option<DumpFormat>,
+    // times `bench_runs` renders of a reference scene instead of rendering
+    pub bench: bool,
+    pub bench_runs: usize,
+    // address of the HTTP render server to run instead of rendering
+    pub serve: Option<String>,
+    // address to trace chunks for coordinators at, and the workers a coordinator sends
+    // its chunks to
+    pub worker: Option<String>,
+    pub workers: Vec<String>,
+    // secret a worker only accepts chunks with, shared by the coordinators
+    pub worker_token: Option<String>,
+    // stderr logging, `verbose` adds the stage timings and per-tile events
+    pub verbose: bool,
+    pub quiet: bool,
+    // bits per sample, 8 or 16, and dithering of the rounding to 8 bits
+    pub bit_depth: u32,
+    pub dither: Dither,
+    pub spp: usize,
+    pub min_spp: usize,
+    pub target_error: f64,
+    pub sampler: SamplerKind,
+    // reconstruction filter of the samples, of its default radius unless `filter_radius`
+    pub filter: Filter,
+    pub filter_radius: Option<f64>,
+    // varies the sample pattern and the fireball noise, 0 is the classic look
+    pub seed: u32,
+    pub integrator: Integrator,
+    pub max_bounces: usize,
+    pub nee: bool,
+    pub density: f64,
+    pub anisotropy: f64,
+    pub scattering_albedo: f64,
+    pub light: AreaLight,
+    pub objects: Vec<Object>,
+    // index of the last `object`, which every `instance` after it starts as a copy of
+    pub template: usize,
+    // scene time in seconds, the `t` of expression SDFs
+    pub time: f64,
+    // the displacement noise drifts with the wind, in units per second, and is sheared by
+    // a curl noise flow of `turbulence` carried along
+    pub wind: Vec3d,
+    pub turbulence: f64,
+    // length in seconds of the explosion the fireballs go through over `time`,
+    // 0 keeps them as they are
+    pub lifecycle: f64,
+    // fireballs burst on the highest plane of the scene: cut off below it and spread out
+    // along it by `mushroom` times their radius
+    pub collide: bool,
+    pub mushroom: f64,
+    // drops noise octaves finer than this many pixels, 0 renders all of them
+    pub noise_lod: f64,
+    // octaves of the displacement noise, and the steps a ray is marched for before it
+    // counts as a miss
+    pub noise_octaves: usize,
+    pub march_steps: usize,
+    // shadow rays of direct shading spread over the light, 1 casts a hard shadow
+    pub shadow_samples: usize,
+    // rays are marched at most this far, and the depth of pixels that see nothing
+    pub far: f64,
+    // hits are resolved to this fraction of an object's radius off its surface, and the
+    // rays leaving them start this far out along the normal
+    pub surface_epsilon: f64,
+    pub normal_offset: f64,
+    // blocks of this many pixels that a coarse pass finds empty are filled with the
+    // background, 0 traces every pixel
+    pub coarse: usize,
+    // a single centered ray per pixel covers the pixel in part with the silhouettes it
+    // passes close to, for anti-aliased previews without supersampling
+    pub edge_aa: bool,
+    // exact or table lookups of the displacement noise, and the side of the table
+    pub noise: NoiseMode,
+    pub noise_table_size: usize,
+    // sprite sheet of `sprite_frames` frames over `duration` seconds, starting at `time`
+    pub sprite_frames: usize,
+    pub sprite_columns: Option<usize>,
+    pub duration: f64,
+    // frames rendered at the same time, sharing the `threads` between them
+    pub parallel_frames: usize,
+    // writes the coverage of the scene as alpha channel, for the formats that have one
+    pub alpha: bool,
+    // glowing embers thrown off the fireballs: how many are in the air at once, how fast
+    // they fly out in units per second, the seconds they glow for, their radius in scene
+    // units and their brightness
+    pub embers: usize,
+    pub ember_speed: f64,
+    pub ember_lifetime: f64,
+    pub ember_size: f64,
+    pub ember_intensity: f64,
+    pub floor: Option<f64>,
+    pub floor_texture: Texture,
+    pub floor_scale: f64,
+    pub sky: Sky,
+    pub background: Backdrop,
+    pub checker_size: f64,
+    pub sun_direction: Option<Vec3d>,
+    pub turbidity: f64,
+    pub fog: f64,
+    pub fog_color: Color,
+    pub denoise: bool,
+    // in stops; `auto_exposure` picks it so the log-average luminance lands on `exposure_key`
+    pub exposure: f64,
+    pub auto_exposure: bool,
+    pub exposure_key: f64,
+    // physical camera: f-number, shutter time in seconds and ISO sensitivity. Setting any
+    // of them takes the image as luminance in cd/m² and exposes it like that camera, the
+    // others following the sunny 16 rule.
+    pub aperture: Option<f64>,
+    pub shutter: Option<f64>,
+    pub iso: Option<f64>,
+    pub bloom: f64,
+    pub bloom_threshold: f64,
+    pub bloom_radius: f64,
+    pub vignette: f64,
+    pub chromatic_aberration: f64,
+    pub grain: f64,
+    // cel shading: bands of light and of palette colors, 0 shades smoothly, and the
+    // thickness in pixels of the ink outlines drawn with it
+    pub toon: usize,
+    pub ink: usize,
+    // batch mode: every combination of the swept option values is rendered
+    pub sweeps: Vec<(String, Vec<String>)>,
+    pub contact_sheet: Option<String>,
+    pub progressive: bool,
+    // the progressive preview renders a new frame at the elapsed time for every pass
+    // instead of refining a still one
+    pub live: bool,
+    // mouse position in pixels from the bottom-left corner, and the file the live preview
+    // reads it from before every frame
+    pub mouse: Vec2d,
+    pub mouse_file: Option<String>,
+    pub passes: usize,
+    // passes worth of samples a pixel of the preview keeps through a camera move, 0 starts
+    // over
+    pub history: usize,
+    // scene files loaded so far, watched by the progressive preview
+    pub scene_files: Vec<String>,
+    // every setting applied so far, in order
+    pub settings: Vec<(String, String)>,
+    pub stereo: Option<StereoMode>,
+    pub interocular: f64,
+    pub convergence: f64,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            width: 640,
+            height: 480,
+            resolution_scale: 1.,
+            fov: std::f64::consts::PI / 3.,
+            camera_position: Vec3d::new(0., 0., 3.),
+            camera_target: None,
+            camera_orbit: Vec2d::zero(),
+            camera_orbit_end: None,
+            lens: Lens::PINHOLE,
+            distortion: Vec2d::zero(),
+            output: "out_r.ppm".to_string(),
+            output_dir: None,
+            threads: 1,
+            format: None,
+            metadata: false,
+            bench: false,
+            bench_runs: 3,
+            check: false,
+            dump_scene: None,
+            serve: None,
+            worker: None,
+            workers: Vec::new(),
+            worker_token: None,
+            verbose: false,
+            quiet: false,
+            bit_depth: 8,
+            dither: Dither::None,
+            spp: 1,
+            min_spp: 8,
+            target_error: 0.,
+            sampler: SamplerKind::Random,
+            filter: Filter::Box,
+            filter_radius: None,
+            seed: 0,
+            integrator: Integrator::Direct,
+            max_bounces: 4,
+            nee: true,
+            density: 4.,
+            anisotropy: 0.3,
+            scattering_albedo: 0.7,
+            light: AreaLight::default(),
+            objects: Vec::new(),
+            template: 0,
+            time: 0.,
+            wind: Vec3d::zero(),
+            turbulence: 0.,
+            lifecycle: 0.,
+            collide: false,
+            mushroom: 0.5,
+            noise_lod: 0.,
+            noise_octaves: FBM_OCTAVES,
+            march_steps: MARCH_STEPS,
+            shadow_samples: 1,
+            far: f64::INFINITY,
+            surface_epsilon: scene::SURFACE_EPSILON,
+            normal_offset: integrator::NORMAL_OFFSET,
+            coarse: 0,
+            edge_aa: false,
+            noise: NoiseMode::Exact,
+            noise_table_size: 64,
+            sprite_frames: 0,
+            sprite_columns: None,
+            parallel_frames: 1,
+            duration: 1.,
+            alpha: false,
+            embers: 0,
+            ember_speed: 1.,
+            ember_lifetime: 1.5,
+            ember_size: 0.01,
+            ember_intensity: 4.,
+            floor: None,
+            floor_texture: Texture::Checker,
+            floor_scale: 1.,
+            sky: Sky::Flat,
+            background: Backdrop::Sky,
+            checker_size: 16.,
+            sun_direction: None,
+            turbidity: 3.,
+            fog: 0.,
+            fog_color: Color::new(0.5, 0.6, 0.7),
+            denoise: false,
+            exposure: 0.,
+            auto_exposure: false,
+            exposure_key: 0.4,
+            aperture: None,
+            shutter: None,
+            iso: None,
+            bloom: 0.,
+            bloom_threshold: 1.,
+            bloom_radius: 32.,
+            vignette: 0.,
+            chromatic_aberration: 0.,
+            grain: 0.,
+            toon: 0,
+            ink: 1,
+            sweeps: Vec::new(),
+            contact_sheet: None,
+            progressive: false,
+            live: false,
+            mouse: Vec2d::zero(),
+            mouse_file: None,
+            passes: 64,
+            history: 8,
+            scene_files: Vec::new(),
+            settings: Vec::new(),
+            stereo: None,
+            interocular: 0.2,
+            convergence: 3.,
+        }
+    }
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, Error> {
+    value
+        .parse()
+        .map_err(|_| Error::Parse(format!("invalid value '{}' for '{}'", value, key)))
+}
+
+impl Options {
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        // scene files are recorded by the settings they contain, the token is kept out of the
+        // metadata and the chunks
+        if !matches!(key, "scene" | "worker-token") {
+            self.settings.push((key.to_string(), value.to_string()));
+        }
+        match key {
+            "scene" => {
+                self.scene_files.push(value.to_string());
+                for (key, setting) in scene::load(value)? {
+                    self.set(&key, &setting).map_err(|err| err.in_file(value))?;
+                }
+            }
+            "width" => self.width = parse(key, value)?,
+            "height" => self.height = parse(key, value)?,
+            "resolution-scale" => self.resolution_scale = parse(key, value)?,
+            "quality" => value.parse::<Quality>()?.apply(self),
+            "fov" => self.fov = parse::<f64>(key, value)?.to_radians(),
+            "camera-position" => self.camera_position = value.parse()?,
+            "camera-target" => self.camera_target = Some(value.parse()?),
+            "camera-orbit" => self.camera_orbit = parse::<Vec2d>(key, value)?.map(f64::to_radians),
+            "camera-orbit-end" => {
+                self.camera_orbit_end = Some(parse::<Vec2d>(key, value)?.map(f64::to_radians))
+            }
+            "lens-radius" => self.lens.radius = parse(key, value)?,
+            "lens-focus" => self.lens.focus = parse(key, value)?,
+            "lens-blades" => self.lens.blades = parse(key, value)?,
+            "lens-rotation" => self.lens.rotation = parse::<f64>(key, value)?.to_radians(),
+            "lens-cat-eye" => self.lens.cat_eye = parse(key, value)?,
+            "distortion" => self.distortion = value.parse()?,
+            "output" => self.output = value.to_string(),
+            "output-dir" => self.output_dir = Some(value.to_string()),
+            "threads" => self.threads = parse(key, value)?,
+            "format" => self.format = Some(value.parse()?),
+            "metadata" => self.metadata = parse(key, value)?,
+            "check" => self.check = parse(key, value)?,
+            "dump-scene" => {
+                self.dump_scene = match value {
+                    "true" => Some(DumpFormat::Text),
+                    _ => Some(value.parse()?),
+                }
+            }
+            "bench" => self.bench = parse(key, value)?,
+            "bench-runs" => self.bench_runs = parse(key, value)?,
+            "worker" => {
+                self.worker = match value {
+                    "false" => None,
+                    "true" => Some(distributed::DEFAULT_ADDRESS.to_string()),
+                    address => Some(address.to_string()),
+                }
+            }
+            "workers" => {
+                self.workers = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|address| !address.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            "worker-token" => self.worker_token = Some(value.to_string()),
+            "serve" => {
+                self.serve = match value {
+                    "false" => None,
+                    "true" => Some(server::DEFAULT_ADDRESS.to_string()),
+                    address => Some(address.to_string()),
+                }
+            }
+            "verbose" => self.verbose = parse(key, value)?,
+            "quiet" => self.quiet = parse(key, value)?,
+            "bit-depth" => {
+                self.bit_depth = match parse(key, value)? {
+                    depth @ (8 | 16) => depth,
+                    depth => {
+                        return Err(Error::Invalid(format!("unsupported bit depth {}", depth)))
+                    }
+                }
+            }
+            "dither" => self.dither = value.parse()?,
+            "spp" => self.spp = parse(key, value)?,
+            "min-spp" => self.min_spp = parse(key, value)?,
+            "target-error" => self.target_error = parse(key, value)?,
+            "sampler" => self.sampler = value.parse()?,
+            "filter" => self.filter = value.parse()?,
+            "filter-radius" => self.filter_radius = Some(parse(key, value)?),
+            "seed" => self.seed = parse(key, value)?,
+            "integrator" => self.integrator = value.parse()?,
+            "max-bounces" => self.max_bounces = parse(key, value)?,
+            "nee" => self.nee = parse(key, value)?,
+            "density" => self.density = parse(key, value)?,
+            "anisotropy" => self.anisotropy = parse(key, value)?,
+            "scattering-albedo" => self.scattering_albedo = parse(key, value)?,
+            "light-shape" => self.light.shape = value.parse()?,
+            "light-size" => self.light.size = value.parse()?,
+            "light-position" => self.light.position = value.parse()?,
+            "light-radius" => self.light.radius = parse(key, value)?,
+            "light-color" => self.light.color = value.parse()?,
+            "light-intensity" => self.light.intensity = parse(key, value)?,
+            "light-falloff" => self.light.falloff = value.parse()?,
+            "light-target" => self.light.target = value.parse()?,
+            "light-spot-angle" => self.light.spot_angle = parse(key, value)?,
+            "light-spot-blend" => self.light.spot_blend = parse(key, value)?,
+            "light-shadows" => self.light.shadows = parse(key, value)?,
+            "light-objects" => {
+                // numbered from 1 in the scene, like in the errors about them
+                self.light.objects = match value {
+                    "all" => None,
+                    _ => Some(
+                        value
+                            .split(',')
+                            .map(|n| match parse::<usize>(key, n.trim())? {
+                                0 => Err(Error::Invalid(format!("{} counts objects from 1", key))),
+                                n => Ok(n - 1),
+                            })
+                            .collect::<Result<_, _>>()?,
+                    ),
+                }
+            }
+            "object" => {
+                let mut object = Object::fireball();
+                if !value.is_empty() && value != "true" {
+                    object.set("shape", value)?;
+                }
+                self.template = self.objects.len();
+                self.objects.push(object);
+            }
+            // another placement of the last object, sharing its meshes and expressions
+            "instance" => {
+                let object = match self.objects.get(self.template) {
+                    Some(object) => object.clone(),
+                    None => Object::fireball(),
+                };
+                self.objects.push(object);
+            }
+            "time" => self.time = parse(key, value)?,
+            "wind" => self.wind = value.parse()?,
+            "turbulence" => self.turbulence = parse(key, value)?,
+            "lifecycle" => self.lifecycle = parse(key, value)?,
+            "collide" => self.collide = parse(key, value)?,
+            "mushroom" => self.mushroom = parse(key, value)?,
+            "noise-lod" => self.noise_lod = parse(key, value)?,
+            "noise-octaves" => self.noise_octaves = parse(key, value)?,
+            "march-steps" => self.march_steps = parse(key, value)?,
+            "shadow-samples" => self.shadow_samples = parse(key, value)?,
+            "far" => self.far = parse(key, value)?,
+            "surface-epsilon" => self.surface_epsilon = parse(key, value)?,
+            "normal-offset" => self.normal_offset = parse(key, value)?,
+            "coarse" => self.coarse = parse(key, value)?,
+            "edge-aa" => self.edge_aa = parse(key, value)?,
+            "noise" => self.noise = value.parse()?,
+            "noise-table-size" => self.noise_table_size = parse(key, value)?,
+            "sprite-frames" => self.sprite_frames = parse(key, value)?,
+            "sprite-columns" => self.sprite_columns = Some(parse(key, value)?),
+            "parallel-frames" => self.parallel_frames = parse(key, value)?,
+            "duration" => self.duration = parse(key, value)?,
+            "alpha" => self.alpha = parse(key, value)?,
+            // before any `object` these configure the default fireball
+            _ if key.starts_with("object-") || key.starts_with("instance-") => {
+                if self.objects.is_empty() {
+                    self.objects.push(Object::fireball());
+                }
+                let (_, key) = key.split_once('-').unwrap();
+                self.objects.last_mut().unwrap().set(key, value)?
+            }
+            "embers" => self.embers = parse(key, value)?,
+            "ember-speed" => self.ember_speed = parse(key, value)?,
+            "ember-lifetime" => self.ember_lifetime = parse(key, value)?,
+            "ember-size" => self.ember_size = parse(key, value)?,
+            "ember-intensity" => self.ember_intensity = parse(key, value)?,
+            "floor" => self.floor = Some(parse(key, value)?),
+            "floor-texture" => self.floor_texture = value.parse()?,
+            "floor-scale" => self.floor_scale = parse(key, value)?,
+            "sky" => self.sky = value.parse()?,
+            "background" => self.background = value.parse()?,
+            "checker-size" => self.checker_size = parse(key, value)?,
+            "sun-direction" => self.sun_direction = Some(value.parse()?),
+            "turbidity" => self.turbidity = parse(key, value)?,
+            "fog" => self.fog = parse(key, value)?,
+            "fog-color" => self.fog_color = value.parse()?,
+            "denoise" => self.denoise = parse(key, value)?,
+            "exposure" => self.exposure = parse(key, value)?,
+            "auto-exposure" => self.auto_exposure = parse(key, value)?,
+            "exposure-key" => self.exposure_key = parse(key, value)?,
+            "aperture" => self.aperture = Some(parse(key, value)?),
+            "shutter" => self.shutter = Some(parse(key, value)?),
+            "iso" => self.iso = Some(parse(key, value)?),
+            "bloom" => self.bloom = parse(key, value)?,
+            "bloom-threshold" => self.bloom_threshold = parse(key, value)?,
+            "bloom-radius" => self.bloom_radius = parse(key, value)?,
+            "vignette" => self.vignette = parse(key, value)?,
+            "chromatic-aberration" => self.chromatic_aberration = parse(key, value)?,
+            "grain" => self.grain = parse(key, value)?,
+            "toon" => self.toon = parse(key, value)?,
+            "ink" => self.ink = parse(key, value)?,
+            "contact-sheet" => self.contact_sheet = Some(value.to_string()),
+            _ if key.starts_with("sweep-") => {
+                let values =
+                    batch::sweep_values(value).map_err(|err| format!("{}: {}", key, err))?;
+                let key = &key["sweep-".len()..];
+                self.sweeps.retain(|(swept, _)| swept != key);
+                self.sweeps.push((key.to_string(), values));
+            }
+            "progressive" => self.progressive = parse(key, value)?,
+            "live" => self.live = parse(key, value)?,
+            "mouse" => self.mouse = parse(key, value)?,
+            "mouse-file" => self.mouse_file = Some(value.to_string()),
+            "passes" => self.passes = parse(key, value)?,
+            "history" => self.history = parse(key, value)?,
+            "stereo" => {
+                self.stereo = match value {
+                    "false" => None,
+                    "true" => Some(StereoMode::SideBySide),
+                    mode => Some(mode.parse()?),
+                }
+            }
+            "interocular" => self.interocular = parse(key, value)?,
+            "convergence" => self.convergence = parse(key, value)?,
+            _ => return Err(Error::Parse(format!("unknown option '{}'", key))),
+        }
+        Ok(())
+    }
+
+    // how an output at `path` is written
+    pub fn encoding(&self, path: &str) -> Encoding {
+        Encoding {
+            format: self.format.unwrap_or_else(|| Format::from_path(path)),
+            depth: self.bit_depth,
+            dither: self.dither,
+        }
+    }
+
+    // Rejects settings that parse but would render NaNs, nothing, or forever.
+    pub fn validate(&self) -> Result<(), Error> {
+        let check = |ok: bool, message: &str| {
+            if ok {
+                Ok(())
+            } else {
+                Err(Error::Invalid(message.to_string()))
+            }
+        };
+        check(self.width > 0, "width must be at least 1")?;
+        check(self.height > 0, "height must be at least 1")?;
+        check(
+            self.resolution_scale > 0. && self.resolution_scale.is_finite(),
+            "resolution-scale must be a positive number",
+        )?;
+        check(
+            self.fov > 0. && self.fov < std::f64::consts::PI,
+            "fov must be between 0 and 180 degrees",
+        )?;
+        check(
+            self.camera_position.is_finite(),
+            "camera-position must be finite",
+        )?;
+        check(
+            self.camera_orbit.is_finite()
+                && self.camera_orbit_end.is_none_or(|end| end.is_finite()),
+            "camera-orbit must be finite",
+        )?;
+        if let Some(target) = self.camera_target {
+            // the horizon is level, which no view straight up or down has
+            let view = target - self.camera_position;
+            check(
+                view.is_finite() && view.cross(Vec3d::new(0., 1., 0.)).length() > 0.,
+                "camera-target must be finite and not straight above or below the camera",
+            )?;
+        }
+        // the eyes are sheared by their offset over the convergence distance
+        check(
+            self.convergence > 0. && self.convergence.is_finite(),
+            "convergence must be a positive number",
+        )?;
+        check(self.interocular.is_finite(), "interocular must be finite")?;
+        check(
+            self.lens.radius >= 0. && self.lens.radius.is_finite(),
+            "lens-radius must not be negative",
+        )?;
+        check(
+            self.lens.focus > 0. && self.lens.focus.is_finite(),
+            "lens-focus must be a positive number",
+        )?;
+        check(
+            self.lens.blades == 0 || self.lens.blades >= 3,
+            "lens-blades must be 0 for a round aperture or at least 3",
+        )?;
+        check(
+            self.lens.rotation.is_finite() && (0. ..=1.).contains(&self.lens.cat_eye),
+            "lens-rotation must be finite and lens-cat-eye between 0 and 1",
+        )?;
+        check(self.distortion.is_finite(), "distortion must be finite")?;
+        check(self.mouse.is_finite(), "mouse must be finite")?;
+        check(self.wind.is_finite(), "wind must be finite")?;
+        check(
+            self.turbulence >= 0. && self.turbulence.is_finite(),
+            "turbulence must not be negative",
+        )?;
+        check(
+            self.lifecycle >= 0. && self.lifecycle.is_finite(),
+            "lifecycle must be a non-negative number",
+        )?;
+        check(
+            self.mushroom >= 0. && self.mushroom.is_finite(),
+            "mushroom must be a non-negative number",
+        )?;
+        check(self.spp >= 1, "spp must be at least 1")?;
+        check(
+            self.target_error >= 0. && self.target_error.is_finite(),
+            "target-error must be a non-negative number",
+        )?;
+        check(
+            self.filter_radius.is_none_or(|r| r > 0. && r <= 4.),
+            "filter-radius must be positive and at most 4 pixels",
+        )?;
+        check(self.passes >= 1, "passes must be at least 1")?;
+        check(self.bench_runs >= 1, "bench-runs must be at least 1")?;
+        check(
+            self.sprite_columns != Some(0),
+            "sprite-columns must be at least 1",
+        )?;
+        check(
+            self.noise_lod >= 0. && self.noise_lod.is_finite(),
+            "noise-lod must be a non-negative number",
+        )?;
+        check(
+            (1..=FBM_OCTAVES).contains(&self.noise_octaves),
+            &format!("noise-octaves must be between 1 and {}", FBM_OCTAVES),
+        )?;
+        check(self.march_steps >= 1, "march-steps must be at least 1")?;
+        check(self.far > 0., "far must be positive")?;
+        check(
+            self.surface_epsilon > 0. && self.surface_epsilon < 1.,
+            "surface-epsilon must be between 0 and 1",
+        )?;
+        check(
+            self.normal_offset >= 0. && self.normal_offset.is_finite(),
+            "normal-offset must be a non-negative number",
+        )?;
+        check(self.toon != 1, "toon needs at least 2 bands")?;
+        check(
+            self.shadow_samples >= 1,
+            "shadow-samples must be at least 1",
+        )?;
+        check(
+            (2..=1024).contains(&self.noise_table_size),
+            "noise-table-size must be between 2 and 1024",
+        )?;
+        check(
+            self.parallel_frames >= 1,
+            "parallel-frames must be at least 1",
+        )?;
+        check(
+            self.time.is_finite() && self.duration.is_finite(),
+            "time and duration must be finite",
+        )?;
+        check(
+            self.density >= 0. && self.density.is_finite(),
+            "density must be a non-negative number",
+        )?;
+        check(
+            self.anisotropy > -1. && self.anisotropy < 1.,
+            "anisotropy must be between -1 and 1",
+        )?;
+        check(
+            self.light.position.is_finite()
+                && self.light.radius > 0.
+                && self.light.intensity.is_finite(),
+            "the light needs a finite position and intensity and a positive radius",
+        )?;
+        check(
+            self.light.spot_angle > 0. && self.light.spot_angle <= 180.,
+            "light-spot-angle must be above 0 and at most 180 degrees",
+        )?;
+        check(
+            (0. ..=1.).contains(&self.light.spot_blend),
+            "light-spot-blend must be between 0 and 1",
+        )?;
+        check(
+            self.light.size.x > 0. && self.light.size.y > 0. && self.light.size.is_finite(),
+            "light-size must be positive",
+        )?;
+        let aimed = self.light.spot_angle < 180. || self.light.shape == LightShape::Rect;
+        check(
+            !aimed || self.light.target != self.light.position,
+            "spot and rect lights need a light-target away from their position",
+        )?;
+        check(self.checker_size > 0., "checker-size must be positive")?;
+        check(
+            self.ember_speed >= 0. && self.ember_speed.is_finite(),
+            "ember-speed must not be negative",
+        )?;
+        check(
+            self.ember_lifetime > 0. && self.ember_lifetime.is_finite(),
+            "ember-lifetime must be a positive number",
+        )?;
+        check(
+            self.ember_size > 0. && self.ember_size.is_finite(),
+            "ember-size must be a positive number",
+        )?;
+        check(
+            self.ember_intensity >= 0. && self.ember_intensity.is_finite(),
+            "ember-intensity must not be negative",
+        )?;
+        check(
+            self.exposure.is_finite() && self.exposure_key > 0.,
+            "exposure must be finite and exposure-key positive",
+        )?;
+        check(
+            [self.aperture, self.shutter, self.iso]
+                .iter()
+                .all(|setting| setting.is_none_or(|v| v > 0. && v.is_finite())),
+            "aperture, shutter and iso must be positive numbers",
+        )?;
+        check(
+            self.worker.is_none() && self.workers.is_empty() || self.worker_token.is_some(),
+            "workers and their coordinators need a shared worker-token",
+        )?;
+        for (n, object) in self.objects.iter().enumerate() {
+            let object_check =
+                |ok: bool, what: &str| check(ok, &format!("object {}: {}", n + 1, what));
+            object_check(object.center.is_finite(), "center must be finite")?;
+            object_check(
+                object.radius > 0. && object.radius.is_finite(),
+                "radius must be a positive number",
+            )?;
+            object_check(
+                object.noise_amplitude.is_finite(),
+                "noise-amplitude must be finite",
+            )?;
+            object_check(
+                object.sdf_lipschitz.is_none_or(|l| l > 0. && l.is_finite()),
+                "lipschitz must be a positive number",
+            )?;
+            let heat = object.material.heat;
+            object_check(
+                heat.core.is_finite() && heat.depth.is_finite(),
+                "heat-core and heat-depth must be finite",
+            )?;
+            object_check(
+                heat.falloff > 0. && heat.falloff.is_finite(),
+                "heat-falloff must be a positive number",
+            )?;
+            object_check(
+                heat.bias > 0. && heat.bias < 1.,
+                "heat-bias must be between 0 and 1",
+            )?;
+            object_check(
+                heat.gain >= 0. && heat.gain.is_finite(),
+                "heat-gain must be a non-negative number",
+            )?;
+            let smoke = object.material.smoke;
+            object_check(
+                smoke.level > 0. && smoke.level <= 1.,
+                "smoke-level must be above 0 and at most 1",
+            )?;
+            object_check(
+                smoke.breakup >= 0. && smoke.breakup.is_finite(),
+                "smoke-breakup must be a non-negative number",
+            )?;
+            let style = object.material.style;
+            object_check(
+                [style.wrap, style.ambient, style.rim]
+                    .iter()
+                    .all(|&x| x >= 0. && x.is_finite()),
+                "wrap, ambient and rim must be non-negative numbers",
+            )?;
+            object_check(
+                style.rim_width > 0. && style.rim_width <= 1.,
+                "rim-width must be above 0 and at most 1",
+            )?;
+            object_check(
+                style.tint_top.is_finite() && style.tint_bottom.is_finite(),
+                "tint-top and tint-bottom must be finite",
+            )?;
+        }
+        Ok(())
+    }
+
+    // how far the animations from their start to their end are at `time`, all the way
+    // without a `duration`
+    pub fn progress(&self) -> f64 {
+        match self.duration > 0. {
+            true => (self.time / self.duration).clamp(0., 1.),
+            false => 1.,
+        }
+    }
+
+    // the size of the image rendered, at least a pixel
+    pub fn resolution(&self) -> (usize, usize) {
+        let scale = |n: usize| ((n as f64 * self.resolution_scale).round() as usize).max(1);
+        (scale(self.width), scale(self.height))
+    }
+
+    // `quiet` wins over `verbose`, so scripts can silence a scene file that asks for logs
+    pub fn log_level(&self) -> Level {
+        match (self.quiet, self.verbose) {
+            (true, _) => Level::Quiet,
+            (false, true) => Level::Debug,
+            (false, false) => Level::Info,
+        }
+    }
+
+    // `path` in the output directory, if it is relative
+    pub fn output_path(&self, path: &str) -> String {
+        match &self.output_dir {
+            Some(dir) if std::path::Path::new(path).is_relative() => std::path::Path::new(dir)
+                .join(path)
+                .to_string_lossy()
+                .into_owned(),
+            _ => path.to_string(),
+        }
+    }
+
+    // the number of threads `threads` asks for
+    pub fn worker_threads(&self) -> usize {
+        match self.threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+
+    pub fn pixel_filter(&self) -> PixelFilter {
+        PixelFilter::new(self.filter, self.filter_radius)
+    }
+
+    fn apply_args(&mut self, args: impl Iterator<Item = String>) -> Result<(), Error> {
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            let key = arg
+                .strip_prefix("--")
+                .ok_or(format!("unexpected argument '{}'", arg))?;
+            // a flag without a value is a switch, `--denoise` is `--denoise true`
+            let value = match args.peek() {
+                Some(next) if !next.starts_with("--") => args.next().unwrap(),
+                _ => "true".to_string(),
+            };
+            self.set(key, &value)?;
+        }
+        Ok(())
+    }
+
+    // the defaults with `args` applied
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Options, Error> {
+        let mut options = Options::default();
+        options.apply_args(args)?;
+        options.validate()?;
+        Ok(options)
+    }
+
+    // The options of the binary: the defaults, overridden by the config file, the
+    // `RABOOM_*` environment variables and `args`, in that order.
+    pub fn load(args: impl Iterator<Item = String>) -> Result<Options, Error> {
+        let mut options = Options::default();
+        config::apply(&mut options)?;
+        options.apply_args(args)?;
+        options.validate()?;
+        Ok(options)
+    }
+}
+
+// Options go through their settings, in the order they were applied, so the objects and
+// meshes of a scene are stored by what defines them and are loaded again on the way back.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Options {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.settings.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Options {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Options, D::Error> {
+        let settings = Vec::<(String, String)>::deserialize(deserializer)?;
+        let mut options = Options::default();
+        for (key, value) in &settings {
+            options.set(key, value).map_err(serde::de::Error::custom)?;
+        }
+        options.validate().map_err(serde::de::Error::custom)?;
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+
+    fn stereo(key: &str, value: &str) -> Options {
+        let mut options = Options::default();
+        options.set("stereo", "true").unwrap();
+        options.set(key, value).unwrap();
+        options
+    }
+
+    #[test]
+    fn validate_rejects_stereo_that_shears_the_eyes_apart() {
+        for convergence in ["0", "-3", "NaN", "inf"] {
+            assert!(stereo("convergence", convergence).validate().is_err());
+        }
+        for interocular in ["NaN", "inf", "-inf"] {
+            assert!(stereo("interocular", interocular).validate().is_err());
+        }
+        assert!(stereo("convergence", "0.5").validate().is_ok());
+        assert!(stereo("interocular", "-0.2").validate().is_ok());
+    }
+}
@@ -0,0 +1,95 @@
+use std::str::FromStr;
+
+use crate::color::Color;
+
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Palette {
+    Fire,
+    Ice,
+    Toxic,
+    Smoke,
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Palette, String> {
+        match s {
+            "fire" => Ok(Palette::Fire),
+            "ice" => Ok(Palette::Ice),
+            "toxic" => Ok(Palette::Toxic),
+            "smoke" => Ok(Palette::Smoke),
+            _ => Err(format!("unknown palette '{}'", s)),
+        }
+    }
+}
+
+// The color stops of the palettes, from the value 0 to 1. Constants, so tables of other
+// palettes can be made for `gradient` at compile time as well.
+pub const FIRE: [Color; 5] = [
+    Color::new(0.4, 0.4, 0.4),
+    Color::new(0.2, 0.2, 0.2),
+    Color::new(1.0, 0.0, 0.0),
+    Color::new(1.0, 0.6, 0.0),
+    Color::new(1.7, 1.3, 1.0), // note that the color is "hot", i.e. has components >1
+];
+
+pub const ICE: [Color; 5] = [
+    Color::new(0.4, 0.4, 0.45),
+    Color::new(0.1, 0.15, 0.3),
+    Color::new(0.0, 0.3, 1.0),
+    Color::new(0.3, 0.8, 1.0),
+    Color::new(1.2, 1.5, 1.7),
+];
+
+pub const TOXIC: [Color; 5] = [
+    Color::new(0.35, 0.4, 0.3),
+    Color::new(0.1, 0.2, 0.05),
+    Color::new(0.2, 0.7, 0.0),
+    Color::new(0.6, 1.0, 0.1),
+    Color::new(1.4, 1.7, 0.8),
+];
+
+pub const SMOKE: [Color; 3] = [
+    Color::new(0.5, 0.5, 0.5),
+    Color::new(0.3, 0.3, 0.3),
+    Color::new(0.15, 0.15, 0.15),
+];
+
+// evenly spaced color stops, interpolated linearly
+pub fn gradient(stops: &[Color], d: f64) -> Color {
+    let x = d.clamp(0., 1.) * (stops.len() - 1) as f64;
+    let i = (x as usize).min(stops.len() - 2);
+    Color::lerp(stops[i], stops[i + 1], x - i as f64)
+}
+
+impl Palette {
+    pub const fn stops(self) -> &'static [Color] {
+        match self {
+            Palette::Fire => &FIRE,
+            Palette::Ice => &ICE,
+            Palette::Toxic => &TOXIC,
+            Palette::Smoke => &SMOKE,
+        }
+    }
+
+    pub fn color(self, d: f64) -> Color {
+        gradient(self.stops(), d)
+    }
+
+    // the name `from_str` reads
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Fire => "fire",
+            Palette::Ice => "ice",
+            Palette::Toxic => "toxic",
+            Palette::Smoke => "smoke",
+        }
+    }
+}
+
+pub fn palette_fire(d: f64) -> Color {
+    gradient(&FIRE, d)
+}
@@ -0,0 +1,182 @@
+use std::ffi::CStr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::error::Error;
+use crate::scene::Shading;
+use crate::vec3d::Vec3d;
+
+// The material a procedural object starts with, its palette, albedo and texture are then
+// set by the usual object options.
+pub type MaterialId = Shading;
+
+// Procedural object of a downstream crate. Registered under a name, it is picked by the
+// `plugin` key of an `[[object]]` and traced, shaded and written like the built-in shapes.
+pub trait SceneSdf: Send + Sync {
+    // Distance from the object space point `p` at scene time `t`. It doesn't have to be
+    // exact: the surface is marched with small steps inside the object's `radius`.
+    fn distance(&self, p: Vec3d, t: f64) -> f64;
+
+    fn material(&self) -> MaterialId;
+
+    // Largest change of `distance` per unit, if known. Objects with a bound are marched
+    // with the full steps it allows instead of small ones, 1 for exact distances.
+    fn lipschitz(&self) -> Option<f64> {
+        None
+    }
+}
+
+static REGISTRY: Mutex<Vec<(String, Arc<dyn SceneSdf>)>> = Mutex::new(Vec::new());
+
+// Makes `sdf` available to scene files as `plugin = "<name>"`, replacing an object
+// registered under the same name. Has to be called before the options are loaded, and on
+// every worker of a distributed render.
+pub fn register(name: &str, sdf: Arc<dyn SceneSdf>) {
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.retain(|(registered, _)| registered != name);
+    registry.push((name.to_string(), sdf));
+}
+
+pub fn lookup(name: &str) -> Option<Arc<dyn SceneSdf>> {
+    let registry = REGISTRY.lock().unwrap();
+    registry
+        .iter()
+        .find(|(registered, _)| registered == name)
+        .map(|(_, sdf)| sdf.clone())
+}
+
+// Shared libraries export a procedural object through a C ABI, so objects can be added to
+// a released binary. Version 1 of the ABI is made of the functions
+//
+//     uint32_t rustaboom_sdf_abi(void);         /* returns 1 */
+//     double rustaboom_sdf_distance(double x, double y, double z, double t);
+//     const char *rustaboom_sdf_name(void);     /* optional, the file name by default */
+//     uint32_t rustaboom_sdf_material(void);    /* optional, 0 fire, 1 diffuse (default), 2 trap */
+//
+// `rustaboom_sdf_distance` is `SceneSdf::distance`, called from all the render threads at
+// once.
+pub const ABI_VERSION: u32 = 1;
+
+type AbiFn = unsafe extern "C" fn() -> u32;
+type DistanceFn = unsafe extern "C" fn(f64, f64, f64, f64) -> f64;
+type NameFn = unsafe extern "C" fn() -> *const std::ffi::c_char;
+type MaterialFn = unsafe extern "C" fn() -> u32;
+
+struct Library {
+    distance: DistanceFn,
+    material: MaterialId,
+}
+
+impl SceneSdf for Library {
+    fn distance(&self, p: Vec3d, t: f64) -> f64 {
+        // the library promised the signature by exporting the ABI version
+        unsafe { (self.distance)(p.x, p.y, p.z, t) }
+    }
+
+    fn material(&self) -> MaterialId {
+        self.material
+    }
+}
+
+#[cfg(unix)]
+mod dl {
+    use std::ffi::{c_char, c_int, c_void, CStr, CString};
+
+    const RTLD_NOW: c_int = 2;
+
+    extern "C" {
+        fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+        fn dlerror() -> *mut c_char;
+    }
+
+    // the library at `path`, loaded for the rest of the process
+    pub fn open(path: &str) -> Result<*mut c_void, String> {
+        let c_path = CString::new(path).map_err(|err| err.to_string())?;
+        let handle = unsafe { dlopen(c_path.as_ptr(), RTLD_NOW) };
+        if handle.is_null() {
+            let message = unsafe { dlerror() };
+            return Err(match message.is_null() {
+                true => "can't load the library".to_string(),
+                false => unsafe { CStr::from_ptr(message) }
+                    .to_string_lossy()
+                    .into_owned(),
+            });
+        }
+        Ok(handle)
+    }
+
+    pub fn symbol(handle: *mut c_void, name: &str) -> Option<*mut c_void> {
+        let c_name = CString::new(name).ok()?;
+        let symbol = unsafe { dlsym(handle, c_name.as_ptr()) };
+        Some(symbol).filter(|symbol| !symbol.is_null())
+    }
+}
+
+#[cfg(not(unix))]
+mod dl {
+    use std::ffi::c_void;
+
+    pub fn open(_path: &str) -> Result<*mut c_void, String> {
+        Err("plugins are only supported on Unix".to_string())
+    }
+
+    pub fn symbol(_handle: *mut c_void, _name: &str) -> Option<*mut c_void> {
+        None
+    }
+}
+
+// Loads the procedural object of the shared library at `path` and registers it under its
+// name, which is returned.
+pub fn load(path: &str) -> Result<String, Error> {
+    let handle = dl::open(path).map_err(|err| Error::io(path, std::io::Error::other(err)))?;
+    let symbol = |name: &str| {
+        dl::symbol(handle, name).ok_or(Error::Parse(format!(
+            "{}: not a rustaboom plugin, '{}' is missing",
+            path, name
+        )))
+    };
+    // the symbols are the functions of the ABI once its version matches
+    let abi: AbiFn = unsafe { std::mem::transmute(symbol("rustaboom_sdf_abi")?) };
+    let version = unsafe { abi() };
+    if version != ABI_VERSION {
+        return Err(Error::Invalid(format!(
+            "{}: plugin ABI version {}, expected {}",
+            path, version, ABI_VERSION
+        )));
+    }
+    let distance: DistanceFn = unsafe { std::mem::transmute(symbol("rustaboom_sdf_distance")?) };
+    let name = match symbol("rustaboom_sdf_name") {
+        Ok(name) => {
+            let name: NameFn = unsafe { std::mem::transmute(name) };
+            let name = unsafe { CStr::from_ptr(name()) };
+            name.to_string_lossy().into_owned()
+        }
+        // `libshape.so` -> `shape`
+        Err(_) => {
+            let stem = Path::new(path)
+                .file_stem()
+                .map_or(path.into(), |stem| stem.to_string_lossy());
+            stem.strip_prefix("lib").unwrap_or(&stem).to_string()
+        }
+    };
+    let material = match symbol("rustaboom_sdf_material") {
+        Ok(material) => {
+            let material: MaterialFn = unsafe { std::mem::transmute(material) };
+            match unsafe { material() } {
+                0 => Shading::Fire,
+                1 => Shading::Diffuse,
+                2 => Shading::Trap,
+                n => {
+                    return Err(Error::Invalid(format!(
+                        "{}: unknown plugin material {}",
+                        path, n
+                    )))
+                }
+            }
+        }
+        Err(_) => Shading::Diffuse,
+    };
+    register(&name, Arc::new(Library { distance, material }));
+    Ok(name)
+}
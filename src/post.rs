@@ -0,0 +1,271 @@
+use crate::color::Color;
+use crate::film::Film;
+use crate::noise::hash;
+use crate::options::Options;
+use crate::render::Frame;
+
+// blur radius of every pyramid level, in pixels of that level
+const LEVEL_SIGMA: f64 = 1.5;
+
+// neighbouring pixels whose depths differ by this fraction, or whose normals are further
+// apart than this cosine, are on either side of an ink line
+const INK_DEPTH: f64 = 0.05;
+const INK_NORMAL: f64 = 0.5;
+
+// range of the luminance histogram in stops
+const HISTOGRAM_MIN: f64 = -16.;
+const HISTOGRAM_MAX: f64 = 8.;
+const HISTOGRAM_BINS: usize = 96;
+// darkest and brightest fractions of the pixels left out of the average
+const HISTOGRAM_LOW: f64 = 0.05;
+const HISTOGRAM_HIGH: f64 = 0.05;
+
+// the sunny 16 rule: f/16 at 1/ISO seconds exposes a sunlit scene
+const SUNNY_APERTURE: f64 = 16.;
+const SUNNY_SHUTTER: f64 = 0.01;
+const SUNNY_ISO: f64 = 100.;
+
+// Post-processing stages applied to the HDR framebuffer before it is quantized.
+pub fn apply(frame: &mut Frame, options: &Options) {
+    let stops = if options.auto_exposure {
+        auto_exposure(&frame.color, options.exposure_key) + options.exposure
+    } else {
+        camera_exposure(options) + options.exposure
+    };
+    if stops != 0. {
+        let scale = stops.exp2();
+        for pixel in frame.color.iter_mut() {
+            *pixel *= scale;
+        }
+    }
+    if options.bloom > 0. {
+        frame.color = bloom(
+            &frame.color,
+            options.bloom_threshold,
+            options.bloom,
+            options.bloom_radius,
+        );
+    }
+    if options.chromatic_aberration > 0. {
+        frame.color = chromatic_aberration(&frame.color, options.chromatic_aberration);
+    }
+    if options.vignette > 0. {
+        vignette(&mut frame.color, options.vignette);
+    }
+    if options.toon > 0 && options.ink > 0 {
+        ink(frame, options.ink);
+    }
+    if options.grain > 0. {
+        grain(&mut frame.color, options.grain);
+    }
+}
+
+// Cel shading outlines: the depth and normal buffers are compared between neighbouring
+// pixels, and where they jump the nearer pixel is inked black, thickened to `thickness`
+// pixels. Silhouettes, creases and overlaps of the scene all get a line.
+pub fn ink(frame: &mut Frame, thickness: usize) {
+    let (w, h) = (frame.color.width(), frame.color.height());
+    let mut edges = Film::new(w, h, false);
+    for y in 0..h {
+        for x in 0..w {
+            for (nx, ny) in [(x + 1, y), (x, y + 1)] {
+                if nx >= w || ny >= h {
+                    continue;
+                }
+                let (d0, d1) = (frame.depth[(x, y)], frame.depth[(nx, ny)]);
+                if d0.is_infinite() && d1.is_infinite() {
+                    continue;
+                }
+                let depth_jump = (d0 - d1).abs() > INK_DEPTH * d0.min(d1);
+                let crease = frame.normal[(x, y)].dot(frame.normal[(nx, ny)]) < INK_NORMAL;
+                if depth_jump || crease {
+                    let nearer = if d1 < d0 { (nx, ny) } else { (x, y) };
+                    edges[nearer] = true;
+                }
+            }
+        }
+    }
+    let reach = thickness - 1;
+    for y in 0..h {
+        for x in 0..w {
+            let mut ys = y.saturating_sub(reach)..=(y + reach).min(h - 1);
+            let inked = ys.any(|sy| {
+                (x.saturating_sub(reach)..=(x + reach).min(w - 1)).any(|sx| edges[(sx, sy)])
+            });
+            if inked {
+                frame.color[(x, y)] = Color::BLACK;
+            }
+        }
+    }
+}
+
+// Exposure value at ISO 100 of an f-number, a shutter time in seconds and an ISO
+// sensitivity, the log2 of the light they let through turned around.
+pub fn ev100(aperture: f64, shutter: f64, iso: f64) -> f64 {
+    (aperture * aperture / shutter * 100. / iso).log2()
+}
+
+// Exposure in stops of the physical camera of the options, 0 without one. The sensor
+// saturates at the luminance 1.2 * 2^EV100 (the saturation-based speed of ISO 12232 with a
+// lens letting 65% through), which is mapped to 1.
+fn camera_exposure(options: &Options) -> f64 {
+    if options.aperture.is_none() && options.shutter.is_none() && options.iso.is_none() {
+        return 0.;
+    }
+    let ev = ev100(
+        options.aperture.unwrap_or(SUNNY_APERTURE),
+        options.shutter.unwrap_or(SUNNY_SHUTTER),
+        options.iso.unwrap_or(SUNNY_ISO),
+    );
+    -ev - 1.2f64.log2()
+}
+
+// Exposure in stops that brings the log-average luminance of the image to `key`. The
+// average is taken over a histogram of log2 luminance without its darkest and brightest
+// tails, so a few black background pixels or hot spots don't throw it off. Pixels with
+// no light at all, like a transparent background, are not counted.
+pub fn auto_exposure(image: &Film<Color>, key: f64) -> f64 {
+    let width = (HISTOGRAM_MAX - HISTOGRAM_MIN) / HISTOGRAM_BINS as f64;
+    let mut histogram = [0usize; HISTOGRAM_BINS];
+    for pixel in image.iter() {
+        let lum = pixel.luminance();
+        if lum > 0. {
+            let bin = ((lum.log2() - HISTOGRAM_MIN) / width) as i64;
+            histogram[bin.clamp(0, HISTOGRAM_BINS as i64 - 1) as usize] += 1;
+        }
+    }
+    let total: usize = histogram.iter().sum();
+    if total == 0 {
+        return 0.;
+    }
+
+    let (low, high) = (
+        HISTOGRAM_LOW * total as f64,
+        (1. - HISTOGRAM_HIGH) * total as f64,
+    );
+    let (mut seen, mut sum, mut count) = (0., 0., 0.);
+    for (bin, n) in histogram.iter().enumerate() {
+        // the part of the bin between the two cut-offs
+        let kept = (seen + *n as f64).min(high) - seen.max(low);
+        if kept > 0. {
+            sum += kept * (HISTOGRAM_MIN + (bin as f64 + 0.5) * width);
+            count += kept;
+        }
+        seen += *n as f64;
+    }
+    if count == 0. {
+        return 0.;
+    }
+    key.log2() - sum / count
+}
+
+// squared distance to the image center, 1 in the corners
+fn radius2(x: usize, y: usize, w: usize, h: usize) -> f64 {
+    let dx = (x as f64 + 0.5) / w as f64 * 2. - 1.;
+    let dy = (y as f64 + 0.5) / h as f64 * 2. - 1.;
+    (dx * dx + dy * dy) / 2.
+}
+
+// Lateral aberration: red is magnified and blue shrunk by `amount` at the corners.
+pub fn chromatic_aberration(image: &Film<Color>, amount: f64) -> Film<Color> {
+    let (w, h) = (image.width(), image.height());
+    Film::from_fn(w, h, |x, y| {
+        let u = (x as f64 + 0.5) / w as f64 - 0.5;
+        let v = (y as f64 + 0.5) / h as f64 - 0.5;
+        let scale = amount * radius2(x, y, w, h);
+        let red = image.sample_bilinear(0.5 + u / (1. + scale), 0.5 + v / (1. + scale));
+        let blue = image.sample_bilinear(0.5 + u * (1. + scale), 0.5 + v * (1. + scale));
+        Color::new(red.r, image[(x, y)].g, blue.b)
+    })
+}
+
+pub fn vignette(image: &mut Film<Color>, strength: f64) {
+    let (w, h) = (image.width(), image.height());
+    for y in 0..h {
+        for x in 0..w {
+            let falloff = (1. - strength * radius2(x, y, w, h)).max(0.);
+            image[(x, y)] *= falloff * falloff;
+        }
+    }
+}
+
+// Zero-mean grain, strongest in the midtones like on film stock.
+pub fn grain(image: &mut Film<Color>, amount: f64) {
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let (fx, fy) = (x as f64, y as f64);
+            let n = hash(fx * 12.9898 + fy * 78.233) + hash(fx * 39.346 + fy * 11.135) - 1.;
+            let lum = image[(x, y)].average().clamp(0., 1.);
+            let response = 4. * lum * (1. - lum);
+            image[(x, y)] *= 1. + n * amount * response;
+        }
+    }
+}
+
+fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+    let radius = (3. * sigma).ceil() as isize;
+    let kernel: Vec<f64> = (-radius..=radius)
+        .map(|x| (-((x * x) as f64) / (2. * sigma * sigma)).exp())
+        .collect();
+    let sum: f64 = kernel.iter().sum();
+    kernel.iter().map(|k| k / sum).collect()
+}
+
+fn blur(image: &Film<Color>, sigma: f64) -> Film<Color> {
+    let kernel = gaussian_kernel(sigma);
+    let radius = (kernel.len() / 2) as isize;
+    let pass = |src: &Film<Color>, horizontal: bool| {
+        Film::from_fn(src.width(), src.height(), |x, y| {
+            let mut sum = Color::BLACK;
+            for (k, weight) in kernel.iter().enumerate() {
+                let o = k as isize - radius;
+                let (x, y) = (x as isize, y as isize);
+                sum += match horizontal {
+                    true => src.get_clamped(x + o, y),
+                    false => src.get_clamped(x, y + o),
+                } * *weight;
+            }
+            sum
+        })
+    };
+    pass(&pass(image, true), false)
+}
+
+fn downsample(image: &Film<Color>) -> Film<Color> {
+    let (w, h) = (image.width(), image.height());
+    Film::from_fn((w / 2).max(1), (h / 2).max(1), |x, y| {
+        let mut sum = Color::BLACK;
+        for (sx, sy) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+            sum += image[((2 * x + sx).min(w - 1), (2 * y + sy).min(h - 1))];
+        }
+        sum / 4.
+    })
+}
+
+// Bright-pass everything above `threshold`, blur it on a Gaussian pyramid reaching
+// `radius` pixels and add the sum of all levels back scaled by `intensity`.
+pub fn bloom(image: &Film<Color>, threshold: f64, intensity: f64, radius: f64) -> Film<Color> {
+    let (w, h) = (image.width(), image.height());
+    let mut level = image.map(|c| (c - threshold).max(Color::BLACK));
+    let mut glow = Film::new(w, h, Color::BLACK);
+    let mut levels = 0;
+    let mut reach = LEVEL_SIGMA;
+    loop {
+        let blurred = blur(&level, LEVEL_SIGMA);
+        for y in 0..h {
+            for x in 0..w {
+                let (u, v) = ((x as f64 + 0.5) / w as f64, (y as f64 + 0.5) / h as f64);
+                glow[(x, y)] += blurred.sample_bilinear(u, v);
+            }
+        }
+        levels += 1;
+        reach *= 2.;
+        if reach > radius || level.width() <= 1 || level.height() <= 1 {
+            break;
+        }
+        level = downsample(&blurred);
+    }
+
+    let scale = intensity / levels as f64;
+    image.zip(&glow, |c, g| c + g * scale)
+}
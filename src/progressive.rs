@@ -0,0 +1,281 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::error::Error;
+use crate::film::Film;
+use crate::metadata::Stats;
+use crate::options::Options;
+use crate::render::{finish, render, render_pass, Frame};
+use crate::scene::Scene;
+use crate::vec3d::Vec3d;
+
+// how often the scene files are checked once the render has converged
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+// how far, relative to its depth, a point can be from the one a pixel saw before a camera
+// move for the pixel to keep its history
+const DEPTH_TOLERANCE: f64 = 0.05;
+
+fn modified(paths: &[String]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+// the settings that place the camera
+const CAMERA_KEYS: [&str; 4] = [
+    "camera-position",
+    "camera-target",
+    "camera-orbit",
+    "camera-orbit-end",
+];
+
+// the settings other than the camera placement, options that agree on them only differ by
+// a camera move
+fn settings_but_camera(options: &Options) -> Vec<&(String, String)> {
+    options
+        .settings
+        .iter()
+        .filter(|(key, _)| !CAMERA_KEYS.contains(&key.as_str()))
+        .collect()
+}
+
+// Sums of the passes of the preview, per pixel as after a camera move only some of the
+// pixels find their history again.
+struct Accumulation {
+    camera: Camera,
+    // straight colors, or with alpha weighted by their coverage to average them
+    sum: Film<Color>,
+    alpha_sum: Film<f64>,
+    // passes summed into every pixel
+    weight: Film<f64>,
+    // depth of the latest pass, where the history of a pixel is looked up after a move
+    depth: Film<f64>,
+}
+
+impl Accumulation {
+    fn new(camera: Camera) -> Accumulation {
+        let (width, height) = (camera.width, camera.height);
+        Accumulation {
+            camera,
+            sum: Film::new(width, height, Color::BLACK),
+            alpha_sum: Film::new(width, height, 0.),
+            weight: Film::new(width, height, 0.),
+            depth: Film::new(width, height, f64::INFINITY),
+        }
+    }
+
+    fn add(&mut self, frame: &Frame, alpha: bool) {
+        for (x, y, c) in frame.color.pixels() {
+            let a = frame.alpha[(x, y)];
+            self.sum[(x, y)] += if alpha { c * a } else { c };
+            self.alpha_sum[(x, y)] += a;
+            self.weight[(x, y)] += 1.;
+        }
+        self.depth.clone_from(&frame.depth);
+    }
+
+    // Temporal reprojection: every pixel of `frame`, the first pass from the new camera,
+    // takes up to `history` passes of the pixel of `previous` that saw the same point, or
+    // the same direction of the background. Points that were hidden or off-screen before
+    // the move start over.
+    fn reproject(&mut self, previous: &Accumulation, frame: &Frame, history: usize) {
+        let (old, new) = (&previous.camera, &self.camera);
+        for j in 0..new.height {
+            for i in 0..new.width {
+                let dir = new.ray_dir(i as f64 + 0.5, j as f64 + 0.5);
+                let depth = frame.depth[(i, j)];
+                // the background only depends on the direction, which `project` turns
+                // into the old camera's view
+                let offset = match depth.is_finite() {
+                    true => new.position + dir * depth - old.position,
+                    false => dir,
+                };
+                let (x, y) = match old.project(offset) {
+                    Some(p) if p.x >= 0. && p.y >= 0. => (p.x as usize, p.y as usize),
+                    _ => continue,
+                };
+                if x >= old.width || y >= old.height {
+                    continue;
+                }
+                let old_depth = previous.depth[(x, y)];
+                let same = match (depth.is_finite(), old_depth.is_finite()) {
+                    (true, true) => {
+                        let expected = offset.length();
+                        (old_depth - expected).abs() <= DEPTH_TOLERANCE * expected
+                    }
+                    (false, false) => true,
+                    _ => false,
+                };
+                let weight = previous.weight[(x, y)];
+                if !same || weight == 0. {
+                    continue;
+                }
+                let keep = weight.min(history as f64) / weight;
+                self.sum[(i, j)] = previous.sum[(x, y)] * keep;
+                self.alpha_sum[(i, j)] = previous.alpha_sum[(x, y)] * keep;
+                self.weight[(i, j)] = weight * keep;
+            }
+        }
+    }
+
+    // the average of the passes, with the AOVs that guide the denoiser
+    fn frame(&self, normal: Film<Vec3d>, depth: Film<f64>, samples: usize, alpha: bool) -> Frame {
+        let (width, height) = (self.camera.width, self.camera.height);
+        let color = Film::from_fn(width, height, |x, y| {
+            let (c, a, w) = (
+                self.sum[(x, y)],
+                self.alpha_sum[(x, y)],
+                self.weight[(x, y)],
+            );
+            match alpha {
+                true if a > 0. => c / a,
+                true => Color::BLACK,
+                false => c / w,
+            }
+        });
+        Frame {
+            color,
+            normal,
+            depth,
+            alpha: self.alpha_sum.zip(&self.weight, |a, w| a / w),
+            samples,
+        }
+    }
+}
+
+// Passes of `spp` samples from the camera of the options, accumulated on top of the
+// history `previous` left by a camera move, until a scene file changes.
+fn accumulate(options: &Options, previous: Option<Accumulation>) -> Result<Accumulation, Error> {
+    let mut previous = previous;
+    let camera = crate::default_camera(options);
+    let scene = Scene::new(options);
+    let watched = modified(&options.scene_files);
+    let mut accumulation = Accumulation::new(camera);
+    let mut aov = None;
+    let mut pass = 0;
+    let start = Instant::now();
+    let mut samples = 0;
+    while modified(&options.scene_files) == watched {
+        if pass == options.passes {
+            std::thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+        let frame = render_pass(&camera, options, &scene, pass);
+        samples += frame.samples;
+        if let Some(previous) = previous.take() {
+            accumulation.reproject(&previous, &frame, options.history);
+        }
+        accumulation.add(&frame, options.alpha);
+        pass += 1;
+        // the first pass provides the AOVs that guide the denoiser
+        let (normal, depth) = aov.get_or_insert((frame.normal, frame.depth)).clone();
+        let mut accumulated = accumulation.frame(normal, depth, samples, options.alpha);
+        finish(&mut accumulated, &camera, options, &scene);
+        let stats = Stats {
+            samples,
+            render: start.elapsed(),
+            ..Stats::default()
+        };
+        crate::output(
+            options,
+            &options.output,
+            &accumulated.image(options.alpha),
+            stats,
+        )?;
+        info!(
+            "pass {}/{}, {} spp",
+            pass,
+            options.passes,
+            pass * options.spp.max(1)
+        );
+    }
+    Ok(accumulation)
+}
+
+// Shadertoy-style live preview: every frame is rendered afresh at the time elapsed since
+// the start, on top of `time`, with the mouse position last read from `mouse_file`, and
+// replaces the image. Returns once a scene file changes.
+fn live(options: &Options) -> Result<(), Error> {
+    let watched = modified(&options.scene_files);
+    let camera = crate::default_camera(options);
+    let start = Instant::now();
+    let mut mouse = None;
+    let mut frames = 0;
+    while modified(&options.scene_files) == watched {
+        let frame_start = Instant::now();
+        let mut frame_options = options.clone();
+        // set like any other option, so the metadata of the image reproduces the frame
+        let time = options.time + start.elapsed().as_secs_f64();
+        frame_options.set("time", &time.to_string())?;
+        if let Some(path) = &options.mouse_file {
+            // the file can be missing or half-written, the last position stays then
+            let position = std::fs::read_to_string(path).unwrap_or_default();
+            let position = position.trim();
+            let coordinate = |c: &str| c.trim().parse::<f64>().is_ok_and(f64::is_finite);
+            if let Some((x, y)) = position.split_once(',') {
+                if coordinate(x) && coordinate(y) {
+                    mouse = Some(position.to_string());
+                }
+            }
+        }
+        if let Some(position) = &mouse {
+            frame_options.set("mouse", position)?;
+        }
+        let frame = render(&camera, &frame_options);
+        let stats = Stats {
+            samples: frame.samples,
+            render: frame_start.elapsed(),
+            ..Stats::default()
+        };
+        let image = frame.image(frame_options.alpha);
+        crate::output(&frame_options, &options.output, &image, stats)?;
+        frames += 1;
+        info!(
+            "frame {}, t = {:.3}, {:.1} fps",
+            frames,
+            time,
+            1. / frame_start.elapsed().as_secs_f64()
+        );
+    }
+    Ok(())
+}
+
+// Progressive preview: passes of `spp` samples are accumulated and the output image is
+// rewritten after every one of them, for a viewer that reloads it. The scene files are
+// watched, and saving one of them restarts the render with the options parsed anew; when
+// only the camera moved, the pixels keep the history they can find again, so the preview
+// stays smooth while the camera is moved around and converges once it stops. With `live`
+// the frames are animated instead.
+// Only returns when an image can't be written.
+pub fn run(args: &[String], options: Options) -> Result<(), Error> {
+    let mut options = options;
+    // the passes before a camera move
+    let mut previous: Option<Accumulation> = None;
+    loop {
+        let last = match options.live {
+            true => {
+                live(&options)?;
+                None
+            }
+            false => Some(accumulate(&options, previous.take())?),
+        };
+        match Options::load(args.iter().cloned()) {
+            Ok(reloaded) => {
+                let moved = settings_but_camera(&reloaded) == settings_but_camera(&options)
+                    && reloaded.history > 0;
+                match last.filter(|_| moved) {
+                    Some(last) => {
+                        info!("camera moved, reprojecting");
+                        previous = Some(last);
+                    }
+                    None => info!("scene changed, restarting"),
+                }
+                options = reloaded;
+            }
+            // keep the last good options until the file is fixed
+            Err(err) => eprintln!("error: {}", err),
+        }
+    }
+}
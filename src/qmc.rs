@@ -0,0 +1,129 @@
+#![allow(dead_code)]
+// Quasi-Monte Carlo sequences. Their points fill [0, 1)^n more evenly than random ones,
+// and scrambled they stay as even while every pixel gets points of its own.
+
+// draws with a weight below this don't show in 32-bit samples
+const PRECISION: f64 = 1. / 4_294_967_296.;
+
+// Position of `i` in a pseudo-random permutation of 0..n picked by `seed`, for any n
+// (Kensler 2013, "Correlated Multi-Jittered Sampling").
+pub fn permute(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    // a permutation of the next power of two, walked until it lands inside 0..n
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            return i.wrapping_add(seed) % n;
+        }
+    }
+}
+
+// integer hash of 32 bits (Wellons' lowbias32)
+pub fn mix(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^ (x >> 16)
+}
+
+// the digits of `index` in `base` mirrored around the radix point
+pub fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inv_base = 1. / f64::from(base);
+    let mut inv = inv_base;
+    let mut result = 0.;
+    while index > 0 {
+        result += f64::from(index % base) * inv;
+        index /= base;
+        inv *= inv_base;
+    }
+    result
+}
+
+// `radical_inverse` with every digit position through its own permutation of the digits
+// picked by `seed`. The leading zeros of the index are permuted as well, down to the
+// precision of a sample.
+pub fn scrambled_radical_inverse(base: u32, mut index: u32, seed: u32) -> f64 {
+    let inv_base = 1. / f64::from(base);
+    let mut inv = inv_base;
+    let mut result = 0.;
+    let mut position = 0;
+    while inv > PRECISION {
+        let digit = permute(index % base, base, mix(seed ^ position));
+        result += f64::from(digit) * inv;
+        index /= base;
+        inv *= inv_base;
+        position += 1;
+    }
+    result
+}
+
+// the first two dimensions of the Sobol sequence: van der Corput and the x + 1 polynomial,
+// as 32-bit fractions
+pub fn sobol_2d(index: u32) -> (u32, u32) {
+    let mut v = 1u32 << 31;
+    let (mut a, mut b) = (0, 0);
+    let mut i = index;
+    let mut bit = 0;
+    while i > 0 {
+        if i & 1 == 1 {
+            a ^= 1 << (31 - bit);
+            b ^= v;
+        }
+        v ^= v >> 1;
+        i >>= 1;
+        bit += 1;
+    }
+    (a, b)
+}
+
+// permutes the low bits, each depending only on the bits below it
+// (Laine and Karras 2011, constants of Burley 2020)
+fn laine_karras(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+// Owen scrambling of a 32-bit fraction: every bit is flipped or not depending on the bits
+// above it, which keeps the stratification of base 2 sequences.
+pub fn owen_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Point `index` of a 2D Sobol sequence Owen-scrambled by `seed` (Burley 2020, "Practical
+// Hash-based Owen Scrambling"). The index is shuffled as well, so pairs of dimensions with
+// different seeds don't correlate although they come from the same two dimensions.
+pub fn owen_sobol_2d(index: u32, seed: u32) -> (f64, f64) {
+    let (a, b) = sobol_2d(owen_scramble(index, mix(seed)));
+    let unit = |bits: u32| f64::from(bits) / 4_294_967_296.;
+    (
+        unit(owen_scramble(a, mix(seed ^ 0x9e37_79b9))),
+        unit(owen_scramble(b, mix(seed ^ 0x85eb_ca6b))),
+    )
+}
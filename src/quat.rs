@@ -0,0 +1,178 @@
+use core::ops::{Mul, MulAssign, Neg};
+
+use crate::matrix::Mat3;
+#[cfg(not(feature = "std"))]
+use crate::vec3d::Float;
+use crate::vec3d::Vec3d;
+
+// Rotation as a unit quaternion `w + xi + yj + zk`. Unlike Euler angles, rotations
+// interpolate along the shortest arc with `slerp` and never lock up.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Quat {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat {
+        x: 0.,
+        y: 0.,
+        z: 0.,
+        w: 1.,
+    };
+
+    pub fn new(x: f64, y: f64, z: f64, w: f64) -> Quat {
+        Quat { x, y, z, w }
+    }
+
+    // the vector part
+    pub fn xyz(self) -> Vec3d {
+        Vec3d::new(self.x, self.y, self.z)
+    }
+
+    // counterclockwise by `angle` radians about `axis`, looking down the axis
+    pub fn from_axis_angle(axis: Vec3d, angle: f64) -> Quat {
+        let (s, c) = (angle / 2.).sin_cos();
+        let v = axis.normalized() * s;
+        Quat::new(v.x, v.y, v.z, c)
+    }
+
+    // Euler angles in radians, applied about x first, then y, then z.
+    pub fn from_euler(angles: Vec3d) -> Quat {
+        Quat::from_axis_angle(Vec3d::new(0., 0., 1.), angles.z)
+            * Quat::from_axis_angle(Vec3d::new(0., 1., 0.), angles.y)
+            * Quat::from_axis_angle(Vec3d::new(1., 0., 0.), angles.x)
+    }
+
+    // the axis and angle in radians, any axis for the identity
+    pub fn to_axis_angle(self) -> (Vec3d, f64) {
+        let q = self.normalized();
+        let s = q.xyz().length();
+        if s == 0. {
+            return (Vec3d::new(1., 0., 0.), 0.);
+        }
+        (q.xyz() / s, 2. * s.atan2(q.w))
+    }
+
+    pub fn dot(self, other: Quat) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn length(self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalized(self) -> Quat {
+        let scale = 1. / self.length();
+        Quat::new(
+            self.x * scale,
+            self.y * scale,
+            self.z * scale,
+            self.w * scale,
+        )
+    }
+
+    // the opposite rotation for unit quaternions
+    pub fn conjugate(self) -> Quat {
+        Quat::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn inverse(self) -> Quat {
+        let norm = self.dot(self);
+        let c = self.conjugate();
+        Quat::new(c.x / norm, c.y / norm, c.z / norm, c.w / norm)
+    }
+
+    pub fn rotate(self, v: Vec3d) -> Vec3d {
+        // v + 2w (q x v) + 2 q x (q x v), cheaper than q v q*
+        let q = self.xyz();
+        let t = q.cross(v) * 2.;
+        v + t * self.w + q.cross(t)
+    }
+
+    // Spherical linear interpolation from `self` at 0 to `other` at 1 at constant angular
+    // speed, along the shorter of the two arcs.
+    pub fn slerp(self, other: Quat, t: f64) -> Quat {
+        let mut cos = self.dot(other);
+        let mut other = other;
+        if cos < 0. {
+            cos = -cos;
+            other = -other;
+        }
+        // nearly the same rotation, the normalized linear blend is exact enough
+        let (a, b) = if cos > 0.9995 {
+            (1. - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1. - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+        Quat::new(
+            self.x * a + other.x * b,
+            self.y * a + other.y * b,
+            self.z * a + other.z * b,
+            self.w * a + other.w * b,
+        )
+        .normalized()
+    }
+
+    pub fn to_mat3(self) -> Mat3 {
+        Mat3::from_cols(
+            self.rotate(Vec3d::new(1., 0., 0.)),
+            self.rotate(Vec3d::new(0., 1., 0.)),
+            self.rotate(Vec3d::new(0., 0., 1.)),
+        )
+    }
+}
+
+impl Default for Quat {
+    fn default() -> Quat {
+        Quat::IDENTITY
+    }
+}
+
+// Hamilton product, `a * b` rotates by `b` first and then by `a`
+impl Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, b: Quat) -> Quat {
+        let a = self;
+        Quat::new(
+            a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+            a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+        )
+    }
+}
+
+impl MulAssign for Quat {
+    fn mul_assign(&mut self, other: Quat) {
+        *self = *self * other;
+    }
+}
+
+impl Mul<Vec3d> for Quat {
+    type Output = Vec3d;
+
+    fn mul(self, v: Vec3d) -> Vec3d {
+        self.rotate(v)
+    }
+}
+
+// the same rotation
+impl Neg for Quat {
+    type Output = Quat;
+
+    fn neg(self) -> Quat {
+        Quat::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
+
+impl From<Quat> for Mat3 {
+    fn from(q: Quat) -> Mat3 {
+        q.to_mat3()
+    }
+}
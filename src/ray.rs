@@ -0,0 +1,42 @@
+use crate::vec3d::Vec3d;
+
+// Half-line from `origin` along the unit direction `dir`.
+#[derive(Copy, Clone, Debug)]
+pub struct Ray {
+    pub origin: Vec3d,
+    pub dir: Vec3d,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3d, dir: Vec3d) -> Ray {
+        Ray { origin, dir }
+    }
+
+    // the point `t` along the ray
+    pub fn at(&self, t: f64) -> Vec3d {
+        self.origin + self.dir * t
+    }
+}
+
+// Surface point found by a ray, `object` indexes `Scene::objects`.
+#[derive(Copy, Clone, Debug)]
+pub struct Hit {
+    pub position: Vec3d,
+    // traveled along the ray from its origin
+    pub distance: f64,
+    // marching steps it took
+    pub steps: usize,
+    pub object: usize,
+}
+
+impl Hit {
+    // a hit of the marchers, which don't know the object yet
+    pub fn new(position: Vec3d, distance: f64, steps: usize) -> Hit {
+        Hit {
+            position,
+            distance,
+            steps,
+            object: 0,
+        }
+    }
+}
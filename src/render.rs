@@ -0,0 +1,446 @@
+use std::ops::Range;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::bench;
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::denoise::denoise;
+use crate::distributed;
+use crate::embers;
+use crate::film::{Band, Film, PixelFilter, Splat};
+use crate::image::Image;
+use crate::integrator::{self, Aov, Integrator};
+use crate::log;
+use crate::options::Options;
+use crate::post;
+use crate::ray::Ray;
+use crate::sampler::{PixelSampler, Sampler};
+use crate::scene::Scene;
+use crate::sky::{backdrop, background, Backdrop};
+use crate::vec3d::Vec3d;
+use crate::volume::{self, volume};
+
+pub struct Frame {
+    pub color: Film<Color>,
+    pub normal: Film<Vec3d>,
+    pub depth: Film<f64>,
+    // fraction of the pixel covered by the scene, the hit mask of the alpha channel
+    pub alpha: Film<f64>,
+    // camera rays traced for the frame
+    pub samples: usize,
+}
+
+impl Frame {
+    // what camera rays that all miss the scene leave, before any is traced
+    pub fn new(width: usize, height: usize) -> Frame {
+        Frame {
+            color: Film::new(width, height, Color::BLACK),
+            normal: Film::new(width, height, Vec3d::zero()),
+            depth: Film::new(width, height, f64::INFINITY),
+            alpha: Film::new(width, height, 0.),
+            samples: 0,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.color.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.color.height()
+    }
+
+    // the color, with the coverage as its alpha channel if `alpha` is asked for
+    pub fn image(self, alpha: bool) -> Image {
+        Image::new(self.color, Some(self.alpha).filter(|_| alpha))
+    }
+}
+
+// With `edges` a direct shading ray that misses the scene takes the silhouette it passes
+// closest to, the anti-aliasing of a pixel traced with a single centered ray.
+#[allow(clippy::too_many_arguments)]
+fn trace(
+    camera: &Camera,
+    options: &Options,
+    scene: &Scene,
+    x: f64,
+    y: f64,
+    edges: bool,
+    sampler: &mut PixelSampler,
+    aov: &mut Aov,
+) -> Color {
+    let ray = match options.lens.radius > 0. {
+        true => camera.lens_ray(&options.lens, x, y, sampler.next_2d()),
+        false => Ray::new(camera.position, camera.ray_dir(x, y)),
+    };
+    let (orig, dir) = (ray.origin, ray.dir);
+    let color = match options.integrator {
+        Integrator::Direct if edges => match scene.intersect(ray) {
+            Some(hit) => integrator::shade(options, scene, &hit, dir, sampler, aov),
+            None => {
+                let spread = camera.pixel_spread();
+                integrator::silhouette(options, scene, ray, spread, sampler, aov)
+            }
+        },
+        Integrator::Direct => integrator::direct(options, scene, orig, dir, sampler, aov),
+        Integrator::Path => integrator::path(options, scene, orig, dir, sampler, aov),
+        Integrator::Volume => volume(options, scene, orig, dir, sampler, aov),
+    };
+    // the part of the sky the scene lets through is swapped for the backdrop, with `alpha`
+    // it is taken out to leave the color premultiplied
+    let matte = match (options.alpha, options.background) {
+        (false, Backdrop::Sky) => return color,
+        (true, _) => Color::BLACK,
+        (false, _) => backdrop(options, dir, x, y),
+    };
+    color + (matte - background(options, dir)) * (1. - aov.opacity)
+}
+
+// straight color of a premultiplied one
+fn unpremultiply(color: Color, alpha: f64) -> Color {
+    if alpha > 0. {
+        color / alpha
+    } else {
+        Color::BLACK
+    }
+}
+
+// Supersamples a pixel with jittered rays. With a non-zero `target_error` sampling stops
+// as soon as the standard error of the mean drops below that fraction of the pixel value,
+// so flat regions settle after `min_spp` samples and only noisy ones go up to `spp`.
+// Pass `pass` of a progressive render continues with the samples after the previous passes.
+// With `alpha` the background is left out of the color, and the fraction of it hidden by
+// the scene is returned along, and so is the number of samples taken. Every sample is also
+// handed to `splat` with its image point and coverage, its color premultiplied with `alpha`.
+#[allow(clippy::too_many_arguments)]
+fn sample_pixel(
+    camera: &Camera,
+    options: &Options,
+    scene: &Scene,
+    sampler: &Sampler,
+    pass: usize,
+    i: usize,
+    j: usize,
+    mut splat: impl FnMut(f64, f64, Color, f64),
+) -> (Color, Aov, f64, usize) {
+    if options.spp <= 1 && pass == 0 {
+        let mut pixel_sampler = sampler.pixel(i, j, 0);
+        let mut aov = Aov::default();
+        let (x, y) = (i as f64 + 0.5, j as f64 + 0.5);
+        let edges = options.edge_aa;
+        let color = trace(
+            camera,
+            options,
+            scene,
+            x,
+            y,
+            edges,
+            &mut pixel_sampler,
+            &mut aov,
+        );
+        splat(x, y, color, aov.opacity);
+        let color = if options.alpha {
+            unpremultiply(color, aov.opacity)
+        } else {
+            color
+        };
+        return (color, aov, aov.opacity, 1);
+    }
+
+    let mut sum = Color::BLACK;
+    let mut opacity_sum = 0.;
+    let mut lum_sum = 0.;
+    let mut lum_sq_sum = 0.;
+    // AOVs are averaged over the samples that hit something
+    let mut normal_sum = Vec3d::zero();
+    let mut depth_sum = 0.;
+    let mut hits = 0;
+    let mut n = 0;
+    while n < options.spp.max(1) {
+        let mut pixel_sampler = sampler.pixel(i, j, pass * options.spp.max(1) + n);
+        let mut aov = Aov::default();
+        let jitter = pixel_sampler.next_2d();
+        let (x, y) = (i as f64 + jitter.x, j as f64 + jitter.y);
+        let color = trace(
+            camera,
+            options,
+            scene,
+            x,
+            y,
+            false,
+            &mut pixel_sampler,
+            &mut aov,
+        );
+        splat(x, y, color, aov.opacity);
+        let lum = color.average();
+        sum += color;
+        lum_sum += lum;
+        lum_sq_sum += lum * lum;
+        opacity_sum += aov.opacity;
+        if aov.depth.is_finite() {
+            normal_sum += aov.normal;
+            depth_sum += aov.depth;
+            hits += 1;
+        }
+        n += 1;
+
+        if options.target_error > 0. && n >= options.min_spp.max(2) {
+            let nf = n as f64;
+            let mean = lum_sum / nf;
+            let variance = (lum_sq_sum / nf - mean * mean).max(0.) / (nf - 1.);
+            if variance.sqrt() <= options.target_error * mean.max(1e-3) {
+                break;
+            }
+        }
+    }
+
+    let mut aov = Aov::default();
+    if hits > 0 {
+        aov.normal = normal_sum.normalized();
+        aov.depth = depth_sum / hits as f64;
+    }
+    let color = if options.alpha {
+        unpremultiply(sum, opacity_sum)
+    } else {
+        sum / n as f64
+    };
+    (color, aov, opacity_sum / n as f64, n)
+}
+
+// rows of the image in a tile, the unit of work of the render threads
+const TILE_ROWS: usize = 16;
+
+// the same rows of the frame buffers, from row `top` of the image
+struct Tile<'a> {
+    top: usize,
+    color: Band<'a, Color>,
+    normal: Band<'a, Vec3d>,
+    depth: Band<'a, f64>,
+    alpha: Band<'a, f64>,
+}
+
+// whether the camera ray through the image point (x, y) sees anything but the background
+fn sees_scene(camera: &Camera, options: &Options, scene: &Scene, x: f64, y: f64) -> bool {
+    let dir = camera.ray_dir(x, y);
+    let medium = matches!(options.integrator, Integrator::Volume)
+        && volume::crosses(scene, camera.position, dir);
+    medium || scene.intersect(Ray::new(camera.position, dir)).is_some()
+}
+
+// Coarse pass over the `rows` from `top`: the pixels of the blocks `coarse` pixels wide
+// whose corner rays all miss the scene, which only see the background. Details of the
+// scene smaller than a block can fall between the corners and are lost with them.
+fn background_pixels(
+    camera: &Camera,
+    options: &Options,
+    scene: &Scene,
+    top: usize,
+    rows: usize,
+) -> Film<bool> {
+    let size = options.coarse;
+    let (columns, lines) = (camera.width.div_ceil(size), rows.div_ceil(size));
+    let corner = |cx: usize, cy: usize| {
+        let x = (cx * size).min(camera.width) as f64;
+        let y = (top + (cy * size).min(rows)) as f64;
+        sees_scene(camera, options, scene, x, y)
+    };
+    let corners = Film::from_fn(columns + 1, lines + 1, corner);
+    Film::from_fn(camera.width, rows, |x, y| {
+        let (cx, cy) = (x / size, y / size);
+        !(corners[(cx, cy)]
+            || corners[(cx + 1, cy)]
+            || corners[(cx, cy + 1)]
+            || corners[(cx + 1, cy + 1)])
+    })
+}
+
+// Traces a tile, returning the number of samples taken. With a reconstruction filter
+// reaching past the pixel a sample is taken in, the samples are splatted over the tile, and
+// the rows next to it are traced again for the samples that reach into it.
+fn render_tile(
+    camera: &Camera,
+    options: &Options,
+    scene: &Scene,
+    sampler: &Sampler,
+    pass: usize,
+    mut tile: Tile,
+) -> usize {
+    let start = Instant::now();
+    let rows = tile.color.height();
+    let mut tile_samples = 0;
+    // the corner rays of the coarse pass leave from the pinhole, which a lens blurs the
+    // scene past
+    let pinhole = options.lens.radius == 0.;
+    let background = match options.coarse {
+        0 => None,
+        _ if !pinhole => None,
+        _ => Some(background_pixels(camera, options, scene, tile.top, rows)),
+    };
+    let is_background = |x: usize, y: usize| background.as_ref().is_some_and(|b| b[(x, y)]);
+    let filter = options.pixel_filter();
+    let filtered = filter != PixelFilter::BOX;
+    let mut splats = filtered.then(|| Film::new(camera.width, rows, Splat::default()));
+    // splats a sample at the image point (x, y) into the tile
+    let top = tile.top as f64;
+    let mut splat = |x: f64, y: f64, color: Color, coverage: f64| {
+        if let Some(splats) = &mut splats {
+            splats.splat(x, y - top, color, coverage, filter);
+        }
+    };
+    for y in 0..rows {
+        for i in 0..camera.width {
+            // the other buffers already hold what a miss leaves in them
+            if is_background(i, y) {
+                let (x, y_image) = (i as f64 + 0.5, (tile.top + y) as f64 + 0.5);
+                let color = match options.alpha {
+                    true => Color::BLACK,
+                    false => backdrop(options, camera.ray_dir(x, y_image), x, y_image),
+                };
+                tile.color.set(i, y, color);
+                splat(x, y_image, color, 0.);
+                continue;
+            }
+            let (color, aov, coverage, samples) = sample_pixel(
+                camera,
+                options,
+                scene,
+                sampler,
+                pass,
+                i,
+                tile.top + y,
+                &mut splat,
+            );
+            tile_samples += samples;
+            tile.color.set(i, y, color);
+            tile.normal.set(i, y, aov.normal);
+            tile.depth.set(i, y, aov.depth);
+            tile.alpha.set(i, y, coverage);
+        }
+    }
+    if filtered {
+        // the rows within the margin above and below the tile, not counted as the samples
+        // of the tile
+        let margin = filter.margin();
+        let above = tile.top.saturating_sub(margin)..tile.top;
+        let below = tile.top + rows..(tile.top + rows + margin).min(camera.height);
+        for j in above.chain(below) {
+            for i in 0..camera.width {
+                sample_pixel(camera, options, scene, sampler, pass, i, j, &mut splat);
+            }
+        }
+    }
+    if let Some(splats) = &splats {
+        for (x, y, s) in splats.pixels() {
+            let color = match options.alpha {
+                true => unpremultiply(s.sum, s.coverage).max(Color::BLACK),
+                false => s.color(),
+            };
+            tile.color.set(x, y, color);
+            tile.alpha.set(x, y, s.coverage());
+        }
+    }
+    debug!(
+        "pass {} rows {}..{}: {} samples in {:.3} s",
+        pass,
+        tile.top,
+        tile.top + rows,
+        tile_samples,
+        start.elapsed().as_secs_f64()
+    );
+    tile_samples
+}
+
+// Traces one pass of `spp` samples per pixel, without any image-space processing, on the
+// `workers` machines if there are any.
+pub fn render_pass(camera: &Camera, options: &Options, scene: &Scene, pass: usize) -> Frame {
+    if !options.workers.is_empty() {
+        return distributed::render_pass(camera, options, scene, pass);
+    }
+    render_rows(camera, options, scene, pass, 0..camera.height)
+}
+
+// Traces the `rows` of the image into a frame of just those rows. The tiles are handed out
+// to `threads` workers as they finish, the calling thread being one of them; every pixel
+// has its own sample stream, so the image doesn't depend on the split.
+pub fn render_rows(
+    camera: &Camera,
+    options: &Options,
+    scene: &Scene,
+    pass: usize,
+    rows: Range<usize>,
+) -> Frame {
+    let _span = log::span("render pass");
+    let sampler = Sampler::new(options.sampler, options.spp, options.seed);
+    let pixels = camera.width * rows.len();
+    let mut frame = Frame::new(camera.width, rows.len());
+    let tile_pixels = TILE_ROWS * camera.width.max(1);
+    let workers = options
+        .worker_threads()
+        .min(pixels.div_ceil(tile_pixels))
+        .max(1);
+    // the tiles borrow the frame until all of them are traced
+    frame.samples = {
+        let tiles = frame
+            .color
+            .bands_mut(TILE_ROWS)
+            .zip(frame.normal.bands_mut(TILE_ROWS))
+            .zip(frame.depth.bands_mut(TILE_ROWS))
+            .zip(frame.alpha.bands_mut(TILE_ROWS))
+            .map(|(((color, normal), depth), alpha)| Tile {
+                top: rows.start + color.top(),
+                color,
+                normal,
+                depth,
+                alpha,
+            });
+        let tiles = Mutex::new(tiles);
+        let work = || {
+            let mut samples = 0;
+            // the lock is released before the tile is traced
+            while let Some(tile) = { tiles.lock().unwrap().next() } {
+                samples += render_tile(camera, options, scene, &sampler, pass, tile);
+            }
+            samples
+        };
+        std::thread::scope(|s| {
+            let helpers: Vec<_> = (1..workers)
+                .map(|_| {
+                    s.spawn(|| {
+                        let samples = work();
+                        (samples, bench::take_counts())
+                    })
+                })
+                .collect();
+            let mut samples = work();
+            for helper in helpers {
+                let (helper_samples, counts) = helper.join().unwrap();
+                samples += helper_samples;
+                bench::add_counts(counts);
+            }
+            samples
+        })
+    };
+    // the background is as deep as the far plane
+    frame.depth = frame.depth.map(|depth| depth.min(options.far));
+    frame
+}
+
+// denoising, embers and post-processing of a frame of the scene traced by `camera`
+pub fn finish(frame: &mut Frame, camera: &Camera, options: &Options, scene: &Scene) {
+    let _span = log::span("post-processing");
+    if options.denoise {
+        frame.color = denoise(frame);
+    }
+    if options.embers > 0 {
+        embers::draw(frame, camera, options, scene);
+    }
+    post::apply(frame, options);
+}
+
+pub fn render(camera: &Camera, options: &Options) -> Frame {
+    let scene = Scene::new(options);
+    let mut frame = render_pass(camera, options, &scene, 0);
+    finish(&mut frame, camera, options, &scene);
+    frame
+}
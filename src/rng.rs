@@ -0,0 +1,97 @@
+#![allow(dead_code)]
+use crate::vec3d::Vec2d;
+
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+// SplitMix64 finalizer, spreads nearby inputs over all 64 bits
+fn mix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// PCG32 (O'Neill 2014): a 64-bit LCG whose state is permuted into 32-bit outputs. Small,
+// fast and reproducible on every platform, and it can jump ahead, so any draw of a stream
+// can be had without the ones before it.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Rng {
+    state: u64,
+    // odd, picks one of 2^63 streams
+    inc: u64,
+}
+
+impl Rng {
+    // the reference seeding of `pcg32_srandom`
+    pub fn new(seed: u64, stream: u64) -> Rng {
+        let mut rng = Rng {
+            state: 0,
+            inc: stream << 1 | 1,
+        };
+        rng.step();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.step();
+        rng
+    }
+
+    // Stream of sample `sample` of pixel (x, y): the pixel picks the stream, the seed and
+    // the sample index where it starts, so every pixel sample draws its own numbers
+    // whichever thread or machine traces it.
+    pub fn from_pixel(seed: u32, x: usize, y: usize, sample: usize) -> Rng {
+        let stream = mix64((x as u64) << 32 ^ y as u64);
+        let start = mix64(u64::from(seed) << 32 ^ sample as u64);
+        Rng::new(start, stream)
+    }
+
+    fn step(&mut self) {
+        self.state = self.state.wrapping_mul(MULTIPLIER).wrapping_add(self.inc);
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.step();
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    // in [0, 1), with the 32 bits of a draw
+    pub fn next_f64(&mut self) -> f64 {
+        f64::from(self.next_u32()) / 4_294_967_296.
+    }
+
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / 16_777_216.
+    }
+
+    pub fn next_2d(&mut self) -> Vec2d {
+        let u = self.next_f64();
+        Vec2d::new(u, self.next_f64())
+    }
+
+    // uniform in 0..n without modulo bias (Lemire 2019), `n` at least 1
+    pub fn below(&mut self, n: u32) -> u32 {
+        let mut m = u64::from(self.next_u32()) * u64::from(n);
+        if (m as u32) < n {
+            let threshold = n.wrapping_neg() % n;
+            while (m as u32) < threshold {
+                m = u64::from(self.next_u32()) * u64::from(n);
+            }
+        }
+        (m >> 32) as u32
+    }
+
+    // skips `delta` draws in O(log delta) steps (Brown 1994)
+    pub fn advance(&mut self, mut delta: u64) {
+        let (mut mult, mut plus) = (1u64, 0u64);
+        let (mut cur_mult, mut cur_plus) = (MULTIPLIER, self.inc);
+        while delta > 0 {
+            if delta & 1 == 1 {
+                mult = mult.wrapping_mul(cur_mult);
+                plus = plus.wrapping_mul(cur_mult).wrapping_add(cur_plus);
+            }
+            cur_plus = cur_mult.wrapping_add(1).wrapping_mul(cur_plus);
+            cur_mult = cur_mult.wrapping_mul(cur_mult);
+            delta >>= 1;
+        }
+        self.state = self.state.wrapping_mul(mult).wrapping_add(plus);
+    }
+}
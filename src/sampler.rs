@@ -0,0 +1,243 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::qmc::{mix, owen_sobol_2d, scrambled_radical_inverse};
+use crate::rng::Rng;
+use crate::vec3d::Vec2d;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum SamplerKind {
+    Random,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SamplerKind, String> {
+        match s {
+            "random" => Ok(SamplerKind::Random),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            "blue-noise" => Ok(SamplerKind::BlueNoise),
+            _ => Err(format!("unknown sampler '{}'", s)),
+        }
+    }
+}
+
+// Generates 2D sample points in [0, 1)^2 for sample `index` of pixel (`x`, `y`).
+// `dim` selects an independent pair of dimensions, so AA jitter, lens and light
+// samples of the same path do not correlate with each other. The random values come from
+// the PCG32 stream of the pixel sample, dimension `dim` being its draws `2 dim` and
+// `2 dim + 1`.
+#[derive(Copy, Clone)]
+pub struct Sampler {
+    pub kind: SamplerKind,
+    pub spp: usize,
+    pub seed: u32,
+}
+
+const PRIMES: [u32; 16] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53];
+const BLUE_NOISE_SIZE: usize = 64;
+
+fn hash(x: usize, y: usize, index: usize, dim: usize) -> u32 {
+    let h = mix(x as u32 ^ mix(y as u32 ^ mix(dim as u32)));
+    mix(h ^ index as u32)
+}
+
+fn to_unit(bits: u32) -> f64 {
+    f64::from(bits) / 4_294_967_296.
+}
+
+// uncorrelated value in [0, 1) for channel `dim` of pixel (x, y)
+pub fn white_noise(x: usize, y: usize, dim: usize) -> f64 {
+    to_unit(hash(x, y, 0, dim))
+}
+
+fn fract(x: f64) -> f64 {
+    x - x.floor()
+}
+
+impl Sampler {
+    pub fn new(kind: SamplerKind, spp: usize, seed: u32) -> Sampler {
+        Sampler { kind, spp, seed }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize, index: usize) -> PixelSampler<'_> {
+        PixelSampler {
+            sampler: self,
+            x,
+            y,
+            index,
+            dim: 0,
+        }
+    }
+
+    pub fn get_2d(&self, x: usize, y: usize, index: usize, dim: usize) -> Vec2d {
+        // other seeds see the pattern of pixels far away, seed 0 keeps the plain one
+        let seed = if self.seed == 0 { 0 } else { mix(self.seed) };
+        let (x, y) = (
+            x.wrapping_add(seed as usize),
+            y.wrapping_add(mix(seed) as usize),
+        );
+        let random = || {
+            let mut rng = Rng::from_pixel(self.seed, x, y, index);
+            rng.advance(2 * dim as u64);
+            rng.next_2d()
+        };
+        let (u, v) = match self.kind {
+            SamplerKind::Random => {
+                let r = random();
+                (r.x, r.y)
+            }
+            SamplerKind::Stratified => {
+                let side = ((self.spp as f64).sqrt() as usize).max(1);
+                let strata = side * side;
+                // rotate the strata order per pixel and dimension
+                let round = index / strata;
+                let stratum = (index + hash(x, y, round, dim) as usize) % strata;
+                let jitter = random();
+                (
+                    ((stratum % side) as f64 + jitter.x) / side as f64,
+                    ((stratum / side) as f64 + jitter.y) / side as f64,
+                )
+            }
+            // the deterministic sequences are scrambled per pixel and dimension
+            SamplerKind::Halton => {
+                let bases = (PRIMES[(2 * dim) % 16], PRIMES[(2 * dim + 1) % 16]);
+                (
+                    scrambled_radical_inverse(bases.0, index as u32, hash(x, y, 0, 2 * dim)),
+                    scrambled_radical_inverse(bases.1, index as u32, hash(x, y, 0, 2 * dim + 1)),
+                )
+            }
+            SamplerKind::Sobol => owen_sobol_2d(index as u32, hash(x, y, 0, dim)),
+            SamplerKind::BlueNoise => {
+                // offset the mask per dimension and walk the R2 sequence per sample
+                let mask = blue_noise_mask();
+                let offset = dim * 17;
+                let u = mask[(y + offset) % BLUE_NOISE_SIZE * BLUE_NOISE_SIZE
+                    + (x + offset) % BLUE_NOISE_SIZE];
+                let v = mask[(y + offset + 32) % BLUE_NOISE_SIZE * BLUE_NOISE_SIZE
+                    + (x + offset + 32) % BLUE_NOISE_SIZE];
+                (
+                    fract(u + index as f64 * 0.754_877_666_246_692_7),
+                    fract(v + index as f64 * 0.569_840_290_998_053_3),
+                )
+            }
+        };
+        Vec2d::new(u, v)
+    }
+}
+
+// threshold in [0, 1) of the blue-noise mask tiled over the image
+pub fn blue_noise(x: usize, y: usize) -> f64 {
+    blue_noise_mask()[y % BLUE_NOISE_SIZE * BLUE_NOISE_SIZE + x % BLUE_NOISE_SIZE]
+}
+
+// Tileable blue-noise threshold mask built once with void-and-cluster.
+pub fn blue_noise_mask() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+    MASK.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE, 1.9))
+}
+
+fn void_and_cluster(size: usize, sigma: f64) -> Vec<f64> {
+    let n = size * size;
+    let mut kernel = vec![0.; n];
+    for dy in 0..size {
+        for dx in 0..size {
+            let wx = dx.min(size - dx) as f64;
+            let wy = dy.min(size - dy) as f64;
+            kernel[dx + dy * size] = (-(wx * wx + wy * wy) / (2. * sigma * sigma)).exp();
+        }
+    }
+
+    let mut energy = vec![0.; n];
+    let mut on = vec![false; n];
+    let splat = |energy: &mut Vec<f64>, p: usize, sign: f64| {
+        let (px, py) = (p % size, p / size);
+        for y in 0..size {
+            for x in 0..size {
+                let k = (x + size - px) % size + (y + size - py) % size * size;
+                energy[x + y * size] += sign * kernel[k];
+            }
+        }
+    };
+    let tightest_cluster = |energy: &[f64], on: &[bool]| {
+        (0..n)
+            .filter(|&p| on[p])
+            .max_by(|&a, &b| energy[a].partial_cmp(&energy[b]).unwrap())
+            .unwrap()
+    };
+    let largest_void = |energy: &[f64], on: &[bool]| {
+        (0..n)
+            .filter(|&p| !on[p])
+            .min_by(|&a, &b| energy[a].partial_cmp(&energy[b]).unwrap())
+            .unwrap()
+    };
+
+    // initial binary pattern: random points relaxed until the tightest cluster
+    // and the largest void coincide
+    let initial = n / 10;
+    for i in 0..initial {
+        let p = mix(i as u32 ^ 0x5bd1_e995) as usize % n;
+        if !on[p] {
+            on[p] = true;
+            splat(&mut energy, p, 1.);
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&energy, &on);
+        on[cluster] = false;
+        splat(&mut energy, cluster, -1.);
+        let void = largest_void(&energy, &on);
+        if void == cluster {
+            on[cluster] = true;
+            splat(&mut energy, cluster, 1.);
+            break;
+        }
+        on[void] = true;
+        splat(&mut energy, void, 1.);
+    }
+    let ones = on.iter().filter(|&&b| b).count();
+
+    let mut rank = vec![0; n];
+    // phase 1: remove points of the initial pattern, tightest clusters first
+    let (mut e, mut o) = (energy.clone(), on.clone());
+    for r in (0..ones).rev() {
+        let cluster = tightest_cluster(&e, &o);
+        o[cluster] = false;
+        splat(&mut e, cluster, -1.);
+        rank[cluster] = r;
+    }
+    // phase 2 and 3: fill the largest voids until the mask is full
+    for r in ones..n {
+        let void = largest_void(&energy, &on);
+        on[void] = true;
+        splat(&mut energy, void, 1.);
+        rank[void] = r;
+    }
+
+    rank.iter().map(|&r| (r as f64 + 0.5) / n as f64).collect()
+}
+
+// Sample stream of one pixel sample: every call hands out the next pair of
+// dimensions, so consumers don't have to agree on dimension indices.
+pub struct PixelSampler<'a> {
+    sampler: &'a Sampler,
+    x: usize,
+    y: usize,
+    index: usize,
+    dim: usize,
+}
+
+impl<'a> PixelSampler<'a> {
+    pub fn next_2d(&mut self) -> Vec2d {
+        let sample = self.sampler.get_2d(self.x, self.y, self.index, self.dim);
+        self.dim += 1;
+        sample
+    }
+}
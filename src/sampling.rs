@@ -0,0 +1,70 @@
+use core::f64::consts::PI;
+
+#[cfg(not(feature = "std"))]
+use crate::vec3d::Float;
+use crate::vec3d::{Vec2d, Vec3d};
+
+// Warps of uniform samples `u1`, `u2` in [0, 1) onto directions and shapes. The hemisphere
+// and the cone are built around +z, `around` turns them to any axis.
+
+// the local direction `v` with its z axis turned onto the unit `axis`
+pub fn around(axis: Vec3d, v: Vec3d) -> Vec3d {
+    let (tangent, bitangent) = axis.orthonormal_basis();
+    tangent * v.x + bitangent * v.y + axis * v.z
+}
+
+// cosine weighted by Malley's method, lifting a uniform disk sample
+pub fn sample_cosine_hemisphere(u1: f64, u2: f64) -> Vec3d {
+    let r = u1.sqrt();
+    let phi = 2. * PI * u2;
+    Vec3d::new(r * phi.cos(), r * phi.sin(), (1. - u1).sqrt())
+}
+
+// solid angle pdf of `sample_cosine_hemisphere` for a direction `cos_theta` off the axis
+pub fn cosine_hemisphere_pdf(cos_theta: f64) -> f64 {
+    cos_theta.max(0.) / PI
+}
+
+pub fn sample_uniform_sphere(u1: f64, u2: f64) -> Vec3d {
+    let z = 1. - 2. * u1;
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * u2;
+    Vec3d::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub fn uniform_sphere_pdf() -> f64 {
+    1. / (4. * PI)
+}
+
+// uniform over the directions within the angle `acos(cos_max)` of the axis
+pub fn sample_cone(cos_max: f64, u1: f64, u2: f64) -> Vec3d {
+    let cos_theta = 1. - u1 * (1. - cos_max);
+    let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+    let phi = 2. * PI * u2;
+    Vec3d::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+pub fn cone_pdf(cos_max: f64) -> f64 {
+    1. / (2. * PI * (1. - cos_max))
+}
+
+// uniform over the unit disk
+pub fn sample_disk(u1: f64, u2: f64) -> Vec2d {
+    let r = u1.sqrt();
+    let phi = 2. * PI * u2;
+    Vec2d::new(r * phi.cos(), r * phi.sin())
+}
+
+// Uniform over the regular polygon with `sides` corners on the unit circle, the first at the
+// angle `rotation`. `u1` picks the triangle between the center and a side and is stretched
+// back over [0, 1) to place the point inside it.
+pub fn sample_polygon(sides: usize, rotation: f64, u1: f64, u2: f64) -> Vec2d {
+    let n = sides as f64;
+    let side = (u1 * n).floor().min(n - 1.);
+    let corner = |k: f64| {
+        let angle = rotation + 2. * PI * k / n;
+        Vec2d::new(angle.cos(), angle.sin())
+    };
+    let (a, b) = (corner(side), corner(side + 1.));
+    (a + (b - a) * u2) * (u1 * n - side).sqrt()
+}
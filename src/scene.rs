@@ -0,0 +1,1269 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::bench::count_ray;
+use crate::bounds::BoundingSphere;
+use crate::color::Color;
+use crate::error::Error;
+use crate::expr::Expr;
+use crate::log;
+use crate::mesh::{self, Mesh, MeshSdf};
+use crate::noise::{hash, lerp, NoiseMode, NoiseTable, FBM_OCTAVES};
+use crate::options::Options;
+use crate::palette::Palette;
+use crate::plugin::{self, SceneSdf};
+use crate::quat::Quat;
+use crate::ray::{Hit, Ray};
+use crate::sdf::{
+    closest_approach, displacement_lipschitz, displacement_noise, distance_field_normal, fireball,
+    fireball_step, heightfield_trace, julia, mandelbulb, menger_sponge, plane_distance,
+    plane_trace, ray_march, sphere_trace, warp_stretch, Displace, DisplacedSteps, Displacement,
+    Orbit, MARCH_STEPS, NOISE_AMPLITUDE, SPHERE_RADIUS,
+};
+use crate::shaping::{bias, smoothstep};
+use crate::terrain::{Heightmap, Relief};
+use crate::texture::Texture;
+use crate::vec3d::{Vec2d, Vec3d};
+
+const PLANE_MAX_DISTANCE: f64 = 100.;
+// marched hits are resolved to this fraction of the object's radius by default
+pub const SURFACE_EPSILON: f64 = 1e-3;
+const MESH_RESOLUTION: usize = 64;
+// speed at which the displacement noise rises through the objects over time
+const NOISE_DRIFT: f64 = 0.5;
+// the turbulence flow changes as if it moved this fast through its noise
+const FLOW_CHANGE: Vec3d = Vec3d::new(0.13, 0.07, 0.21);
+const FIREBALL_NORMAL_EPSILON: f64 = 0.1;
+// terrain normals are taken over this fraction of the feature size
+const TERRAIN_NORMAL_EPSILON: f64 = 0.01;
+const EXPRESSION_NORMAL_EPSILON: f64 = 0.01;
+// the smoke's breakup noise is sampled this far from the displacement, so they don't match
+const SMOKE_NOISE_OFFSET: Vec3d = Vec3d::new(17.3, -9.1, 5.7);
+// temperatures over which the fire fades into the smoke, either side of its level
+const SMOKE_BAND: f64 = 0.1;
+// height above the ground over which a fireball's spread along it falls off by e, as a
+// fraction of its radius
+const MUSHROOM_HEIGHT: f64 = 0.3;
+// an exploding fireball ignites at this fraction of its size
+const IGNITION_RADIUS: f64 = 0.1;
+// the flash of the ignition, how much brighter than the fire it starts and the fraction of
+// the lifecycle it fades over
+const IGNITION_FLASH: f64 = 3.;
+const FLASH_TIME: f64 = 0.05;
+// power of the cooling curve, higher cools off faster early on
+const COOLING: f64 = 1.5;
+// the temperature below which the fire is smoke, from the ignition to the end, past the
+// hottest fire with its breakup and band
+const SMOKE_START: f64 = 0.05;
+const SMOKE_END: f64 = 1.4;
+
+// Scene files are a small TOML subset: `key = value` pairs, `#` comments and
+// `[section]` headers. Keys inside a section are prefixed with the section name,
+// so `threshold = 0.8` under `[bloom]` sets the `bloom-threshold` option.
+// Arrays are flattened to comma separated lists, `[10, 10, 10]` reads as `10,10,10`.
+// An `[[object]]` header adds a new scene object, configured by the keys following it, and
+// an `[[instance]]` header a copy of the last one, changed by the keys following it.
+// `include = "path"` at the top level reads another file in place, relative to the one
+// including it. A `[material.name]` table defines a named set of keys that `use = "name"`
+// applies wherever it appears, prefixed like the keys around it.
+
+// object keys that pick the shape or hold its data, which a change of shape doesn't apply
+// again
+const SHAPE_KEYS: [&str; 6] = [
+    "mesh",
+    "mesh-resolution",
+    "heightmap",
+    "sdf",
+    "plugin",
+    "lipschitz",
+];
+
+// Euler angles in degrees "x,y,z", turning about x first, then y, then z
+fn euler_degrees(value: &str) -> Result<Quat, String> {
+    let angles: Vec3d = value.parse()?;
+    Ok(Quat::from_euler(angles * (PI / 180.)))
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+// A scene file being read with the files it includes: the entries so far, the materials
+// defined, and the chain of files being read, to catch a file including itself.
+#[derive(Default)]
+struct Reader {
+    entries: Vec<(String, String)>,
+    materials: HashMap<String, Vec<(String, String)>>,
+    files: Vec<PathBuf>,
+}
+
+impl Reader {
+    // `text` of a file in `dir`, which its includes are relative to
+    fn read(&mut self, text: &str, dir: &Path) -> Result<(), Error> {
+        let mut section = String::new();
+        // the material whose table the keys are in
+        let mut material: Option<String> = None;
+        for (n, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with("[[") && line.ends_with("]]") {
+                section = line[2..line.len() - 2].trim().to_string();
+                material = None;
+                self.entries.push((section.clone(), String::new()));
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].trim().to_string();
+                material = section.strip_prefix("material.").map(|name| {
+                    let name = unquote(name).to_string();
+                    self.materials.entry(name.clone()).or_default();
+                    name
+                });
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(format!("line {}: expected 'key = value'", n + 1))?;
+            let key = key.trim().replace('_', "-");
+            let value = value.trim();
+            let value = if value.starts_with('[') && value.ends_with(']') {
+                value[1..value.len() - 1]
+                    .split(',')
+                    .map(unquote)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            } else {
+                unquote(value).to_string()
+            };
+            let settings = match key.as_str() {
+                "include" if section.is_empty() => {
+                    self.include(&dir.join(value))?;
+                    continue;
+                }
+                "use" => self
+                    .materials
+                    .get(&value)
+                    .ok_or(format!("line {}: unknown material '{}'", n + 1, value))?
+                    .clone(),
+                _ => vec![(key, value)],
+            };
+            match &material {
+                Some(name) => self
+                    .materials
+                    .entry(name.clone())
+                    .or_default()
+                    .extend(settings),
+                None if section.is_empty() => self.entries.extend(settings),
+                None => self.entries.extend(
+                    settings
+                        .into_iter()
+                        .map(|(key, value)| (format!("{}-{}", section, key), value)),
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, path: &Path) -> Result<(), Error> {
+        let name = path.display().to_string();
+        // the same file reached through `..` or links is caught as well
+        let path = std::fs::canonicalize(path).map_err(|err| Error::io(&name, err))?;
+        if self.files.contains(&path) {
+            return Err(Error::Parse(format!("{} includes itself", name)));
+        }
+        let text = std::fs::read_to_string(&path).map_err(|err| Error::io(&name, err))?;
+        self.files.push(path.clone());
+        let dir = path.parent().unwrap_or(Path::new(""));
+        self.read(&text, dir).map_err(|err| err.in_file(&name))?;
+        self.files.pop();
+        Ok(())
+    }
+}
+
+pub fn parse(text: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut reader = Reader::default();
+    reader.read(text, Path::new(""))?;
+    Ok(reader.entries)
+}
+
+pub fn load(path: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut reader = Reader::default();
+    reader.include(Path::new(path))?;
+    Ok(reader.entries)
+}
+
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Shape {
+    Fireball,
+    Plane,
+    Mandelbulb,
+    Menger,
+    Julia,
+    Mesh,
+    Terrain,
+    Expression,
+    Plugin,
+}
+
+impl FromStr for Shape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Shape, String> {
+        match s {
+            "fireball" => Ok(Shape::Fireball),
+            "plane" => Ok(Shape::Plane),
+            "mandelbulb" => Ok(Shape::Mandelbulb),
+            "menger" => Ok(Shape::Menger),
+            "julia" => Ok(Shape::Julia),
+            "mesh" => Ok(Shape::Mesh),
+            "terrain" => Ok(Shape::Terrain),
+            "expression" => Ok(Shape::Expression),
+            "plugin" => Ok(Shape::Plugin),
+            _ => Err(format!("unknown shape '{}'", s)),
+        }
+    }
+}
+
+impl Shape {
+    // the name `from_str` reads
+    pub fn name(self) -> &'static str {
+        match self {
+            Shape::Fireball => "fireball",
+            Shape::Plane => "plane",
+            Shape::Mandelbulb => "mandelbulb",
+            Shape::Menger => "menger",
+            Shape::Julia => "julia",
+            Shape::Mesh => "mesh",
+            Shape::Terrain => "terrain",
+            Shape::Expression => "expression",
+            Shape::Plugin => "plugin",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Shading {
+    // palette color picked by the depth below the undisplaced surface, glowing on its own
+    Fire,
+    Diffuse,
+    // diffuse with the albedo picked from the palette by a fractal's orbit trap
+    Trap,
+    // the same, picked by the iteration a fractal's orbit escaped at
+    Iterations,
+}
+
+impl FromStr for Shading {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Shading, String> {
+        match s {
+            "fire" => Ok(Shading::Fire),
+            "diffuse" => Ok(Shading::Diffuse),
+            "trap" => Ok(Shading::Trap),
+            "iterations" => Ok(Shading::Iterations),
+            _ => Err(format!("unknown shading '{}'", s)),
+        }
+    }
+}
+
+impl Shading {
+    // the name `from_str` reads
+    pub fn name(self) -> &'static str {
+        match self {
+            Shading::Fire => "fire",
+            Shading::Diffuse => "diffuse",
+            Shading::Trap => "trap",
+            Shading::Iterations => "iterations",
+        }
+    }
+}
+
+// How deep below the undisplaced surface, in units of the noise amplitude, a point of a
+// fire-shaded object has to be to glow: the temperature that indexes the palette rises from 0
+// at `depth` to 1 `falloff` further in, measured from a hot core that can sit off the
+// center. The ramp is bent by a Schlick bias, and `gain` scales the light emitted.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Heat {
+    // in object space relative to the center, ignored by meshes
+    pub core: Vec3d,
+    pub depth: f64,
+    pub falloff: f64,
+    // 0.5 is a straight ramp, more heats the outer layers up, less keeps the heat inside
+    pub bias: f64,
+    pub gain: f64,
+}
+
+impl Heat {
+    pub const DEFAULT: Heat = Heat {
+        core: Vec3d::zero(),
+        depth: 0.2,
+        falloff: 0.5,
+        bias: 0.5,
+        gain: 1.,
+    };
+
+    // the temperature at `level` below the surface, in [0, 1]
+    pub fn temperature(&self, level: f64) -> f64 {
+        bias(
+            ((level - self.depth) / self.falloff).clamp(0., 1.),
+            self.bias,
+        )
+    }
+}
+
+// A shell of smoke over a fire-shaded object: where the temperature is below `level` the
+// surface turns from glowing fire into smoke colored by `palette`, lit by the light like a
+// diffuse surface. A second noise channel of amplitude `breakup` shifts the boundary so it
+// isn't just a layer of the fire.
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Smoke {
+    // no shell without
+    pub palette: Option<Palette>,
+    pub level: f64,
+    pub breakup: f64,
+}
+
+impl Smoke {
+    pub const DEFAULT: Smoke = Smoke {
+        palette: None,
+        level: 0.3,
+        breakup: 0.5,
+    };
+}
+
+// Art-directed terms of direct shading, none of them physical. `wrap` lets the light reach
+// around the terminator, `ambient` scales the ambient floor, a rim of the light's color
+// `rim_width` wide (as a fraction of the grazing angles) brightens silhouettes by `rim`, and
+// the shaded color is tinted from `tint_bottom` on downward normals to `tint_top` on upward
+// ones.
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Style {
+    pub wrap: f64,
+    pub ambient: f64,
+    pub rim: f64,
+    pub rim_width: f64,
+    pub tint_top: Color,
+    pub tint_bottom: Color,
+}
+
+impl Style {
+    pub const DEFAULT: Style = Style {
+        wrap: 0.,
+        ambient: 1.,
+        rim: 0.,
+        rim_width: 0.3,
+        tint_top: Color::WHITE,
+        tint_bottom: Color::WHITE,
+    };
+}
+
+#[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Material {
+    pub shading: Shading,
+    pub palette: Palette,
+    pub albedo: Color,
+    pub texture: Option<Texture>,
+    pub texture_scale: f64,
+    pub heat: Heat,
+    pub smoke: Smoke,
+    pub style: Style,
+}
+
+#[derive(Clone)]
+pub struct Object {
+    pub shape: Shape,
+    // fireball parameters
+    pub center: Vec3d,
+    pub radius: f64,
+    pub noise_amplitude: f64,
+    // where the displacement noise is sampled, picked by the object's own seed if it has
+    // one and the scene seed otherwise
+    pub noise_offset: Vec3d,
+    pub seed: Option<u32>,
+    // the displacement noise sheared by a curl noise flow of this strength, carried by the
+    // wind from `warp_offset`
+    pub warp: f64,
+    pub warp_offset: Vec3d,
+    // height of the ground a fireball bursts on, which cuts it off and spreads it out by up
+    // to `mushroom` times its radius
+    pub ground: Option<f64>,
+    pub mushroom: f64,
+    // the noise level of detail, a pixel is `lod_spread` wide at unit distance from
+    // `lod_eye`, 0 keeps every octave
+    pub lod_eye: Vec3d,
+    pub lod_spread: f64,
+    // octaves of the displacement noise, and the steps rays are marched for
+    pub noise_octaves: usize,
+    pub march_steps: usize,
+    // rays stop this far from their origin, the far plane of the camera
+    pub max_distance: f64,
+    // hits are resolved to this fraction of `radius` off the surface
+    pub surface_epsilon: f64,
+    // temperatures are snapped to this many palette colors, 0 keeps them continuous
+    pub posterize: usize,
+    // lattice of the displacement noise, exact noise without
+    pub noise_table: Option<Arc<NoiseTable>>,
+    // plane parameters
+    pub height: f64,
+    // fractal parameters, `radius` scales the fractal around `center`
+    pub power: f64,
+    pub iterations: usize,
+    pub julia_c: [f64; 4],
+    // mesh parameters, the mesh is fitted into the sphere of `radius`
+    pub mesh: Option<Arc<Mesh>>,
+    pub mesh_resolution: usize,
+    pub mesh_sdf: Option<Arc<MeshSdf>>,
+    // terrain parameters, `height` is the base level and `noise_amplitude` the relief
+    pub terrain_scale: f64,
+    pub heightmap: Option<Arc<Heightmap>>,
+    pub heightmap_size: f64,
+    // user distance function of the object space position and `time`, bounded by `radius`
+    pub expression: Option<Arc<Expr>>,
+    // procedural object registered by a downstream crate, bounded by `radius` too
+    pub plugin: Option<Arc<dyn SceneSdf>>,
+    // Lipschitz bound of the expression or plugin, which are marched in small steps without
+    pub sdf_lipschitz: Option<f64>,
+    pub time: f64,
+    // mouse position of the live preview over the image, 0 to 1 from the bottom-left corner
+    pub mouse: Vec2d,
+    // orientation about `center` at time 0, and the one reached at `duration` when the
+    // object turns over time
+    pub rotation: Quat,
+    pub rotation_end: Option<Quat>,
+    pub material: Material,
+    // the keys set so far, applied again to the defaults of a new `shape`
+    pub settings: Vec<(String, String)>,
+}
+
+impl Object {
+    pub fn fireball() -> Object {
+        Object {
+            shape: Shape::Fireball,
+            center: Vec3d::zero(),
+            radius: SPHERE_RADIUS,
+            noise_amplitude: NOISE_AMPLITUDE,
+            noise_offset: Vec3d::zero(),
+            seed: None,
+            warp: 0.,
+            warp_offset: Vec3d::zero(),
+            ground: None,
+            mushroom: 0.,
+            lod_eye: Vec3d::zero(),
+            lod_spread: 0.,
+            noise_octaves: FBM_OCTAVES,
+            march_steps: MARCH_STEPS,
+            max_distance: f64::INFINITY,
+            surface_epsilon: SURFACE_EPSILON,
+            posterize: 0,
+            noise_table: None,
+            height: 0.,
+            power: 8.,
+            iterations: 8,
+            julia_c: [-0.2, 0.6, 0.2, 0.2],
+            mesh: None,
+            mesh_resolution: MESH_RESOLUTION,
+            mesh_sdf: None,
+            terrain_scale: 2.,
+            heightmap: None,
+            heightmap_size: 10.,
+            expression: None,
+            plugin: None,
+            sdf_lipschitz: None,
+            time: 0.,
+            mouse: Vec2d::zero(),
+            rotation: Quat::IDENTITY,
+            rotation_end: None,
+            material: Material {
+                shading: Shading::Fire,
+                palette: Palette::Fire,
+                albedo: Color::gray(0.5),
+                texture: None,
+                texture_scale: 1.,
+                heat: Heat::DEFAULT,
+                smoke: Smoke::DEFAULT,
+                style: Style::DEFAULT,
+            },
+            settings: Vec::new(),
+        }
+    }
+
+    pub fn plane(height: f64) -> Object {
+        Object {
+            shape: Shape::Plane,
+            height,
+            material: Material {
+                shading: Shading::Diffuse,
+                texture: Some(Texture::Checker),
+                ..Object::fireball().material
+            },
+            ..Object::fireball()
+        }
+    }
+
+    pub fn fractal(shape: Shape) -> Object {
+        let iterations = match shape {
+            Shape::Menger => 4,
+            Shape::Julia => 10,
+            _ => 8,
+        };
+        Object {
+            shape,
+            radius: 1.,
+            noise_amplitude: 0.,
+            iterations,
+            material: Material {
+                shading: Shading::Trap,
+                ..Object::fireball().material
+            },
+            ..Object::fireball()
+        }
+    }
+
+    // an undisplaced diffuse mesh, `noise_amplitude` turns it into a fireball-like surface
+    pub fn mesh() -> Object {
+        Object {
+            shape: Shape::Mesh,
+            radius: 1.,
+            noise_amplitude: 0.,
+            material: Material {
+                shading: Shading::Diffuse,
+                ..Object::fireball().material
+            },
+            ..Object::fireball()
+        }
+    }
+
+    pub fn terrain() -> Object {
+        Object {
+            shape: Shape::Terrain,
+            height: -1.5,
+            material: Material {
+                shading: Shading::Diffuse,
+                texture: Some(Texture::Dirt),
+                ..Object::fireball().material
+            },
+            ..Object::fireball()
+        }
+    }
+
+    fn bake(&mut self) {
+        self.mesh_sdf = self
+            .mesh
+            .as_ref()
+            .map(|mesh| Arc::new(MeshSdf::bake(mesh, self.mesh_resolution)));
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let parse_f64 = |value: &str| {
+            value
+                .parse::<f64>()
+                .map_err(|_| format!("invalid value '{}' for '{}'", value, key))
+        };
+        match key {
+            "shape" => {
+                let shape = value.parse()?;
+                let settings = std::mem::take(&mut self.settings);
+                *self = match shape {
+                    Shape::Fireball => Object::fireball(),
+                    Shape::Plane => Object::plane(self.height),
+                    Shape::Mesh => Object {
+                        mesh: self.mesh.take(),
+                        mesh_resolution: self.mesh_resolution,
+                        mesh_sdf: self.mesh_sdf.take(),
+                        ..Object::mesh()
+                    },
+                    Shape::Terrain => Object {
+                        heightmap: self.heightmap.take(),
+                        ..Object::terrain()
+                    },
+                    Shape::Expression => Object {
+                        shape,
+                        noise_amplitude: 0.,
+                        expression: self.expression.take(),
+                        sdf_lipschitz: self.sdf_lipschitz,
+                        material: Material {
+                            shading: Shading::Diffuse,
+                            ..Object::fireball().material
+                        },
+                        ..Object::fireball()
+                    },
+                    Shape::Plugin => Object {
+                        shape,
+                        noise_amplitude: 0.,
+                        plugin: self.plugin.take(),
+                        sdf_lipschitz: self.sdf_lipschitz,
+                        ..Object::fireball()
+                    },
+                    _ => Object::fractal(shape),
+                };
+                // the data of the shapes is carried over above where the new one uses it
+                for (key, value) in settings {
+                    if !SHAPE_KEYS.contains(&key.as_str()) {
+                        self.set(&key, &value)?;
+                    }
+                }
+                return Ok(());
+            }
+            "center" => self.center = value.parse()?,
+            "seed" => {
+                self.seed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid value '{}' for '{}'", value, key))?,
+                )
+            }
+            "rotation" => self.rotation = euler_degrees(value)?,
+            "rotation-end" => self.rotation_end = Some(euler_degrees(value)?),
+            "radius" => self.radius = parse_f64(value)?,
+            "noise-amplitude" => self.noise_amplitude = parse_f64(value)?,
+            "height" => self.height = parse_f64(value)?,
+            "power" => self.power = parse_f64(value)?,
+            "iterations" => {
+                self.iterations = value
+                    .parse()
+                    .map_err(|_| format!("invalid value '{}' for '{}'", value, key))?
+            }
+            "mesh" => {
+                if self.shape != Shape::Mesh {
+                    self.set("shape", "mesh")?;
+                }
+                self.mesh = Some(Arc::new(mesh::load_obj(value)?));
+                self.bake();
+            }
+            "mesh-resolution" => {
+                self.mesh_resolution = value
+                    .parse()
+                    .map_err(|_| format!("invalid value '{}' for '{}'", value, key))?;
+                self.bake();
+            }
+            "terrain-scale" => self.terrain_scale = parse_f64(value)?,
+            "heightmap" => {
+                if self.shape != Shape::Terrain {
+                    self.set("shape", "terrain")?;
+                }
+                self.heightmap = Some(Arc::new(Heightmap::load(value)?));
+            }
+            "heightmap-size" => self.heightmap_size = parse_f64(value)?,
+            "lipschitz" => self.sdf_lipschitz = Some(parse_f64(value)?),
+            "sdf" => {
+                let expression = Expr::parse(value).map_err(|err| format!("{}: {}", key, err))?;
+                if self.shape != Shape::Expression {
+                    self.set("shape", "expression")?;
+                }
+                self.expression = Some(Arc::new(expression));
+            }
+            "plugin" => {
+                let sdf = plugin::lookup(value)
+                    .ok_or(format!("no procedural object registered as '{}'", value))?;
+                if self.shape != Shape::Plugin {
+                    self.set("shape", "plugin")?;
+                }
+                self.material.shading = sdf.material();
+                self.plugin = Some(sdf);
+            }
+            "julia-c" => {
+                let c = value
+                    .split(',')
+                    .map(|c| parse_f64(c.trim()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.julia_c = match c[..] {
+                    [x, y, z, w] => [x, y, z, w],
+                    _ => return Err(Error::Parse(format!("expected 4 components for '{}'", key))),
+                };
+            }
+            "shading" => self.material.shading = value.parse()?,
+            "palette" => self.material.palette = value.parse()?,
+            "albedo" => self.material.albedo = value.parse()?,
+            "texture" => {
+                self.material.texture = match value {
+                    "none" => None,
+                    _ => Some(value.parse()?),
+                }
+            }
+            "texture-scale" => self.material.texture_scale = parse_f64(value)?,
+            "heat-core" => self.material.heat.core = value.parse()?,
+            "heat-depth" => self.material.heat.depth = parse_f64(value)?,
+            "heat-falloff" => self.material.heat.falloff = parse_f64(value)?,
+            "heat-bias" => self.material.heat.bias = parse_f64(value)?,
+            "heat-gain" => self.material.heat.gain = parse_f64(value)?,
+            "smoke" => {
+                self.material.smoke.palette = match value {
+                    "none" => None,
+                    _ => Some(value.parse()?),
+                }
+            }
+            "smoke-level" => self.material.smoke.level = parse_f64(value)?,
+            "smoke-breakup" => self.material.smoke.breakup = parse_f64(value)?,
+            "wrap" => self.material.style.wrap = parse_f64(value)?,
+            "ambient" => self.material.style.ambient = parse_f64(value)?,
+            "rim" => self.material.style.rim = parse_f64(value)?,
+            "rim-width" => self.material.style.rim_width = parse_f64(value)?,
+            "tint-top" => self.material.style.tint_top = value.parse()?,
+            "tint-bottom" => self.material.style.tint_bottom = value.parse()?,
+            _ => return Err(Error::Parse(format!("unknown object option '{}'", key))),
+        }
+        self.settings.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    // `p` in object space: relative to `center` and turned back by `rotation`
+    fn local(&self, p: Vec3d) -> Vec3d {
+        self.unrotate(p - self.center)
+    }
+
+    fn unrotate(&self, v: Vec3d) -> Vec3d {
+        match self.rotation == Quat::IDENTITY {
+            true => v,
+            false => self.rotation.conjugate() * v,
+        }
+    }
+
+    // fractal estimator evaluated in object space, returns the distance and the orbit
+    fn estimate(&self, p: Vec3d) -> (f64, Orbit) {
+        let q = self.local(p) / self.radius;
+        let mut orbit = Orbit {
+            trap: 0.,
+            iterations: 0.,
+        };
+        let d = match self.shape {
+            Shape::Mandelbulb => mandelbulb(q, self.power, self.iterations, &mut orbit),
+            Shape::Menger => menger_sponge(q, self.iterations, &mut orbit),
+            Shape::Julia => julia(q, self.julia_c, self.iterations, &mut orbit),
+            _ => 0.,
+        };
+        (d * self.radius, orbit)
+    }
+
+    // how far the unbounded plane and terrain are traced
+    fn plane_reach(&self) -> f64 {
+        self.max_distance.min(PLANE_MAX_DISTANCE)
+    }
+
+    // radius of the sphere around `center` the shape fits into
+    fn bounding_radius(&self) -> f64 {
+        match self.shape {
+            Shape::Menger => self.radius * 3f64.sqrt(),
+            Shape::Julia => self.radius * 1.5,
+            Shape::Mandelbulb => self.radius * 1.2,
+            Shape::Fireball if self.ground.is_some() => self.radius * (1. + self.mushroom),
+            _ => self.radius,
+        }
+    }
+
+    // Largest change of `distance` per unit, None for expressions and plugins that don't
+    // declare one. The noise adds its own bound times the amplitude to the shape's.
+    pub fn lipschitz(&self) -> Option<f64> {
+        match self.shape {
+            Shape::Plane | Shape::Terrain => Some(1.),
+            Shape::Fireball => Some(self.fireball_steps().lipschitz()),
+            Shape::Expression | Shape::Plugin => {
+                self.shape_lipschitz().map(|_| self.displaced().lipschitz())
+            }
+            _ => Some(self.displaced().lipschitz()),
+        }
+    }
+
+    // bound of the undisplaced shape, the one declared for expressions and plugins
+    fn shape_lipschitz(&self) -> Option<f64> {
+        match self.shape {
+            Shape::Expression => self.sdf_lipschitz,
+            Shape::Plugin => self
+                .sdf_lipschitz
+                .or_else(|| self.plugin.as_ref().and_then(|sdf| sdf.lipschitz())),
+            _ => Some(1.),
+        }
+    }
+
+    // Sphere holding every point `trace` can report, None for the unbounded plane and
+    // terrain. The marchers stop up to their epsilon outside of the surface.
+    pub fn bounds(&self) -> Option<BoundingSphere> {
+        let radius = match self.shape {
+            Shape::Plane | Shape::Terrain => return None,
+            _ => self.bounding_radius() + self.epsilon(),
+        };
+        Some(BoundingSphere::new(self.center, radius))
+    }
+
+    // how close to the surface a marched ray counts as a hit
+    fn epsilon(&self) -> f64 {
+        self.surface_epsilon * self.radius
+    }
+
+    // distance to the undisplaced mesh, infinite until a mesh is loaded
+    fn mesh_distance(&self, p: Vec3d) -> f64 {
+        match &self.mesh_sdf {
+            Some(sdf) => sdf.distance(self.local(p) / self.radius) * self.radius,
+            None => f64::INFINITY,
+        }
+    }
+
+    fn relief(&self) -> Relief<'_> {
+        Relief {
+            base: self.height,
+            amplitude: self.noise_amplitude,
+            scale: self.terrain_scale,
+            heightmap: self
+                .heightmap
+                .as_deref()
+                .map(|map| (map, self.heightmap_size)),
+        }
+    }
+
+    // Steps along the fireball by the bounds of its noise. On the ground the spread of the
+    // mushroom changes by at most its height over its falloff, added to the sphere's bound.
+    fn fireball_steps(&self) -> DisplacedSteps {
+        let spread = match self.ground {
+            Some(_) => self.mushroom / MUSHROOM_HEIGHT,
+            None => 0.,
+        };
+        DisplacedSteps::new(
+            1. + spread,
+            self.noise_amplitude,
+            warp_stretch(self.warp),
+            self.noise_octaves,
+        )
+    }
+
+    // bound on the gradient of the displacement noise, of the octaves it keeps
+    fn noise_lipschitz(&self) -> f64 {
+        displacement_lipschitz(self.noise_octaves) * warp_stretch(self.warp)
+    }
+
+    // the displacement noise at `p`, without the detail finer than a pixel there
+    fn noise(&self, p: Vec3d) -> Displacement<'_> {
+        Displacement {
+            amplitude: self.noise_amplitude,
+            offset: self.noise_offset,
+            warp: self.warp,
+            warp_offset: self.warp_offset,
+            footprint: (p - self.lod_eye).length() * self.lod_spread,
+            octaves: self.noise_octaves,
+            table: self.noise_table.as_deref(),
+        }
+    }
+
+    // The distance field of the meshes, fractals, formulas and plugins, displaced by the
+    // noise times `noise_amplitude` like the fireball's sphere.
+    fn displaced(&self) -> Displace<impl Fn(Vec3d) -> f64 + '_, impl Fn(Vec3d) -> f64 + '_> {
+        Displace {
+            sdf: move |p| match self.shape {
+                Shape::Expression => match &self.expression {
+                    Some(expression) => expression.eval(self.local(p), self.time, self.mouse),
+                    None => f64::INFINITY,
+                },
+                Shape::Plugin => match &self.plugin {
+                    Some(sdf) => sdf.distance(self.local(p), self.time),
+                    None => f64::INFINITY,
+                },
+                Shape::Mesh => self.mesh_distance(p),
+                _ => self.estimate(p).0,
+            },
+            noise: move |p| displacement_noise(self.local(p), self.noise(p)),
+            sdf_lipschitz: self.shape_lipschitz().unwrap_or(1.),
+            amplitude: self.noise_amplitude,
+            noise_lipschitz: self.noise_lipschitz(),
+        }
+    }
+
+    pub fn distance(&self, p: Vec3d) -> f64 {
+        match self.shape {
+            Shape::Terrain => {
+                let relief = self.relief();
+                let lipschitz = relief.lipschitz();
+                (p.y - relief.height(p.x, p.z)) / (1. + lipschitz * lipschitz).sqrt()
+            }
+            Shape::Fireball => self.blast(self.local(p), p),
+            Shape::Plane => plane_distance(p, self.height),
+            Shape::Expression
+            | Shape::Plugin
+            | Shape::Mesh
+            | Shape::Mandelbulb
+            | Shape::Menger
+            | Shape::Julia => self.displaced().distance(p),
+        }
+    }
+
+    // The fireball at `p`, `local` in object space. On the ground its radius grows near the
+    // contact, so the blast spreads out along it in a mushroom, and it is cut off below.
+    fn blast(&self, local: Vec3d, p: Vec3d) -> f64 {
+        match self.ground {
+            Some(ground) => {
+                fireball(local, self.spread(p, ground), self.noise(p)).max(ground - p.y)
+            }
+            None => fireball(local, self.radius, self.noise(p)),
+        }
+    }
+
+    // `blast` as the safe `steps` of the fireball. The cut below the ground is marched with
+    // full steps, as it has a gradient of 1.
+    fn blast_step(&self, steps: &DisplacedSteps, local: Vec3d, p: Vec3d) -> f64 {
+        match self.ground {
+            Some(ground) => {
+                fireball_step(local, self.spread(p, ground), self.noise(p), steps).max(ground - p.y)
+            }
+            None => fireball_step(local, self.radius, self.noise(p), steps),
+        }
+    }
+
+    // the radius of the fireball spread out at `p` over the ground
+    fn spread(&self, p: Vec3d, ground: f64) -> f64 {
+        let height = (p.y - ground).max(0.) / (MUSHROOM_HEIGHT * self.radius);
+        self.radius + self.mushroom * self.radius * (-height).exp()
+    }
+
+    // orbit trap and escape iteration of the fractal at `p`, 0 for the other shapes
+    pub fn orbit(&self, p: Vec3d) -> Orbit {
+        self.estimate(p).1
+    }
+
+    pub fn trace(&self, ray: Ray) -> Option<Hit> {
+        let center = self.center;
+        let local_ray = Ray::new(ray.origin - center, ray.dir);
+        let hit = match self.shape {
+            // the steps never pass through the surface, and near it they are the distance
+            // over its bound, with the epsilon scaled alike
+            Shape::Fireball => {
+                let steps = self.fireball_steps();
+                let epsilon = self.epsilon() / steps.lipschitz();
+                let local = |p: Vec3d| self.blast_step(&steps, self.unrotate(p), p + center);
+                ray_march(
+                    local_ray,
+                    self.bounding_radius(),
+                    epsilon,
+                    self.march_steps,
+                    self.max_distance,
+                    local,
+                )
+            }
+            // nothing guarantees a user formula is a true distance, so it takes small steps
+            // unless it declares a bound
+            Shape::Expression | Shape::Plugin => match self.lipschitz() {
+                Some(lipschitz) => {
+                    let epsilon = self.epsilon() / lipschitz;
+                    let local = |p: Vec3d| self.distance(p + center) / lipschitz;
+                    ray_march(
+                        local_ray,
+                        self.radius,
+                        epsilon,
+                        self.march_steps,
+                        self.max_distance,
+                        local,
+                    )
+                }
+                None => {
+                    let local = |p: Vec3d| self.distance(p + center);
+                    sphere_trace(
+                        local_ray,
+                        self.radius,
+                        self.epsilon(),
+                        self.max_distance,
+                        local,
+                    )
+                }
+            },
+            Shape::Plane => return plane_trace(ray, self.height, self.plane_reach()),
+            Shape::Terrain => {
+                let relief = self.relief();
+                return heightfield_trace(
+                    ray,
+                    |x, z| relief.height(x, z),
+                    relief.lipschitz(),
+                    relief.top(),
+                    self.plane_reach(),
+                );
+            }
+            // the displacement is marched by its bound, with the epsilon scaled alike so hits
+            // are resolved as closely as without it
+            Shape::Mandelbulb | Shape::Menger | Shape::Julia | Shape::Mesh => {
+                let displaced = self.displaced();
+                let epsilon = self.epsilon() / displaced.lipschitz();
+                let local = |p: Vec3d| displaced.bound(p + center);
+                ray_march(
+                    local_ray,
+                    self.bounding_radius(),
+                    epsilon,
+                    self.march_steps,
+                    self.max_distance,
+                    local,
+                )
+            }
+        };
+        hit.map(|hit| Hit {
+            position: hit.position + center,
+            ..hit
+        })
+    }
+
+    // Where a ray that misses the object passes closest to its surface as seen from the
+    // ray origin, and the angle of the distance there. Only the shapes marched by their
+    // bound have silhouettes to approach.
+    pub fn approach(&self, ray: Ray) -> Option<(Hit, f64)> {
+        if matches!(self.shape, Shape::Plane | Shape::Terrain) {
+            return None;
+        }
+        let lipschitz = self.lipschitz()?;
+        let center = self.center;
+        let local_ray = Ray::new(ray.origin - center, ray.dir);
+        let epsilon = self.epsilon() / lipschitz;
+        let local = |p: Vec3d| self.distance(p + center) / lipschitz;
+        let radius = self.bounding_radius();
+        let (hit, ratio) = closest_approach(
+            local_ray,
+            radius,
+            epsilon,
+            self.march_steps,
+            self.max_distance,
+            local,
+        )?;
+        let hit = Hit {
+            position: hit.position + center,
+            ..hit
+        };
+        Some((hit, ratio * lipschitz))
+    }
+
+    pub fn normal(&self, p: Vec3d) -> Vec3d {
+        match self.shape {
+            Shape::Fireball => {
+                distance_field_normal(p, FIREBALL_NORMAL_EPSILON, |p| self.distance(p))
+            }
+            Shape::Plane => Vec3d::new(0., 1., 0.),
+            Shape::Expression | Shape::Plugin => {
+                distance_field_normal(p, EXPRESSION_NORMAL_EPSILON, |p| self.distance(p))
+            }
+            Shape::Terrain => {
+                let relief = self.relief();
+                let eps = TERRAIN_NORMAL_EPSILON * self.terrain_scale;
+                let dx = relief.height(p.x + eps, p.z) - relief.height(p.x - eps, p.z);
+                let dz = relief.height(p.x, p.z + eps) - relief.height(p.x, p.z - eps);
+                Vec3d::new(-dx, 2. * eps, -dz).normalized()
+            }
+            Shape::Mesh => {
+                let eps = match &self.mesh_sdf {
+                    Some(sdf) if self.noise_amplitude <= 0. => sdf.cell() * self.radius / 2.,
+                    _ => FIREBALL_NORMAL_EPSILON,
+                };
+                distance_field_normal(p, eps, |p| self.distance(p))
+            }
+            Shape::Mandelbulb | Shape::Menger | Shape::Julia => {
+                distance_field_normal(p, self.epsilon(), |p| self.distance(p))
+            }
+        }
+    }
+
+    // Depth below the undisplaced surface in units of the noise amplitude, around the hot
+    // core. Undisplaced shapes, such as fractals and meshes by default, measure it in units
+    // of their radius instead.
+    pub fn noise_level(&self, p: Vec3d) -> f64 {
+        let unit = match self.noise_amplitude == 0. {
+            true => self.radius,
+            false => self.noise_amplitude,
+        };
+        let depth = match self.shape {
+            Shape::Mesh => -self.mesh_distance(p),
+            _ => self.radius - (self.local(p) - self.material.heat.core).length(),
+        };
+        depth / unit
+    }
+
+    // the temperature of the material at `p`, which picks its palette color
+    pub fn temperature(&self, p: Vec3d) -> f64 {
+        let heat = self.material.heat.temperature(self.noise_level(p));
+        match self.posterize {
+            0 => heat,
+            n => (heat * n as f64).floor().min(n as f64 - 1.) / (n as f64 - 1.),
+        }
+    }
+
+    // How much of the fire at `p` the smoke shell covers, 0 to 1, and the smoke's color
+    // there, darker towards the fire. None without a smoke palette.
+    pub fn smoke(&self, p: Vec3d) -> Option<(f64, Color)> {
+        let smoke = self.material.smoke;
+        let palette = smoke.palette?;
+        let heat = self.temperature(p);
+        let breakup = displacement_noise(self.local(p) + SMOKE_NOISE_OFFSET, self.noise(p)) - 0.5;
+        let fire = smoothstep(
+            smoke.level - SMOKE_BAND,
+            smoke.level + SMOKE_BAND,
+            heat + breakup * smoke.breakup,
+        );
+        Some((1. - fire, palette.color(heat / smoke.level)))
+    }
+}
+
+// Stage of an explosion at its normalized time from ignition at 0 to 1, when only smoke is
+// left: how far out it has blasted as a fraction of its size, slowing down as it grows, how
+// bright its fire glows, flashing at the ignition and cooling off, and the temperature below
+// which it has turned into smoke, rising until none of the fire is left.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Lifecycle {
+    pub expansion: f64,
+    pub glow: f64,
+    pub smoke_level: f64,
+}
+
+impl Lifecycle {
+    pub fn at(t: f64) -> Lifecycle {
+        let t = t.clamp(0., 1.);
+        Lifecycle {
+            expansion: IGNITION_RADIUS + (1. - IGNITION_RADIUS) * (1. - (1. - t).powi(3)),
+            glow: (1. + IGNITION_FLASH * (-t / FLASH_TIME).exp()) * (1. - t).powf(COOLING),
+            smoke_level: lerp(SMOKE_START, SMOKE_END, smoothstep(0., 1., t)),
+        }
+    }
+
+    // scales the fireball `object` of its full size to the stage, with a smoke shell of
+    // the smoke palette unless it has one of its own
+    fn apply(&self, object: &mut Object) {
+        object.radius *= self.expansion;
+        object.noise_amplitude *= self.expansion;
+        let material = &mut object.material;
+        material.heat.core *= self.expansion;
+        material.heat.gain *= self.glow;
+        material.smoke.level = self.smoke_level;
+        material.smoke.palette.get_or_insert(Palette::Smoke);
+    }
+}
+
+pub struct Scene {
+    pub objects: Vec<Object>,
+}
+
+impl Scene {
+    pub fn new(options: &Options) -> Scene {
+        // meshes and heightmaps are loaded and baked here
+        let _span = log::span("scene load");
+        let mut objects = options.objects.clone();
+        if objects.is_empty() {
+            objects.push(Object::fireball());
+        }
+        if let Some(height) = options.floor {
+            let mut floor = Object::plane(height);
+            floor.material.texture = Some(options.floor_texture);
+            floor.material.texture_scale = options.floor_scale;
+            objects.push(floor);
+        }
+        let noise_offset = |seed: u32| {
+            let seed = f64::from(seed);
+            Vec3d::new(hash(seed * 1.1), hash(seed * 2.3), hash(seed * 3.7)) * 1000.
+                - Vec3d::new(0., options.time * NOISE_DRIFT, 0.)
+                - options.wind * options.time
+        };
+        // the samples of a pixel resolve detail finer than the pixel
+        let camera = crate::default_camera(options);
+        let pixel = 2. * (camera.fov / 2.).tan() / camera.height as f64;
+        let lod_spread = options.noise_lod * pixel / (options.spp as f64).sqrt();
+        let noise_table = match options.noise {
+            NoiseMode::Exact => None,
+            NoiseMode::Cached => Some(Arc::new(NoiseTable::new(options.noise_table_size))),
+        };
+        // fireballs burst on the highest plane
+        let ground = objects
+            .iter()
+            .filter(|object| object.shape == Shape::Plane)
+            .map(|object| object.height)
+            .reduce(f64::max)
+            .filter(|_| options.collide);
+        for object in objects.iter_mut() {
+            object.noise_table = noise_table.clone();
+            object.time = options.time;
+            object.mouse = options.mouse / Vec2d::new(camera.width as f64, camera.height as f64);
+            if let Some(end) = object.rotation_end {
+                object.rotation = object.rotation.slerp(end, options.progress());
+            }
+            if options.lifecycle > 0. && object.shape == Shape::Fireball {
+                Lifecycle::at(options.time / options.lifecycle).apply(object);
+            }
+            if object.shape == Shape::Fireball {
+                object.ground = ground;
+                object.mushroom = options.mushroom;
+            }
+            object.noise_offset = noise_offset(object.seed.unwrap_or(options.seed));
+            object.warp = options.turbulence;
+            object.warp_offset = (FLOW_CHANGE - options.wind) * options.time;
+            object.lod_eye = camera.position;
+            object.lod_spread = lod_spread;
+            object.noise_octaves = options.noise_octaves;
+            // the steps are shortened by the stretch of the warp, so it takes as many more
+            object.march_steps =
+                (options.march_steps as f64 * warp_stretch(options.turbulence)).ceil() as usize;
+            object.max_distance = options.far;
+            object.surface_epsilon = options.surface_epsilon;
+            object.posterize = options.toon;
+        }
+        Scene { objects }
+    }
+
+    pub fn object(&self, hit: &Hit) -> &Object {
+        &self.objects[hit.object]
+    }
+
+    // closest hit along the ray among the objects accepted by `filter`
+    pub fn intersect_filtered(&self, ray: Ray, filter: impl Fn(&Object) -> bool) -> Option<Hit> {
+        count_ray();
+        let mut closest: Option<Hit> = None;
+        for (i, object) in self.objects.iter().enumerate() {
+            if !filter(object) {
+                continue;
+            }
+            if let Some(hit) = object.trace(ray) {
+                if closest.is_none_or(|c| hit.distance < c.distance) {
+                    closest = Some(Hit { object: i, ..hit });
+                }
+            }
+        }
+        closest
+    }
+
+    pub fn intersect(&self, ray: Ray) -> Option<Hit> {
+        self.intersect_filtered(ray, |_| true)
+    }
+
+    // The silhouette a ray that misses the scene passes within a pixel of, and the part of
+    // the pixel it covers, for pixels `spread` wide at unit distance. The coverage falls
+    // from 1 for a ray grazing the surface to 0 a pixel away: silhouettes grow by half a
+    // pixel, but the hits inside stay fully covered without an edge inside of them.
+    pub fn silhouette(&self, ray: Ray, spread: f64) -> Option<(Hit, f64)> {
+        let mut closest: Option<(Hit, f64)> = None;
+        for (i, object) in self.objects.iter().enumerate() {
+            let Some((hit, angle)) = object.approach(ray) else {
+                continue;
+            };
+            let coverage = 1. - angle / spread;
+            if coverage > 0. && closest.is_none_or(|(_, c)| coverage > c) {
+                closest = Some((Hit { object: i, ..hit }, coverage));
+            }
+        }
+        closest
+    }
+}
@@ -0,0 +1,568 @@
+#[cfg(feature = "std")]
+use crate::bench::count_evaluation;
+use crate::noise::{
+    curl_noise, fbm_lipschitz, fbm_range, smooth_octaves, NoiseTable, Octaves, CURL_LIPSCHITZ,
+    FBM_OCTAVES,
+};
+use crate::ray::{Hit, Ray};
+#[cfg(not(feature = "std"))]
+use crate::vec3d::Float;
+use crate::vec3d::Vec3d;
+
+// the evaluations are counted for the benchmark, which needs std
+#[cfg(not(feature = "std"))]
+fn count_evaluation() {}
+
+pub const SPHERE_RADIUS: f64 = 1.5;
+pub const NOISE_AMPLITUDE: f64 = 1.;
+
+// noise frequency of the displacement
+const DISPLACEMENT_SCALE: f64 = 3.4;
+// bound on the gradient of `displacement_noise` of `octaves` octaves
+pub const fn displacement_lipschitz(octaves: usize) -> f64 {
+    fbm_lipschitz(octaves) * DISPLACEMENT_SCALE
+}
+
+pub const DISPLACEMENT_LIPSCHITZ: f64 = displacement_lipschitz(FBM_OCTAVES);
+
+// frequency of the curl noise the displacement is warped by, its swirls are several times
+// the size of the displacement's features
+const WARP_SCALE: f64 = 0.5;
+
+// how much a warp of `warp` units stretches distances in the noise it is applied to
+pub fn warp_stretch(warp: f64) -> f64 {
+    1. + warp * WARP_SCALE * CURL_LIPSCHITZ
+}
+
+// The fractal noise a surface is displaced by.
+#[derive(Copy, Clone)]
+pub struct Displacement<'a> {
+    pub amplitude: f64,
+    // where the noise is sampled around
+    pub offset: Vec3d,
+    // the sample points are pushed along a curl noise flow sampled around `warp_offset`,
+    // scaled by `warp`, which shears the noise
+    pub warp: f64,
+    pub warp_offset: Vec3d,
+    // detail finer than this is left out, and the octaves after `octaves`
+    pub footprint: f64,
+    pub octaves: usize,
+    // baked lattice values, exact noise without
+    pub table: Option<&'a NoiseTable>,
+}
+
+// the FBM of the displacement at `p`, in [0, 1] and without the amplitude
+pub fn displacement_noise(p: Vec3d, noise: Displacement) -> f64 {
+    displacement_octaves(p, noise).sum()
+}
+
+// the octaves of `displacement_noise`, before they are summed
+pub fn displacement_octaves<'a>(
+    p: Vec3d,
+    noise: Displacement<'a>,
+) -> Octaves<impl Fn(Vec3d) -> f64 + 'a> {
+    let p = match noise.warp > 0. {
+        true => p + curl_noise((p + noise.warp_offset) * WARP_SCALE) * noise.warp,
+        false => p,
+    };
+    let p = (p + noise.offset) * DISPLACEMENT_SCALE;
+    let footprint = noise.footprint * DISPLACEMENT_SCALE;
+    smooth_octaves(p, footprint, noise.octaves, noise.table)
+}
+
+// inward offset of a surface at `p` by up to the amplitude
+pub fn displacement(p: Vec3d, noise: Displacement) -> f64 {
+    -displacement_noise(p, noise) * noise.amplitude
+}
+
+// Any distance field `sdf` displaced inwards by `amplitude` times `noise`, a source of values
+// in [0, 1] whose gradient is at most `noise_lipschitz`. `sdf_lipschitz` bounds the gradient
+// of the field itself, 1 for distances and distance bounds. The sum is no distance anymore:
+// `bound` divides it by its Lipschitz bound, which never overshoots the displaced surface,
+// so it can be marched with full steps.
+#[derive(Copy, Clone)]
+pub struct Displace<S, N> {
+    pub sdf: S,
+    pub sdf_lipschitz: f64,
+    pub noise: N,
+    pub amplitude: f64,
+    pub noise_lipschitz: f64,
+}
+
+impl<S: Fn(Vec3d) -> f64, N: Fn(Vec3d) -> f64> Displace<S, N> {
+    // the noise isn't evaluated without an amplitude
+    pub fn distance(&self, p: Vec3d) -> f64 {
+        match self.amplitude == 0. {
+            true => (self.sdf)(p),
+            false => (self.sdf)(p) + self.amplitude * (self.noise)(p),
+        }
+    }
+
+    // the largest change of `distance` per unit
+    pub fn lipschitz(&self) -> f64 {
+        self.sdf_lipschitz + self.amplitude.abs() * self.noise_lipschitz
+    }
+
+    pub fn bound(&self, p: Vec3d) -> f64 {
+        self.distance(p) / self.lipschitz()
+    }
+}
+
+// Safe steps along a field displaced by `amplitude` times `displacement_noise`. The octaves
+// are summed one at a time: the first `k` of them leave the rest adding between 0 and
+// their range, so the field is at least the partial sum with the rest at whichever end
+// lowers it, and that drops by at most the bound of the first `k` per unit. Every `k` gives
+// a step that stays outside of the surface and the longest is taken, so the fine octaves,
+// steep but small, only shorten the steps near the surface. They aren't even evaluated
+// while the octaves left can't lengthen the step.
+#[derive(Copy, Clone)]
+pub struct DisplacedSteps {
+    // the range of the octaves after the first `k` and the bound on the first `k`, by `k`
+    bounds: [(f64, f64); FBM_OCTAVES + 1],
+    amplitude: f64,
+}
+
+impl DisplacedSteps {
+    // `sdf_lipschitz` bounds the undisplaced field and `stretch` is the warp's, only the
+    // first `octaves` octaves vary
+    pub fn new(sdf_lipschitz: f64, amplitude: f64, stretch: f64, octaves: usize) -> Self {
+        let mut bounds = [(0., 0.); FBM_OCTAVES + 1];
+        for (k, bound) in bounds.iter_mut().enumerate() {
+            *bound = (
+                fbm_range(k, FBM_OCTAVES),
+                sdf_lipschitz + amplitude.abs() * displacement_lipschitz(k.min(octaves)) * stretch,
+            );
+        }
+        DisplacedSteps { bounds, amplitude }
+    }
+
+    // the Lipschitz bound of the displaced field, with every octave summed
+    pub fn lipschitz(&self) -> f64 {
+        self.bounds[FBM_OCTAVES].1
+    }
+
+    // How far a ray can advance from where the undisplaced field is `sdf` and the noise has
+    // the `octaves`. Once it is summed the step is at least the displaced field over its
+    // bound, so it drops below the epsilon over the bound only where the field does.
+    pub fn step(&self, sdf: f64, mut octaves: Octaves<impl Fn(Vec3d) -> f64>) -> f64 {
+        let mut step = f64::NEG_INFINITY;
+        loop {
+            let k = octaves.added();
+            let (range, lipschitz) = self.bounds[k];
+            let partial = sdf + self.amplitude * octaves.value();
+            step = step.max((partial + self.amplitude.min(0.) * range) / lipschitz);
+            // the longest step the octaves left could give
+            let most = match self.bounds.get(k + 1) {
+                Some(&(_, next)) => (partial + self.amplitude.max(0.) * range) / next,
+                None => return step,
+            };
+            if step >= most {
+                return step;
+            }
+            octaves.add();
+        }
+    }
+}
+
+// sphere of `radius` displaced inwards by the noise
+pub fn fireball(p: Vec3d, radius: f64, noise: Displacement) -> f64 {
+    p.length() - (radius + displacement(p, noise))
+}
+
+// `fireball` as the safe `steps` of its displacement
+pub fn fireball_step(p: Vec3d, radius: f64, noise: Displacement, steps: &DisplacedSteps) -> f64 {
+    steps.step(p.length() - radius, displacement_octaves(p, noise))
+}
+
+// where a ray from `orig` towards `dir` leaves the sphere of `radius` around the origin
+fn sphere_exit(orig: Vec3d, dir: Vec3d, radius: f64) -> f64 {
+    let b = orig.dot(dir);
+    (b * b - (orig.dot(orig) - radius * radius)).max(0.).sqrt() - b
+}
+
+const REFINE_STEPS: usize = 8;
+
+// Bisects between `lo`, outside the surface, and `hi`, where a marcher found it, for the
+// first distance along the ray where `above` drops to 0. A step that overshot into the
+// surface is pulled back to within 1/256 of it.
+fn bisect(mut lo: f64, mut hi: f64, above: impl Fn(f64) -> f64) -> f64 {
+    for _j in 0..REFINE_STEPS {
+        let mid = (lo + hi) / 2.;
+        if above(mid) > 0. {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    hi
+}
+
+// Marches a field without a known Lipschitz bound in steps of a tenth of its value, at least
+// 0.01, until it drops below `epsilon`. Features thinner than a step can be missed, and a
+// step that crosses the surface is bisected back onto it. Rays stop where they leave the
+// sphere of `radius`, or `max_distance` from their origin.
+pub fn sphere_trace(
+    ray: Ray,
+    radius: f64,
+    epsilon: f64,
+    max_distance: f64,
+    sdf: impl Fn(Vec3d) -> f64,
+) -> Option<Hit> {
+    let Ray { origin: orig, dir } = ray;
+    if orig.dot(orig) - orig.dot(dir).powi(2) > radius.powi(2) {
+        return None;
+    } // early discard
+
+    let far = sphere_exit(orig, dir, radius).min(max_distance);
+    let mut pos = orig;
+    let (mut t, mut previous) = (0., 0.);
+    for i in 0..128 {
+        if t > far {
+            return None;
+        }
+        count_evaluation();
+        let d = sdf(pos);
+        if d < epsilon {
+            return Some(refine(ray, previous, Hit::new(pos, t, i + 1), d, &sdf));
+        }
+        let step = (d * 0.1).max(0.01);
+        previous = t;
+        pos += dir * step;
+        t += step;
+    }
+    None
+}
+
+// A `hit` with the field at `d` moved onto the surface between it and the last sample
+// outside, `lo` along the ray. Hits short of the surface are kept as they are.
+fn refine(ray: Ray, lo: f64, hit: Hit, d: f64, sdf: impl Fn(Vec3d) -> f64) -> Hit {
+    if d >= 0. {
+        return hit;
+    }
+    let t = bisect(lo, hit.distance, |t| {
+        count_evaluation();
+        sdf(ray.at(t))
+    });
+    Hit::new(ray.at(t), t, hit.steps)
+}
+
+pub fn distance_field_normal(pos: Vec3d, eps: f64, sdf: impl Fn(Vec3d) -> f64) -> Vec3d {
+    let sdf = |p: Vec3d| {
+        count_evaluation();
+        sdf(p)
+    };
+    let d = sdf(pos);
+    let nx = sdf(pos + Vec3d::new(eps, 0., 0.)) - d;
+    let ny = sdf(pos + Vec3d::new(0., eps, 0.)) - d;
+    let nz = sdf(pos + Vec3d::new(0., 0., eps)) - d;
+    Vec3d::new(nx, ny, nz).normalized()
+}
+
+pub fn plane_distance(p: Vec3d, height: f64) -> f64 {
+    p.y - height
+}
+
+// The plane SDF is exact, so it is marched with full steps up to `max_distance`.
+pub fn plane_trace(ray: Ray, height: f64, max_distance: f64) -> Option<Hit> {
+    let mut pos = ray.origin;
+    let mut traveled = 0.;
+    for i in 0..128 {
+        count_evaluation();
+        let d = plane_distance(pos, height);
+        if d < 1e-4 {
+            return Some(Hit::new(pos, traveled, i + 1));
+        }
+        traveled += d;
+        if traveled > max_distance {
+            return None;
+        }
+        pos += ray.dir * d;
+    }
+    None
+}
+
+// Exact or bounded distance estimators are marched with full steps inside the bounding
+// sphere of `radius`, a hit is reported once the estimate drops below `epsilon`. Rays still
+// marching after `steps` steps or `max_distance` from their origin miss.
+pub fn ray_march(
+    ray: Ray,
+    radius: f64,
+    epsilon: f64,
+    steps: usize,
+    max_distance: f64,
+    sdf: impl Fn(Vec3d) -> f64,
+) -> Option<Hit> {
+    let b = ray.origin.dot(ray.dir);
+    let disc = b * b - (ray.origin.dot(ray.origin) - radius * radius);
+    if disc < 0. {
+        return None;
+    }
+    let (near, far) = (
+        (-b - disc.sqrt()).max(0.),
+        (-b + disc.sqrt()).min(max_distance),
+    );
+    let mut t = near;
+    for i in 0..steps {
+        if t > far {
+            return None;
+        }
+        let pos = ray.at(t);
+        count_evaluation();
+        let d = sdf(pos);
+        if d < epsilon {
+            return Some(Hit::new(pos, t, i + 1));
+        }
+        t += d;
+    }
+    None
+}
+
+// Marches a ray `ray_march` finds missing for where it passes closest to the surface as
+// seen from its origin, the least `sdf(p) / t` along it, returned with that ratio. The
+// steps shrink where the ray grazes the surface, so the minimum is sampled closely.
+pub fn closest_approach(
+    ray: Ray,
+    radius: f64,
+    epsilon: f64,
+    steps: usize,
+    max_distance: f64,
+    sdf: impl Fn(Vec3d) -> f64,
+) -> Option<(Hit, f64)> {
+    let b = ray.origin.dot(ray.dir);
+    let disc = b * b - (ray.origin.dot(ray.origin) - radius * radius);
+    if disc < 0. {
+        return None;
+    }
+    let (near, far) = (
+        (-b - disc.sqrt()).max(0.),
+        (-b + disc.sqrt()).min(max_distance),
+    );
+    let mut t = near;
+    let mut closest: Option<(Hit, f64)> = None;
+    for i in 0..steps {
+        if t > far {
+            break;
+        }
+        let pos = ray.at(t);
+        count_evaluation();
+        let d = sdf(pos);
+        let ratio = d.max(0.) / t.max(epsilon);
+        if closest.is_none_or(|(_, closest)| ratio < closest) {
+            closest = Some((Hit::new(pos, t, i + 1), ratio));
+        }
+        if d < epsilon {
+            break;
+        }
+        t += d;
+    }
+    closest
+}
+
+// Steps of the marchers by default, enough for the short steps of displaced fields, whose
+// bounds divide them by up to 30.
+pub const MARCH_STEPS: usize = 512;
+
+const BAILOUT: f64 = 2.;
+
+// What a fractal's iteration left behind at a point, both roughly in [0, 1] and meant to
+// index a palette the way fractal renderers color them.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Orbit {
+    // how close the iterated point came to the origin
+    pub trap: f64,
+    // the iteration the point escaped at over the iterations run, smoothed between whole
+    // counts, 1 for points that never escape. Menger sponges count the level of the hole
+    // that carves the point instead.
+    pub iterations: f64,
+}
+
+// Mandelbulb of degree `power` in spherical coordinates, White and Nylander's formula.
+pub fn mandelbulb(p: Vec3d, power: f64, iterations: usize, orbit: &mut Orbit) -> f64 {
+    let mut z = p;
+    let mut dr = 1.;
+    let mut r = z.length();
+    let mut trap = f64::INFINITY;
+    let mut escaped = iterations as f64;
+    for i in 0..iterations {
+        if r > BAILOUT {
+            // how far past the bailout the point landed gives the fraction of a step
+            escaped = i as f64 - (r.ln() / BAILOUT.ln()).ln() / power.ln();
+            break;
+        }
+        trap = trap.min(r);
+        dr = r.powf(power - 1.) * power * dr + 1.;
+        let theta = (z.z / r).clamp(-1., 1.).acos() * power;
+        let phi = z.y.atan2(z.x) * power;
+        z = Vec3d::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        ) * r.powf(power)
+            + p;
+        r = z.length();
+    }
+    *orbit = Orbit {
+        trap: trap.min(1.),
+        iterations: (escaped / iterations.max(1) as f64).clamp(0., 1.),
+    };
+    0.5 * r.max(1e-9).ln() * r / dr
+}
+
+fn box_distance(p: Vec3d, half_size: f64) -> f64 {
+    let q = p.abs() - half_size;
+    let outside = q.max(Vec3d::zero()).length();
+    outside + q.max_element().min(0.)
+}
+
+// Menger sponge filling the cube [-1, 1]^3, after Inigo Quilez.
+pub fn menger_sponge(p: Vec3d, iterations: usize, orbit: &mut Orbit) -> f64 {
+    let mut d = box_distance(p, 1.);
+    let mut scale = 1.;
+    let mut trap = f64::INFINITY;
+    let mut level = 0;
+    for i in 0..iterations {
+        let a = Vec3d::new(
+            (p.x * scale).rem_euclid(2.) - 1.,
+            (p.y * scale).rem_euclid(2.) - 1.,
+            (p.z * scale).rem_euclid(2.) - 1.,
+        );
+        scale *= 3.;
+        let r = (1. - 3. * a.abs()).abs();
+        let cross = r.x.max(r.y).min(r.y.max(r.z)).min(r.z.max(r.x));
+        let hole = (cross - 1.) / scale;
+        if hole > d {
+            d = hole;
+            level = i + 1;
+        }
+        trap = trap.min(a.length() / 3f64.sqrt());
+    }
+    *orbit = Orbit {
+        trap,
+        iterations: level as f64 / iterations.max(1) as f64,
+    };
+    d
+}
+
+fn quaternion_mul(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+    [
+        a[0] * b[0] - a[1] * b[1] - a[2] * b[2] - a[3] * b[3],
+        a[0] * b[1] + a[1] * b[0] + a[2] * b[3] - a[3] * b[2],
+        a[0] * b[2] - a[1] * b[3] + a[2] * b[0] + a[3] * b[1],
+        a[0] * b[3] + a[1] * b[2] - a[2] * b[1] + a[3] * b[0],
+    ]
+}
+
+fn quaternion_length(q: [f64; 4]) -> f64 {
+    (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt()
+}
+
+// 3D slice (w = 0) of the quaternion Julia set of z -> z^2 + c.
+pub fn julia(p: Vec3d, c: [f64; 4], iterations: usize, orbit: &mut Orbit) -> f64 {
+    let mut z = [p.x, p.y, p.z, 0.];
+    let mut dz = [1., 0., 0., 0.];
+    let mut trap = f64::INFINITY;
+    let mut escaped = iterations as f64;
+    for i in 0..iterations {
+        let dz2 = quaternion_mul(z, dz);
+        dz = [2. * dz2[0], 2. * dz2[1], 2. * dz2[2], 2. * dz2[3]];
+        let z2 = quaternion_mul(z, z);
+        z = [z2[0] + c[0], z2[1] + c[1], z2[2] + c[2], z2[3] + c[3]];
+        let r = quaternion_length(z);
+        trap = trap.min(r);
+        if r > BAILOUT * 2. {
+            escaped = (i + 1) as f64 - (r.ln() / (BAILOUT * 2.).ln()).ln() / 2f64.ln();
+            break;
+        }
+    }
+    *orbit = Orbit {
+        trap: trap.min(1.),
+        iterations: (escaped / iterations.max(1) as f64).clamp(0., 1.),
+    };
+    let r = quaternion_length(z);
+    0.5 * r * r.max(1e-9).ln() / quaternion_length(dz)
+}
+
+const HEIGHTFIELD_STEPS: usize = 512;
+
+// Marches a height field y = height(x, z) whose slope is bounded by `lipschitz`: the vertical
+// distance to the surface times 1/sqrt(1 + L^2) never overshoots it. The hit is refined by
+// bisection, rays above `top` heading upwards leave early.
+pub fn heightfield_trace(
+    ray: Ray,
+    height: impl Fn(f64, f64) -> f64,
+    lipschitz: f64,
+    top: f64,
+    max_distance: f64,
+) -> Option<Hit> {
+    let Ray { origin: orig, dir } = ray;
+    let step_scale = 1. / (1. + lipschitz * lipschitz).sqrt();
+    let above = |t: f64| {
+        count_evaluation();
+        let p = orig + dir * t;
+        p.y - height(p.x, p.z)
+    };
+    let mut t = 0.;
+    let mut previous = 0.;
+    for i in 0..HEIGHTFIELD_STEPS {
+        let p = orig + dir * t;
+        if t > max_distance || (p.y > top && dir.y >= 0.) {
+            return None;
+        }
+        let d = above(t);
+        if d < 1e-4 * (1. + t) {
+            let t = bisect(previous, t, above);
+            return Some(Hit::new(orig + dir * t, t, i + 1));
+        }
+        previous = t;
+        // above the relief the ray can safely skip down to its top
+        let skip = if p.y > top && dir.y < 0. {
+            (p.y - top) / -dir.y
+        } else {
+            0.
+        };
+        t += (d * step_scale).max(skip).max(1e-3 * t);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fireball, fireball_step, DisplacedSteps, Displacement};
+    use crate::rng::Rng;
+    use crate::vec3d::Vec3d;
+
+    // the fireball is nowhere on or inside the surface along a step from `p` towards `dir`
+    fn stays_outside(p: Vec3d, dir: Vec3d, noise: Displacement, steps: &DisplacedSteps) -> bool {
+        let step = fireball_step(p, 1.5, noise, steps);
+        (1..=100).all(|i| fireball(p + dir * (step * i as f64 / 100.), 1.5, noise) > 0.)
+    }
+
+    #[test]
+    fn displaced_steps_never_cross_the_surface() {
+        let mut rng = Rng::new(1, 0);
+        let mut random = || Vec3d::new(rng.next_f64(), rng.next_f64(), rng.next_f64()) * 4. - 2.;
+        for (amplitude, octaves, footprint) in [(1., 4, 0.), (-0.7, 4, 0.), (2., 2, 0.02)] {
+            let noise = Displacement {
+                amplitude,
+                offset: Vec3d::zero(),
+                warp: 0.,
+                warp_offset: Vec3d::zero(),
+                footprint,
+                octaves,
+                table: None,
+            };
+            let steps = DisplacedSteps::new(1., amplitude, 1., octaves);
+            for _ in 0..2000 {
+                let (p, dir) = (random(), random().normalized());
+                if fireball(p, 1.5, noise) > 0. {
+                    assert!(
+                        stays_outside(p, dir, noise, &steps),
+                        "{} towards {}",
+                        p,
+                        dir
+                    );
+                }
+            }
+        }
+    }
+}
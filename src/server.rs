@@ -0,0 +1,545 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::image::Format;
+use crate::json::{self, Value};
+use crate::metadata::{self, Stats};
+use crate::options::Options;
+
+// where a bare `--serve` listens, only reachable from the machine itself
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+// largest scene accepted in a request body
+const MAX_BODY: usize = 1 << 20;
+// how long a client may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+// largest image side and sample count a posted scene may ask for
+const MAX_SIZE: usize = 8192;
+const MAX_SPP: usize = 4096;
+
+// The settings a posted scene may change: what is rendered and how it looks. Anything that
+// reads or writes files on the server (scene files, plugins, meshes, heightmaps, outputs),
+// or changes how the server itself runs, is refused.
+const RENDER_KEYS: &[&str] = &[
+    "width",
+    "height",
+    "resolution-scale",
+    "quality",
+    "fov",
+    "camera-position",
+    "camera-target",
+    "camera-orbit",
+    "camera-orbit-end",
+    "lens-radius",
+    "lens-focus",
+    "lens-blades",
+    "lens-rotation",
+    "lens-cat-eye",
+    "distortion",
+    "format",
+    "bit-depth",
+    "dither",
+    "spp",
+    "min-spp",
+    "target-error",
+    "sampler",
+    "filter",
+    "filter-radius",
+    "seed",
+    "integrator",
+    "max-bounces",
+    "nee",
+    "density",
+    "anisotropy",
+    "scattering-albedo",
+    "light-shape",
+    "light-size",
+    "light-position",
+    "light-radius",
+    "light-color",
+    "light-intensity",
+    "light-falloff",
+    "light-target",
+    "light-spot-angle",
+    "light-spot-blend",
+    "light-shadows",
+    "light-objects",
+    "object",
+    "instance",
+    "time",
+    "duration",
+    "wind",
+    "turbulence",
+    "lifecycle",
+    "collide",
+    "mushroom",
+    "noise-lod",
+    "noise-octaves",
+    "march-steps",
+    "shadow-samples",
+    "far",
+    "surface-epsilon",
+    "normal-offset",
+    "coarse",
+    "edge-aa",
+    "noise",
+    "alpha",
+    "embers",
+    "ember-speed",
+    "ember-lifetime",
+    "ember-size",
+    "ember-intensity",
+    "floor",
+    "floor-texture",
+    "floor-scale",
+    "sky",
+    "background",
+    "checker-size",
+    "sun-direction",
+    "turbidity",
+    "fog",
+    "fog-color",
+    "denoise",
+    "exposure",
+    "auto-exposure",
+    "exposure-key",
+    "aperture",
+    "shutter",
+    "iso",
+    "bloom",
+    "bloom-threshold",
+    "bloom-radius",
+    "vignette",
+    "chromatic-aberration",
+    "grain",
+    "toon",
+    "ink",
+    "stereo",
+    "interocular",
+    "convergence",
+];
+
+// the same for the keys of `objects`, set as `object-<key>`
+const OBJECT_KEYS: &[&str] = &[
+    "shape",
+    "center",
+    "seed",
+    "rotation",
+    "rotation-end",
+    "radius",
+    "noise-amplitude",
+    "height",
+    "power",
+    "iterations",
+    "mesh-resolution",
+    "terrain-scale",
+    "heightmap-size",
+    "lipschitz",
+    "sdf",
+    "plugin",
+    "julia-c",
+    "shading",
+    "palette",
+    "albedo",
+    "texture",
+    "texture-scale",
+    "heat-core",
+    "heat-depth",
+    "heat-falloff",
+    "heat-bias",
+    "heat-gain",
+    "smoke",
+    "smoke-level",
+    "smoke-breakup",
+    "wrap",
+    "ambient",
+    "rim",
+    "rim-width",
+    "tint-top",
+    "tint-bottom",
+];
+
+enum Status {
+    Queued,
+    Running,
+    // the encoded image
+    Done {
+        width: usize,
+        height: usize,
+        format: Format,
+        bytes: Arc<Vec<u8>>,
+        stats: Stats,
+    },
+    // the render panicked
+    Failed(String),
+}
+
+// Every job posted since the server started, job `id` at index `id - 1`.
+#[derive(Default)]
+struct Jobs {
+    status: Mutex<Vec<Status>>,
+    // signalled whenever a job finishes
+    finished: Condvar,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    location: Option<String>,
+    body: Arc<Vec<u8>>,
+}
+
+impl Response {
+    fn json(status: &'static str, body: String) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            location: None,
+            body: Arc::new(body.into_bytes()),
+        }
+    }
+
+    fn error(status: &'static str, message: &str) -> Response {
+        Response::json(
+            status,
+            format!("{{\"error\": {}}}\n", metadata::quote(message)),
+        )
+    }
+}
+
+// the message for a failed read, naming the timeout when the client went quiet
+fn read_error(err: std::io::Error) -> String {
+    match err.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+            format!("no request within {} seconds", READ_TIMEOUT.as_secs())
+        }
+        _ => err.to_string(),
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Result<Request, String> {
+    stream
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .map_err(|err| err.to_string())?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(read_error)?;
+    let mut words = line.split_whitespace();
+    let (method, path) = match (words.next(), words.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err("malformed request line".to_string()),
+    };
+    let mut length = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line).map_err(read_error)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| "invalid Content-Length".to_string())?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(format!("request body over {} bytes", MAX_BODY));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(read_error)?;
+    Ok(Request { method, path, body })
+}
+
+fn write_response(mut stream: &TcpStream, response: &Response) -> std::io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    if let Some(location) = &response.location {
+        head.push_str(&format!("Location: {}\r\n", location));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+// Turns a JSON scene into settings the way scene files are: nested objects prefix their
+// keys (`{"light": {"intensity": 500}}` is `light-intensity`), arrays are comma-separated
+// lists, and every member of `objects` starts a new `[[object]]`.
+fn flatten(
+    prefix: &str,
+    value: &Value,
+    settings: &mut Vec<(String, String)>,
+) -> Result<(), String> {
+    let scalar = |value: &Value| match value {
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(x) => Some(x.to_string()),
+        Value::String(s) => Some(s.clone()),
+        _ => None,
+    };
+    match value {
+        Value::Object(members) => {
+            for (key, value) in members {
+                let key = key.replace('_', "-");
+                let key = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{}-{}", prefix, key)
+                };
+                match value {
+                    Value::Array(objects) if key == "objects" => {
+                        for object in objects {
+                            settings.push(("object".to_string(), String::new()));
+                            flatten("object", object, settings)?;
+                        }
+                    }
+                    value => flatten(&key, value, settings)?,
+                }
+            }
+        }
+        Value::Array(items) => {
+            let items: Option<Vec<String>> = items.iter().map(scalar).collect();
+            let items =
+                items.ok_or(format!("'{}' must be a list of numbers or strings", prefix))?;
+            settings.push((prefix.to_string(), items.join(",")));
+        }
+        Value::Null => {}
+        value => settings.push((prefix.to_string(), scalar(value).unwrap())),
+    }
+    Ok(())
+}
+
+// the options of a posted scene, on top of the ones the server was started with
+fn job_options(base: &Options, body: &[u8]) -> Result<Options, Error> {
+    let text = std::str::from_utf8(body).map_err(|_| "the scene is not UTF-8")?;
+    let scene = json::parse(text)?;
+    if !matches!(scene, Value::Object(_)) {
+        return Err("the scene must be a JSON object".into());
+    }
+    let mut settings = Vec::new();
+    flatten("", &scene, &mut settings)?;
+    let mut options = base.clone();
+    for (key, value) in &settings {
+        let allowed = match key.strip_prefix("object-") {
+            Some(key) => OBJECT_KEYS.contains(&key),
+            None => RENDER_KEYS.contains(&key.as_str()),
+        };
+        if !allowed {
+            return Err(Error::Parse(format!(
+                "'{}' can't be set by a posted scene",
+                key
+            )));
+        }
+        options.set(key, value)?;
+    }
+    options.validate()?;
+    let (width, height) = options.resolution();
+    if width > MAX_SIZE || height > MAX_SIZE {
+        return Err(Error::Invalid(format!(
+            "the image must be at most {0}x{0}",
+            MAX_SIZE
+        )));
+    }
+    if options.spp > MAX_SPP || options.min_spp > MAX_SPP {
+        return Err(Error::Invalid(format!(
+            "at most {} samples per pixel",
+            MAX_SPP
+        )));
+    }
+    if options.format.is_none() {
+        options.format = Some(Format::Png);
+    }
+    Ok(options)
+}
+
+fn status_json(id: usize, status: &Status) -> String {
+    match status {
+        Status::Queued => format!("{{\"id\": {}, \"status\": \"queued\"}}\n", id),
+        Status::Running => format!("{{\"id\": {}, \"status\": \"running\"}}\n", id),
+        Status::Done {
+            width,
+            height,
+            stats,
+            ..
+        } => format!(
+            "{{\"id\": {}, \"status\": \"done\", \"width\": {}, \"height\": {}, \
+             \"samples\": {}, \"render_seconds\": {}, \"image\": \"/jobs/{}/image\"}}\n",
+            id,
+            width,
+            height,
+            stats.samples,
+            stats.render.as_secs_f64(),
+            id
+        ),
+        Status::Failed(error) => format!(
+            "{{\"id\": {}, \"status\": \"failed\", \"error\": {}}}\n",
+            id,
+            metadata::quote(error)
+        ),
+    }
+}
+
+fn handle(
+    request: &Request,
+    base: &Options,
+    jobs: &Jobs,
+    queue: &Sender<(usize, Options)>,
+) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["render"]) => {
+            let options = match job_options(base, &request.body) {
+                Ok(options) => options,
+                Err(err @ Error::Invalid(_)) => {
+                    return Response::error("422 Unprocessable Entity", &err.to_string())
+                }
+                Err(err) => return Response::error("400 Bad Request", &err.to_string()),
+            };
+            let id = {
+                let mut status = jobs.status.lock().unwrap();
+                status.push(Status::Queued);
+                status.len()
+            };
+            info!("job {} queued", id);
+            queue.send((id, options)).unwrap();
+            Response {
+                location: Some(format!("/jobs/{}", id)),
+                ..Response::json("202 Accepted", status_json(id, &Status::Queued))
+            }
+        }
+        ("GET", ["jobs", id]) | ("GET", ["jobs", id, "image"]) => {
+            let mut status = jobs.status.lock().unwrap();
+            let id = match id.parse::<usize>() {
+                Ok(id) if id >= 1 && id <= status.len() => id,
+                _ => return Response::error("404 Not Found", "no such job"),
+            };
+            if segments.len() == 2 {
+                return Response::json("200 OK", status_json(id, &status[id - 1]));
+            }
+            // the image is sent as soon as the job is done
+            loop {
+                match &status[id - 1] {
+                    Status::Done { format, bytes, .. } => {
+                        return Response {
+                            status: "200 OK",
+                            content_type: format.mime_type(),
+                            location: None,
+                            body: Arc::clone(bytes),
+                        }
+                    }
+                    Status::Failed(error) => {
+                        return Response::error("500 Internal Server Error", error)
+                    }
+                    Status::Queued | Status::Running => {}
+                }
+                status = jobs.finished.wait(status).unwrap();
+            }
+        }
+        (_, ["render"]) | (_, ["jobs", ..]) => {
+            Response::error("405 Method Not Allowed", "method not allowed")
+        }
+        _ => Response::error("404 Not Found", "not found"),
+    }
+}
+
+// renders the queued jobs one after another
+fn render_jobs(jobs: &Jobs, queue: mpsc::Receiver<(usize, Options)>) {
+    for (id, options) in queue {
+        jobs.status.lock().unwrap()[id - 1] = Status::Running;
+        info!("job {} started", id);
+        // a job that panics fails on its own, the server goes on with the next one
+        let render = panic::catch_unwind(AssertUnwindSafe(|| {
+            let (image, stats) = crate::render_image(&options);
+            let encoding = options.encoding(&options.output);
+            let bytes = image.encode(&encoding, &metadata::text(&options));
+            Status::Done {
+                width: image.width(),
+                height: image.height(),
+                format: encoding.format,
+                bytes: Arc::new(bytes),
+                stats,
+            }
+        }));
+        let status = match render {
+            Ok(status) => {
+                if let Status::Done { stats, .. } = &status {
+                    info!("job {} done in {:.3} s", id, stats.render.as_secs_f64());
+                }
+                status
+            }
+            Err(payload) => {
+                let message = match payload.downcast_ref::<&str>() {
+                    Some(message) => message.to_string(),
+                    None => payload
+                        .downcast_ref::<String>()
+                        .cloned()
+                        .unwrap_or_else(|| "the render panicked".to_string()),
+                };
+                eprintln!("error: job {} failed: {}", id, message);
+                Status::Failed(message)
+            }
+        };
+        jobs.status.lock().unwrap()[id - 1] = status;
+        jobs.finished.notify_all();
+    }
+}
+
+// Serves renders over HTTP until the process is stopped. `POST /render` queues a JSON
+// scene rendered with the server's options underneath and answers with the job's id,
+// `GET /jobs/<id>` reports its status and `GET /jobs/<id>/image` sends the image, waiting
+// for it if the job isn't done yet. Jobs are rendered one at a time, each with all the
+// `--threads` of the server, and kept until the server stops.
+pub fn run(options: &Options) -> Result<(), Error> {
+    let address = options.serve.as_deref().unwrap_or(DEFAULT_ADDRESS);
+    let listener = TcpListener::bind(address).map_err(|err| Error::io(address, err))?;
+    info!("serving on http://{}", address);
+    let mut base = options.clone();
+    base.serve = None;
+    base.settings.retain(|(key, _)| key != "serve");
+    let base = Arc::new(base);
+    let jobs = Arc::new(Jobs::default());
+    let (queue, pending) = mpsc::channel();
+    {
+        let jobs = Arc::clone(&jobs);
+        std::thread::spawn(move || render_jobs(&jobs, pending));
+    }
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                continue;
+            }
+        };
+        let (base, jobs, queue) = (Arc::clone(&base), Arc::clone(&jobs), queue.clone());
+        std::thread::spawn(move || {
+            let response = match read_request(&stream) {
+                Ok(request) => {
+                    debug!("{} {}", request.method, request.path);
+                    handle(&request, &base, &jobs, &queue)
+                }
+                Err(message) => Response::error("400 Bad Request", &message),
+            };
+            // the client may be gone already, nothing to do about it
+            let _ = write_response(&stream, &response);
+        });
+    }
+    Ok(())
+}
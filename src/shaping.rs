@@ -0,0 +1,137 @@
+#![allow(dead_code)]
+use core::f64::consts::PI;
+
+#[cfg(not(feature = "std"))]
+use crate::vec3d::Float;
+
+// Shaping functions for distance fields and procedural values: smooth minima and maxima to
+// blend shapes, steps, remapping and easing curves. Functions of [0, 1] map 0 to 0 and 1
+// to 1.
+
+// Polynomial smooth minimum of two distances, blended over a band of width `k` around
+// where they are equal, by at most k / 4.
+pub fn smin(a: f64, b: f64, k: f64) -> f64 {
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0., 1.);
+    b + (a - b) * h - k * h * (1. - h)
+}
+
+// smooth maximum, the intersection counterpart of `smin`
+pub fn smax(a: f64, b: f64, k: f64) -> f64 {
+    -smin(-a, -b, k)
+}
+
+// `smin` with a cubic blend, continuous in the second derivative too, by at most k / 6
+pub fn smin_cubic(a: f64, b: f64, k: f64) -> f64 {
+    let h = (k - (a - b).abs()).max(0.) / k;
+    a.min(b) - h * h * h * k / 6.
+}
+
+pub fn smax_cubic(a: f64, b: f64, k: f64) -> f64 {
+    -smin_cubic(-a, -b, k)
+}
+
+// Exponential smooth minimum, the log of the sum of the exponentials, `k` being the
+// distance over which they fall off. It blends everywhere and lowers equal distances by
+// k ln 2, but the result doesn't depend on the order of several of them.
+pub fn smin_exp(a: f64, b: f64, k: f64) -> f64 {
+    let m = a.min(b);
+    // relative to the minimum, so the exponentials don't underflow far from the surface
+    m - k * ((-(a - m) / k).exp() + (-(b - m) / k).exp()).ln()
+}
+
+pub fn smax_exp(a: f64, b: f64, k: f64) -> f64 {
+    -smin_exp(-a, -b, k)
+}
+
+// from 0 at `edge0` to 1 at `edge1` along a straight line, clamped
+pub fn linearstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    ((x - edge0) / (edge1 - edge0)).clamp(0., 1.)
+}
+
+// Hermite interpolation from 0 at `edge0` to 1 at `edge1`, like GLSL's `smoothstep`
+pub fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let x = linearstep(edge0, edge1, x);
+    x * x * (3. - 2. * x)
+}
+
+// Perlin's quintic step, flat in the second derivative as well at both ends
+pub fn smootherstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let x = linearstep(edge0, edge1, x);
+    x * x * x * (x * (6. * x - 15.) + 10.)
+}
+
+// `x` moved linearly from the range of `in_min` to `in_max` onto `out_min` to `out_max`,
+// not clamped
+pub fn remap(x: f64, in_min: f64, in_max: f64, out_min: f64, out_max: f64) -> f64 {
+    out_min + (x - in_min) / (in_max - in_min) * (out_max - out_min)
+}
+
+// Schlick's bias on [0, 1]: `x` bent towards 1 for `b` above 0.5 and towards 0 below,
+// 0.5 going to `b`.
+pub fn bias(x: f64, b: f64) -> f64 {
+    x / ((1. / b - 2.) * (1. - x) + 1.)
+}
+
+// Schlick's gain on [0, 1]: an S curve for `g` below 0.5 and its inverse above, both
+// halves mirrored `bias` curves. 0.5 stays in place and `g` = 0.5 is the identity.
+pub fn gain(x: f64, g: f64) -> f64 {
+    match x < 0.5 {
+        true => bias(2. * x, g) / 2.,
+        false => 1. - bias(2. - 2. * x, g) / 2.,
+    }
+}
+
+// 1 at `center`, falling smoothly to 0 at `width` away from it
+pub fn cubic_pulse(center: f64, width: f64, x: f64) -> f64 {
+    1. - smoothstep(0., width, (x - center).abs())
+}
+
+// rises quickly from 0 and decays exponentially, peaking at 1 where `x` = 1 / k
+pub fn exp_impulse(x: f64, k: f64) -> f64 {
+    let h = k * x;
+    h * (1. - h).exp()
+}
+
+// 0 at both ends of [0, 1] and 1 in the middle, sharper with larger `k`
+pub fn parabola(x: f64, k: f64) -> f64 {
+    (4. * x * (1. - x)).powf(k)
+}
+
+// Easing curves on [0, 1] for animation. `ease_in` starts slowly, `ease_out` ends slowly
+// and `ease_in_out` does both, symmetric around the middle.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Ease {
+    Linear,
+    Quad,
+    Cubic,
+    Quart,
+    Sine,
+    Expo,
+}
+
+impl Ease {
+    pub fn ease_in(self, t: f64) -> f64 {
+        let t = t.clamp(0., 1.);
+        match self {
+            Ease::Linear => t,
+            Ease::Quad => t * t,
+            Ease::Cubic => t * t * t,
+            Ease::Quart => t * t * t * t,
+            Ease::Sine => 1. - (t * PI / 2.).cos(),
+            // exactly 0 at the start, where 2^-10 would be left
+            Ease::Expo if t == 0. => 0.,
+            Ease::Expo => 2f64.powf(10. * t - 10.),
+        }
+    }
+
+    pub fn ease_out(self, t: f64) -> f64 {
+        1. - self.ease_in(1. - t)
+    }
+
+    pub fn ease_in_out(self, t: f64) -> f64 {
+        match t < 0.5 {
+            true => self.ease_in(2. * t) / 2.,
+            false => 1. - self.ease_in(2. - 2. * t) / 2.,
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use crate::color::Color;
+use crate::options::Options;
+use crate::vec3d::Vec3d;
+
+pub const FLAT_SKY: Color = Color::new(0.2, 0.7, 0.8);
+
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Sky {
+    Flat,
+    Atmosphere,
+    Preetham,
+}
+
+impl FromStr for Sky {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Sky, String> {
+        match s {
+            "flat" => Ok(Sky::Flat),
+            "atmosphere" => Ok(Sky::Atmosphere),
+            "preetham" => Ok(Sky::Preetham),
+            _ => Err(format!("unknown sky '{}'", s)),
+        }
+    }
+}
+
+// What camera rays that miss the scene show. Only the look of the background changes,
+// the scene is still lit by the sky.
+#[derive(Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Backdrop {
+    Sky,
+    // left black, for compositing over the alpha channel
+    Transparent,
+    Color(Color),
+    // gray squares of `checker_size` pixels, like image editors show transparency
+    Checker,
+}
+
+impl FromStr for Backdrop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Backdrop, String> {
+        match s {
+            "sky" => Ok(Backdrop::Sky),
+            "transparent" => Ok(Backdrop::Transparent),
+            "checker" => Ok(Backdrop::Checker),
+            _ => s
+                .parse()
+                .map(Backdrop::Color)
+                .map_err(|_| format!("unknown background '{}'", s)),
+        }
+    }
+}
+
+const CHECKER_LIGHT: f64 = 0.8;
+const CHECKER_DARK: f64 = 0.5;
+
+// the sun follows the light unless it is given explicitly
+pub fn sun_direction(options: &Options) -> Vec3d {
+    options
+        .sun_direction
+        .unwrap_or(options.light.position)
+        .normalized()
+}
+
+// Radiance arriving from the environment along `dir`.
+pub fn background(options: &Options, dir: Vec3d) -> Color {
+    match options.sky {
+        Sky::Flat => FLAT_SKY,
+        Sky::Atmosphere => atmosphere(dir, sun_direction(options)),
+        Sky::Preetham => preetham(dir, sun_direction(options), options.turbidity),
+    }
+}
+
+// Background of the image at pixel (x, y), seen along `dir`.
+pub fn backdrop(options: &Options, dir: Vec3d, x: f64, y: f64) -> Color {
+    match options.background {
+        Backdrop::Sky => background(options, dir),
+        Backdrop::Transparent => Color::BLACK,
+        Backdrop::Color(color) => color,
+        Backdrop::Checker => {
+            let size = options.checker_size;
+            let square = (x / size).floor() + (y / size).floor();
+            if square.rem_euclid(2.) < 1. {
+                Color::gray(CHECKER_LIGHT)
+            } else {
+                Color::gray(CHECKER_DARK)
+            }
+        }
+    }
+}
+
+// Exponential fog: the surface radiance is attenuated over `distance` and replaced by
+// light scattered in by the fog. Returns (transmittance, in-scattered radiance).
+pub fn fog(options: &Options, distance: f64) -> (f64, Color) {
+    if options.fog <= 0. {
+        return (1., Color::BLACK);
+    }
+    let transmittance = (-options.fog * distance).exp();
+    (transmittance, options.fog_color * (1. - transmittance))
+}
+
+const PLANET_RADIUS: f64 = 6371e3;
+const ATMOSPHERE_RADIUS: f64 = 6471e3;
+const VIEW_HEIGHT: f64 = 1e3;
+const RAYLEIGH: Color = Color::new(5.5e-6, 13.0e-6, 22.4e-6);
+const MIE: f64 = 21e-6;
+const RAYLEIGH_HEIGHT: f64 = 8e3;
+const MIE_HEIGHT: f64 = 1.2e3;
+const MIE_G: f64 = 0.758;
+const SUN_INTENSITY: f64 = 20.;
+const VIEW_STEPS: usize = 16;
+const LIGHT_STEPS: usize = 8;
+
+// distances to the two intersections of the ray with a sphere at the planet center
+fn ray_sphere(orig: Vec3d, dir: Vec3d, radius: f64) -> Option<(f64, f64)> {
+    let b = orig.dot(dir);
+    let c = orig.dot(orig) - radius * radius;
+    let disc = b * b - c;
+    if disc < 0. {
+        return None;
+    }
+    Some((-b - disc.sqrt(), -b + disc.sqrt()))
+}
+
+fn extinction(rayleigh_depth: f64, mie_depth: f64) -> Color {
+    let tau = RAYLEIGH * rayleigh_depth + Color::gray(MIE * 1.1 * mie_depth);
+    tau.map(|tau| (-tau).exp())
+}
+
+// Single-scattering Rayleigh + Mie atmosphere seen from just above the ground,
+// with the scene's y axis pointing up and the sun in direction `sun`.
+pub fn atmosphere(dir: Vec3d, sun: Vec3d) -> Color {
+    let orig = Vec3d::new(0., PLANET_RADIUS + VIEW_HEIGHT, 0.);
+    let (_, mut far) = match ray_sphere(orig, dir, ATMOSPHERE_RADIUS) {
+        Some(t) => t,
+        None => return Color::BLACK,
+    };
+    if let Some((ground, _)) = ray_sphere(orig, dir, PLANET_RADIUS) {
+        if ground > 0. {
+            far = far.min(ground);
+        }
+    }
+
+    let step = far / VIEW_STEPS as f64;
+    let mut rayleigh = Color::BLACK;
+    let mut mie = Color::BLACK;
+    let mut rayleigh_depth = 0.;
+    let mut mie_depth = 0.;
+    for i in 0..VIEW_STEPS {
+        let p = orig + dir * ((i as f64 + 0.5) * step);
+        let height = p.length() - PLANET_RADIUS;
+        let rayleigh_step = (-height / RAYLEIGH_HEIGHT).exp() * step;
+        let mie_step = (-height / MIE_HEIGHT).exp() * step;
+        rayleigh_depth += rayleigh_step;
+        mie_depth += mie_step;
+
+        let (_, light_far) = ray_sphere(p, sun, ATMOSPHERE_RADIUS).unwrap_or((0., 0.));
+        let light_step = light_far / LIGHT_STEPS as f64;
+        let mut light_rayleigh = 0.;
+        let mut light_mie = 0.;
+        for j in 0..LIGHT_STEPS {
+            let q = p + sun * ((j as f64 + 0.5) * light_step);
+            let height = q.length() - PLANET_RADIUS;
+            light_rayleigh += (-height / RAYLEIGH_HEIGHT).exp() * light_step;
+            light_mie += (-height / MIE_HEIGHT).exp() * light_step;
+        }
+
+        let attenuation = extinction(rayleigh_depth + light_rayleigh, mie_depth + light_mie);
+        rayleigh += attenuation * rayleigh_step;
+        mie += attenuation * mie_step;
+    }
+
+    let mu = dir.dot(sun);
+    let phase_rayleigh = 3. / (16. * PI) * (1. + mu * mu);
+    let g2 = MIE_G * MIE_G;
+    let phase_mie = 3. / (8. * PI) * ((1. - g2) * (1. + mu * mu))
+        / ((1. + g2 - 2. * mu * MIE_G).powf(1.5) * (2. + g2));
+    (RAYLEIGH * rayleigh * phase_rayleigh + mie * (MIE * phase_mie)) * SUN_INTENSITY
+}
+
+// scales kcd/m^2 of the Preetham model into the renderer's radiance range
+const PREETHAM_SCALE: f64 = 0.04;
+
+// Perez et al. sky luminance distribution
+fn perez(theta: f64, gamma: f64, c: [f64; 5]) -> f64 {
+    (1. + c[0] * (c[1] / theta.cos()).exp())
+        * (1. + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos() * gamma.cos())
+}
+
+// Analytic daylight model from Preetham, Shirley and Smits, "A Practical Analytic Model
+// for Daylight" (1999). `turbidity` ranges from 2 (clear) to ~10 (hazy).
+pub fn preetham(dir: Vec3d, sun: Vec3d, turbidity: f64) -> Color {
+    let t = turbidity;
+    let theta_s = sun.y.clamp(0., 1.).acos();
+    // evaluate slightly above the horizon below it, the ground reflects the horizon sky
+    let dir = Vec3d::new(dir.x, dir.y.max(0.01), dir.z).normalized();
+    let theta = dir.y.acos();
+    let gamma = dir.dot(sun).clamp(-1., 1.).acos();
+
+    let coeffs_y = [
+        0.1787 * t - 1.4630,
+        -0.3554 * t + 0.4275,
+        -0.0227 * t + 5.3251,
+        0.1206 * t - 2.5771,
+        -0.0670 * t + 0.3703,
+    ];
+    let coeffs_x = [
+        -0.0193 * t - 0.2592,
+        -0.0665 * t + 0.0008,
+        -0.0004 * t + 0.2125,
+        -0.0641 * t - 0.8989,
+        -0.0033 * t + 0.0452,
+    ];
+    let coeffs_yc = [
+        -0.0167 * t - 0.2608,
+        -0.0950 * t + 0.0092,
+        -0.0079 * t + 0.2102,
+        -0.0441 * t - 1.6537,
+        -0.0109 * t + 0.0529,
+    ];
+
+    let chi = (4. / 9. - t / 120.) * (PI - 2. * theta_s);
+    let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+    let (ts, ts2, ts3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+    let zenith_x = t * t * (0.00166 * ts3 - 0.00375 * ts2 + 0.00209 * ts)
+        + t * (-0.02903 * ts3 + 0.06377 * ts2 - 0.03202 * ts + 0.00394)
+        + (0.11693 * ts3 - 0.21196 * ts2 + 0.06052 * ts + 0.25886);
+    let zenith_yc = t * t * (0.00275 * ts3 - 0.00610 * ts2 + 0.00317 * ts)
+        + t * (-0.04214 * ts3 + 0.08970 * ts2 - 0.04153 * ts + 0.00516)
+        + (0.15346 * ts3 - 0.26756 * ts2 + 0.06670 * ts + 0.26688);
+
+    let luminance = zenith_y * perez(theta, gamma, coeffs_y) / perez(0., theta_s, coeffs_y);
+    let x = zenith_x * perez(theta, gamma, coeffs_x) / perez(0., theta_s, coeffs_x);
+    let y = zenith_yc * perez(theta, gamma, coeffs_yc) / perez(0., theta_s, coeffs_yc);
+
+    // Yxy -> XYZ -> linear sRGB
+    let luminance = luminance.max(0.) * PREETHAM_SCALE;
+    let cx = x / y * luminance;
+    let cz = (1. - x - y) / y * luminance;
+    Color::new(
+        3.2406 * cx - 1.5372 * luminance - 0.4986 * cz,
+        -0.9689 * cx + 1.8758 * luminance + 0.0415 * cz,
+        0.0557 * cx - 0.2040 * luminance + 1.0570 * cz,
+    )
+    .max(Color::BLACK)
+}
@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::color::Color;
+use crate::error::Error;
+use crate::film::Film;
+use crate::image::Image;
+use crate::metadata::Stats;
+use crate::options::Options;
+use crate::render::render;
+
+// Renders `sprite_frames` frames evenly spread over `duration` seconds, so the flipbook
+// loops, and packs them row by row into one sheet. With `alpha` the coverage of every
+// frame goes into the alpha channel of the sheet. `parallel_frames` frames are rendered at
+// a time, which keeps many cores busy on small frames where the tiles alone don't.
+pub fn run(options: &Options) -> Result<(), Error> {
+    let frames = options.sprite_frames;
+    let columns = options
+        .sprite_columns
+        .unwrap_or_else(|| (frames as f64).sqrt().ceil() as usize)
+        .clamp(1, frames);
+    let rows = frames.div_ceil(columns);
+    let (width, height) = options.resolution();
+    let mut color = Film::new(columns * width, rows * height, Color::BLACK);
+    let mut alpha = Film::new(columns * width, rows * height, 0.);
+    let start = Instant::now();
+    let mut samples = 0;
+
+    // every frame gets an equal share of the threads, at least one
+    let parallel = options.parallel_frames.min(frames);
+    let mut frame_options = Vec::with_capacity(frames);
+    for frame in 0..frames {
+        let mut options = options.clone();
+        // set like any other option, so the frame's settings reproduce it
+        let time = options.time + options.duration * frame as f64 / frames as f64;
+        options.set("time", &time.to_string())?;
+        options.threads = (options.worker_threads() / parallel).max(1);
+        frame_options.push(options);
+    }
+    let next = AtomicUsize::new(0);
+    let rendered = Mutex::new(Vec::with_capacity(frames));
+    std::thread::scope(|s| {
+        for _ in 0..parallel {
+            s.spawn(|| loop {
+                let frame = next.fetch_add(1, Ordering::Relaxed);
+                let Some(options) = frame_options.get(frame) else {
+                    return;
+                };
+                info!("frame {}/{}, t = {:.3}", frame + 1, frames, options.time);
+                let image = render(&crate::default_camera(options), options);
+                rendered.lock().unwrap().push((frame, image));
+            });
+        }
+    });
+
+    for (frame, image) in rendered.into_inner().unwrap() {
+        samples += image.samples;
+        let (x0, y0) = ((frame % columns) * width, (frame / columns) * height);
+        color.blit(x0, y0, &image.color);
+        alpha.blit(x0, y0, &image.alpha);
+    }
+
+    let sheet = Image::new(color, Some(alpha).filter(|_| options.alpha));
+    let stats = Stats {
+        samples,
+        render: start.elapsed(),
+        ..Stats::default()
+    };
+    crate::output(options, &options.output, &sheet, stats)
+}
@@ -0,0 +1,137 @@
+use crate::error::Error;
+use crate::film::Film;
+use crate::noise::fractal_brownian_motion_2d;
+
+// empirical Lipschitz constant of the noise module's FBM, per unit of its input
+const FBM_LIPSCHITZ: f64 = 2.;
+
+// Grayscale heights in [0, 1] read from a PGM/PPM image, row 0 is the far edge (-z).
+pub struct Heightmap {
+    values: Film<f64>,
+    // largest height difference between neighbouring texels
+    max_step: f64,
+}
+
+fn parse_pnm(data: &[u8]) -> Result<Heightmap, Error> {
+    // header tokens are whitespace separated, `#` starts a comment
+    let mut pos = 0;
+    let mut token = || -> Result<String, String> {
+        loop {
+            while pos < data.len() && data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos < data.len() && data[pos] == b'#' {
+                while pos < data.len() && data[pos] != b'\n' {
+                    pos += 1;
+                }
+                continue;
+            }
+            break;
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err("unexpected end of file".to_string());
+        }
+        Ok(String::from_utf8_lossy(&data[start..pos]).into_owned())
+    };
+    let magic = token()?;
+    let number = |t: String| {
+        t.parse::<usize>()
+            .map_err(|_| format!("invalid header value '{}'", t))
+    };
+    let width = number(token()?)?;
+    let height = number(token()?)?;
+    let max = number(token()?)?.max(1) as f64;
+    let channels = match magic.as_str() {
+        "P2" | "P5" => 1,
+        "P3" | "P6" => 3,
+        _ => return Err(Error::Parse(format!("unsupported image type '{}'", magic))),
+    };
+    let count = width * height * channels;
+    let samples: Vec<f64> = if magic == "P2" || magic == "P3" {
+        (0..count)
+            .map(|_| token().and_then(number).map(|v| v as f64))
+            .collect::<Result<_, _>>()?
+    } else {
+        // a single whitespace byte separates the header from the raster
+        let start = pos + 1;
+        let bytes = if max > 255. { 2 } else { 1 };
+        let raster = data
+            .get(start..start + count * bytes)
+            .ok_or("truncated raster")?;
+        raster
+            .chunks(bytes)
+            .map(|b| b.iter().fold(0., |v, byte| v * 256. + *byte as f64))
+            .collect()
+    };
+    let values = samples
+        .chunks(channels)
+        .map(|c| c.iter().sum::<f64>() / (channels as f64 * max))
+        .collect();
+    let values = Film::from_vec(width, height, values);
+
+    let mut max_step: f64 = 0.;
+    for (x, y, v) in values.pixels() {
+        if x + 1 < width {
+            max_step = max_step.max((values[(x + 1, y)] - v).abs());
+        }
+        if y + 1 < height {
+            max_step = max_step.max((values[(x, y + 1)] - v).abs());
+        }
+    }
+    Ok(Heightmap { values, max_step })
+}
+
+impl Heightmap {
+    pub fn load(path: &str) -> Result<Heightmap, Error> {
+        let data = std::fs::read(path).map_err(|err| Error::io(path, err))?;
+        parse_pnm(&data).map_err(|err| err.in_file(path))
+    }
+
+    // bilinear lookup at (u, v) in [0, 1]^2, the border texels extend outwards
+    pub fn sample(&self, u: f64, v: f64) -> f64 {
+        self.values.sample_bilinear(u, v)
+    }
+
+    // memory the heights take
+    pub fn bytes(&self) -> usize {
+        self.values.width() * self.values.height() * std::mem::size_of::<f64>()
+    }
+}
+
+// Height field of a terrain: `amplitude` of FBM relief with features `scale` wide, or of
+// the heightmap stretched over a `size` wide square centered at the origin.
+pub struct Relief<'a> {
+    pub base: f64,
+    pub amplitude: f64,
+    pub scale: f64,
+    pub heightmap: Option<(&'a Heightmap, f64)>,
+}
+
+impl Relief<'_> {
+    pub fn height(&self, x: f64, z: f64) -> f64 {
+        let value = match self.heightmap {
+            Some((map, size)) => map.sample(x / size + 0.5, z / size + 0.5),
+            None => fractal_brownian_motion_2d(x / self.scale, z / self.scale),
+        };
+        self.base + self.amplitude * value
+    }
+
+    // bound on the slope of the height field
+    pub fn lipschitz(&self) -> f64 {
+        match self.heightmap {
+            Some((map, size)) => {
+                let texel = size / map.values.width().max(map.values.height()) as f64;
+                self.amplitude * map.max_step / texel
+            }
+            None => self.amplitude * FBM_LIPSCHITZ / self.scale,
+        }
+    }
+
+    pub fn top(&self) -> f64 {
+        self.base + self.amplitude.max(0.)
+    }
+}
@@ -0,0 +1,71 @@
+use std::str::FromStr;
+
+use crate::color::Color;
+use crate::noise::{fractal_brownian_motion, noise};
+use crate::vec3d::Vec3d;
+
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Texture {
+    Checker,
+    Dirt,
+}
+
+impl FromStr for Texture {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Texture, String> {
+        match s {
+            "checker" => Ok(Texture::Checker),
+            "dirt" => Ok(Texture::Dirt),
+            _ => Err(format!("unknown texture '{}'", s)),
+        }
+    }
+}
+
+fn checker(u: f64, v: f64) -> Color {
+    if (u.floor() + v.floor()) as i64 % 2 == 0 {
+        Color::gray(0.8)
+    } else {
+        Color::gray(0.3)
+    }
+}
+
+fn dirt(u: f64, v: f64) -> Color {
+    let soil = Color::new(0.35, 0.27, 0.18);
+    let dust = Color::new(0.55, 0.48, 0.38);
+    let pebbles = noise(Vec3d::new(u * 8., v * 8., 0.5));
+    let patches = fractal_brownian_motion(Vec3d::new(u, v, 0.));
+    Color::lerp(soil, dust, patches * 1.5 - 0.4) * (0.8 + 0.2 * pebbles)
+}
+
+impl Texture {
+    pub fn planar(self, u: f64, v: f64) -> Color {
+        match self {
+            Texture::Checker => checker(u, v),
+            Texture::Dirt => dirt(u, v),
+        }
+    }
+
+    // the name `from_str` reads
+    pub fn name(self) -> &'static str {
+        match self {
+            Texture::Checker => "checker",
+            Texture::Dirt => "dirt",
+        }
+    }
+
+    // Projects the texture along the three axes and blends the projections by the normal,
+    // so any surface can be textured without a parametrization.
+    pub fn triplanar(self, p: Vec3d, normal: Vec3d, scale: f64) -> Color {
+        let q = p * scale;
+        let w = Vec3d::new(
+            normal.x.abs().powi(4),
+            normal.y.abs().powi(4),
+            normal.z.abs().powi(4),
+        );
+        let w = w / w.sum();
+        self.planar(q.y, q.z) * w.x + self.planar(q.z, q.x) * w.y + self.planar(q.x, q.y) * w.z
+    }
+}
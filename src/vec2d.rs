@@ -0,0 +1,61 @@
+#![allow(dead_code)]
+use crate::vec3d::{convert, vector, Float, Vec3};
+
+// double precision, for screen coordinates and sample positions
+pub type Vec2d = Vec2<f64>;
+pub type Vec2f = Vec2<f32>;
+
+// laid out as two consecutive scalars, like `[T; 2]`
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Vec2<T> {
+    pub x: T,
+    pub y: T,
+}
+
+vector!(Vec2, 2, { x: 0, y: 1 });
+
+impl<T: Float> Vec2<T> {
+    // the vector in the plane z = `z`
+    pub fn extend(self, z: T) -> Vec3<T> {
+        Vec3 {
+            x: self.x,
+            y: self.y,
+            z,
+        }
+    }
+
+    // the vector turned a quarter counterclockwise
+    pub fn perp(self) -> Vec2<T> {
+        Vec2::new(-self.y, self.x)
+    }
+
+    // the z component of the cross product of the vectors in the plane
+    pub fn perp_dot(self, other: Vec2<T>) -> T {
+        self.x * other.y - self.y * other.x
+    }
+
+    pub fn angle(self) -> T {
+        self.y.atan2(self.x)
+    }
+}
+
+impl<T: Float> From<Vec3<T>> for Vec2<T> {
+    fn from(other: Vec3<T>) -> Vec2<T> {
+        other.xy()
+    }
+}
+
+convert!("mint", <T> mint::Vector2<T>, Vec2<T> { x, y });
+convert!("mint", <T> mint::Point2<T>, Vec2<T> { x, y });
+convert!("glam", glam::DVec2, Vec2d { x, y });
+convert!("glam", glam::Vec2, Vec2f { x, y });
+convert!("nalgebra", <T: nalgebra::Scalar> nalgebra::Vector2<T>, Vec2<T> { x, y });
+convert!("nalgebra", <T: nalgebra::Scalar> nalgebra::Point2<T>, Vec2<T> { x, y });
+convert!("cgmath", <T> cgmath::Vector2<T>, Vec2<T> { x, y });
+convert!("cgmath", <T> cgmath::Point2<T>, Vec2<T> { x, y });
+
+#[cfg(feature = "mint")]
+impl<T: Float> mint::IntoMint for Vec2<T> {
+    type MintType = mint::Vector2<T>;
+}
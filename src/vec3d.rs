@@ -1,309 +1,763 @@
 #![allow(dead_code)]
-use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
-use std::{cmp, fmt};
+use core::fmt;
+use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use core::str::FromStr;
 
-pub fn lerp(a: Vec3d, b: Vec3d, d: f64) -> Vec3d {
-    a + (b - a) * d.max(0.).min(1.)
+pub use crate::vec2d::{Vec2, Vec2d, Vec2f};
+pub use crate::vec4d::{Vec4, Vec4d, Vec4f};
+
+// double precision, what the renderer works in
+pub type Vec3d = Vec3<f64>;
+// single precision, half the memory and twice the SIMD lanes
+pub type Vec3f = Vec3<f32>;
+
+pub fn lerp<T: Float>(a: Vec3<T>, b: Vec3<T>, d: T) -> Vec3<T> {
+    a + (b - a) * d.clamp(T::ZERO, T::ONE)
 }
 
+// laid out as three consecutive scalars, like `[T; 3]`
 #[derive(Copy, Clone)]
-pub struct Vec3d {
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
+#[repr(C)]
+pub struct Vec3<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
 }
 
-impl Vec3d {
-    pub fn x_comp(self) -> Vec3d {
-        Vec3d::new(self.x, 0., 0.)
-    }
+// The operators, conversions and component-wise methods every vector type shares, for
+// the struct `$name` with the `$n` fields `$first`, `$f...` at the given indices.
+macro_rules! vector {
+    ($name:ident, $n:literal, { $first:ident: $i0:literal $(, $f:ident: $i:literal)* }) => {
+        impl<T: crate::vec3d::Float> $name<T> {
+            pub const fn new($first: T $(, $f: T)*) -> $name<T> {
+                $name { $first $(, $f)* }
+            }
+
+            pub const fn splat(v: T) -> $name<T> {
+                $name { $first: v $(, $f: v)* }
+            }
+
+            pub const fn zero() -> $name<T> {
+                $name::splat(T::ZERO)
+            }
+
+            pub const fn one() -> $name<T> {
+                $name::splat(T::ONE)
+            }
+
+            // the same vector in another precision
+            pub fn cast<U: crate::vec3d::Float>(self) -> $name<U> {
+                $name {
+                    $first: U::from_f64(self.$first.to_f64())
+                    $(, $f: U::from_f64(self.$f.to_f64()))*
+                }
+            }
+
+            // `f` applied to every component
+            pub fn map(self, f: impl Fn(T) -> T) -> $name<T> {
+                $name { $first: f(self.$first) $(, $f: f(self.$f))* }
+            }
+
+            // `f` applied to the components of both vectors pairwise
+            pub fn zip(self, other: $name<T>, f: impl Fn(T, T) -> T) -> $name<T> {
+                $name {
+                    $first: f(self.$first, other.$first) $(, $f: f(self.$f, other.$f))*
+                }
+            }
+
+            pub fn length(&self) -> T {
+                self.length_squared().sqrt()
+            }
+
+            pub fn length_squared(&self) -> T {
+                self.dot(*self)
+            }
+
+            pub fn scale(&self, scalar: T) -> $name<T> {
+                *self * scalar
+            }
+
+            pub fn mul_elem(self, other: $name<T>) -> $name<T> {
+                self * other
+            }
+
+            pub fn lerp(v1: $name<T>, v2: $name<T>, alpha: T) -> $name<T> {
+                v1 + (v2 - v1) * alpha.clamp(T::ZERO, T::ONE)
+            }
+
+            pub fn clamp(&self, min: T, max: T) -> $name<T> {
+                self.map(|v| v.max(min).min(max))
+            }
+
+            pub fn distance(&self, v: $name<T>) -> T {
+                (*self - v).length()
+            }
+
+            pub fn normalize(&mut self) {
+                let norm = self.length();
+                *self /= norm;
+            }
+
+            pub fn normalized(&self) -> $name<T> {
+                let scale = T::ONE / self.length();
+                *self * scale
+            }
+
+            pub fn normalized_by(&self, frac: T) -> $name<T> {
+                let scale = frac / self.length();
+                *self * scale
+            }
+
+            pub fn dot(&self, vec: $name<T>) -> T {
+                self.$first * vec.$first $(+ self.$f * vec.$f)*
+            }
+
+            pub fn abs(self) -> $name<T> {
+                self.map(T::abs)
+            }
+
+            pub fn floor(self) -> $name<T> {
+                self.map(T::floor)
+            }
+
+            // the part above the floor, in [0, 1) also for negative components
+            pub fn fract(self) -> $name<T> {
+                self - self.floor()
+            }
+
+            pub fn powf(self, exponent: T) -> $name<T> {
+                self.map(|v| v.powf(exponent))
+            }
+
+            pub fn exp(self) -> $name<T> {
+                self.map(T::exp)
+            }
+
+            pub fn sqrt(self) -> $name<T> {
+                self.map(T::sqrt)
+            }
+
+            pub fn signum(self) -> $name<T> {
+                self.map(T::signum)
+            }
+
+            pub fn recip(self) -> $name<T> {
+                self.map(T::recip)
+            }
+
+            // 0 for the components below `edge` and 1 for the others, like GLSL's `step`
+            pub fn step(self, edge: T) -> $name<T> {
+                self.map(|v| if v < edge { T::ZERO } else { T::ONE })
+            }
+
+            // Hermite interpolation from 0 at `edge0` to 1 at `edge1`, like GLSL's `smoothstep`
+            pub fn smoothstep(self, edge0: T, edge1: T) -> $name<T> {
+                let t = ((self - edge0) / (edge1 - edge0)).clamp(T::ZERO, T::ONE);
+                t * t * ($name::splat(T::from_f64(3.)) - t * T::TWO)
+            }
+
+            pub fn min_element(self) -> T {
+                self.$first $(.min(self.$f))*
+            }
+
+            pub fn max_element(self) -> T {
+                self.$first $(.max(self.$f))*
+            }
+
+            pub fn sum(self) -> T {
+                self.$first $(+ self.$f)*
+            }
+
+            pub fn average(self) -> T {
+                self.sum() / T::from_f64($n as f64)
+            }
+
+            pub fn min(self, other: impl Into<$name<T>>) -> $name<T> {
+                self.zip(other.into(), T::min)
+            }
+
+            pub fn max(self, other: impl Into<$name<T>>) -> $name<T> {
+                self.zip(other.into(), T::max)
+            }
+
+            pub fn as_slice(&self) -> [T; $n] {
+                [self.$first $(, self.$f)*]
+            }
+
+            // the components in order
+            pub fn iter(&self) -> core::array::IntoIter<T, $n> {
+                IntoIterator::into_iter(self.as_slice())
+            }
+
+            // whether no component differs by more than `epsilon`, never with NaNs
+            pub fn abs_diff_eq(self, other: $name<T>, epsilon: T) -> bool {
+                (self - other).iter().all(|d| d.abs() <= epsilon)
+            }
+
+            // Whether every component is within `epsilon`, or within `max_relative` of the
+            // larger of the two magnitudes, which suits values far from 1.
+            pub fn relative_eq(self, other: $name<T>, epsilon: T, max_relative: T) -> bool {
+                self.iter().zip(other.iter()).all(|(a, b)| {
+                    let diff = (a - b).abs();
+                    diff <= epsilon || diff <= max_relative * a.abs().max(b.abs())
+                })
+            }
+
+            pub fn is_finite(&self) -> bool {
+                self.$first.is_finite() $(&& self.$f.is_finite())*
+            }
+        }
 
-    pub fn y_comp(self) -> Vec3d {
-        Vec3d::new(0., self.y, 0.)
-    }
+        crate::vec3d::vector!(@op $name, Add, add, AddAssign, add_assign, +);
+        crate::vec3d::vector!(@op $name, Sub, sub, SubAssign, sub_assign, -);
+        // component-wise, the dot product is `dot`
+        crate::vec3d::vector!(@op $name, Mul, mul, MulAssign, mul_assign, *);
+        crate::vec3d::vector!(@op $name, Div, div, DivAssign, div_assign, /);
+        crate::vec3d::vector!(@scalar $name, f32);
+        crate::vec3d::vector!(@scalar $name, f64);
 
-    pub fn z_comp(self) -> Vec3d {
-        Vec3d::new(0., 0., self.z)
-    }
+        impl<T: crate::vec3d::Float> core::ops::Neg for $name<T> {
+            type Output = $name<T>;
 
-    pub fn new(x: impl Scalar, y: impl Scalar, z: impl Scalar) -> Vec3d {
-        Vec3d {
-            x: x.float(),
-            y: y.float(),
-            z: z.float(),
+            fn neg(self) -> $name<T> {
+                self.map(|v| -v)
+            }
         }
-    }
 
-    pub fn length(&self) -> f64 {
-        eucl(self.x, self.y, self.z)
-    }
+        impl<T: crate::vec3d::Float> PartialEq for $name<T> {
+            fn eq(&self, other: &$name<T>) -> bool {
+                self.$first == other.$first $(&& self.$f == other.$f)*
+            }
+        }
 
-    pub fn length_squared(&self) -> f64 {
-        self.x * self.x + self.y * self.y + self.z * self.z
-    }
+        // parses the comma-separated components, as used in command line options
+        impl<T: crate::vec3d::Float> core::str::FromStr for $name<T> {
+            type Err = alloc::string::String;
+
+            fn from_str(s: &str) -> Result<$name<T>, alloc::string::String> {
+                let c = s
+                    .split(',')
+                    .map(|c| c.trim().parse::<T>())
+                    .collect::<Result<alloc::vec::Vec<_>, _>>()
+                    .map_err(|_| alloc::format!("invalid vector '{}'", s))?;
+                match c.as_slice() {
+                    [$first $(, $f)*] => Ok($name::new(*$first $(, *$f)*)),
+                    _ => Err(alloc::format!("expected {} components in '{}'", $n, s)),
+                }
+            }
+        }
 
-    pub fn zero() -> Vec3d {
-        Vec3d::new(0., 0., 0.)
-    }
+        // the components separated by commas, the form `from_str` reads back
+        impl<T: crate::vec3d::Float> core::fmt::Display for $name<T> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                write!(f, "{}", self.$first)?;
+                $(write!(f, ",{}", self.$f)?;)*
+                Ok(())
+            }
+        }
 
-    pub fn one() -> Vec3d {
-        Vec3d::new(1., 1., 1.)
-    }
+        impl<T: crate::vec3d::Float> core::fmt::Debug for $name<T> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                write!(f, "({}", self.$first)?;
+                $(write!(f, ", {}", self.$f)?;)*
+                write!(f, ")")
+            }
+        }
 
-    pub fn scale(&self, scalar: f64) -> Vec3d {
-        Vec3d::new(self.x * scalar, self.y * scalar, self.z * scalar)
-    }
+        impl<T: crate::vec3d::Float, U: crate::vec3d::Scalar> From<[U; $n]> for $name<T> {
+            fn from(other: [U; $n]) -> $name<T> {
+                let [$first $(, $f)*] = other;
+                $name::new(T::from_f64($first.float()) $(, T::from_f64($f.float()))*)
+            }
+        }
 
-    pub fn lerp(v1: Vec3d, v2: Vec3d, alpha: f64) -> Vec3d {
-        v1 + (v2 - v1) * alpha.max(0.).min(1.)
-    }
+        impl<T> core::ops::Index<usize> for $name<T> {
+            type Output = T;
 
-    pub fn clamp(&self, min: f64, max: f64) -> Vec3d {
-        Vec3d::new(
-            self.x.max(min).min(max),
-            self.y.max(min).min(max),
-            self.z.max(min).min(max),
-        )
-    }
+            fn index(&self, index: usize) -> &Self::Output {
+                match index {
+                    $i0 => &self.$first,
+                    $($i => &self.$f,)*
+                    _ => panic!(concat!("Out of bounds in ", stringify!($name))),
+                }
+            }
+        }
 
-    pub fn distance(&self, v: Vec3d) -> f64 {
-        let dx = self.x - v.x;
-        let dy = self.y - v.y;
-        let dz = self.z - v.z;
-        eucl(dx, dy, dz)
-    }
+        impl<T> core::ops::IndexMut<usize> for $name<T> {
+            fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+                match index {
+                    $i0 => &mut self.$first,
+                    $($i => &mut self.$f,)*
+                    _ => panic!(concat!("Out of bounds in ", stringify!($name))),
+                }
+            }
+        }
 
-    pub fn normalize(&mut self) {
-        let norm = eucl(self.x, self.y, self.z);
-        self.x /= norm;
-        self.y /= norm;
-        self.z /= norm;
-    }
+        impl<T: crate::vec3d::Float> IntoIterator for $name<T> {
+            type Item = T;
+            type IntoIter = core::array::IntoIter<T, $n>;
 
-    pub fn normalized(&self) -> Vec3d {
-        let scale = 1. / self.length();
-        Vec3d::new(self.x * scale, self.y * scale, self.z * scale)
-    }
+            fn into_iter(self) -> Self::IntoIter {
+                IntoIterator::into_iter(self.as_slice())
+            }
+        }
 
-    pub fn normalized_by(&self, frac: f64) -> Vec3d {
-        let scale = frac / self.length();
-        Vec3d::new(self.x * scale, self.y * scale, self.z * scale)
-    }
+        // as an array of the components
+        #[cfg(feature = "serde")]
+        impl<T: crate::vec3d::Float + serde::Serialize> serde::Serialize for $name<T> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.as_slice().serialize(serializer)
+            }
+        }
 
-    pub fn dot(&self, vec: Vec3d) -> f64 {
-        self.x * vec.x + self.y * vec.y + self.z * vec.z
-    }
+        #[cfg(feature = "serde")]
+        impl<'de, T> serde::Deserialize<'de> for $name<T>
+        where
+            T: crate::vec3d::Float + serde::Deserialize<'de>,
+        {
+            fn deserialize<D>(deserializer: D) -> Result<$name<T>, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                <[T; $n]>::deserialize(deserializer)
+                    .map(|[$first $(, $f)*]| $name::new($first $(, $f)*))
+            }
+        }
 
-    pub fn cross(self, vec: Vec3d) -> Vec3d {
-        Vec3d::new(
-            self.y * vec.z - self.z * vec.y,
-            self.z * vec.x - self.x * vec.z,
-            self.x * vec.y - self.y * vec.x,
-        )
-    }
+        // scalars of one type without padding, any bit pattern is a valid vector
+        #[cfg(feature = "bytemuck")]
+        unsafe impl<T: crate::vec3d::Float + bytemuck::Zeroable> bytemuck::Zeroable for $name<T> {}
 
-    fn vec_from_angle(theta: f64, phi: f64) -> Vec3d {
-        Vec3d::new(
-            theta.sin() * phi.cos(),
-            theta.sin() * phi.sin(),
-            theta.cos(),
-        )
-    }
+        #[cfg(feature = "bytemuck")]
+        unsafe impl<T: crate::vec3d::Float + bytemuck::Pod> bytemuck::Pod for $name<T> {}
+    };
 
-    pub fn angle2d(self) -> f64 {
-        self.y.atan2(self.x)
-    }
+    // component-wise with another vector or with a scalar, and the assigning forms
+    (@op $name:ident, $op:ident, $method:ident, $assign:ident, $assign_method:ident, $sym:tt) => {
+        impl<T: crate::vec3d::Float> core::ops::$op for $name<T> {
+            type Output = $name<T>;
 
-    pub fn angle(self) -> (f64, f64) {
-        (self.y.atan2(self.x), self.z.cos())
-    }
+            fn $method(self, other: $name<T>) -> $name<T> {
+                self.zip(other, |a, b| a $sym b)
+            }
+        }
 
-    pub fn recip(self) -> Vec3d {
-        Vec3d::new(self.x.recip(), self.y.recip(), self.z.recip())
-    }
+        impl<T: crate::vec3d::Float> core::ops::$op<T> for $name<T> {
+            type Output = $name<T>;
 
-    pub fn min(self, other: impl Into<Vec3d>) -> Vec3d {
-        let other = other.into();
-        Vec3d::new(
-            self.x.min(other.x),
-            self.y.min(other.y),
-            self.z.min(other.z),
-        )
-    }
+            fn $method(self, other: T) -> $name<T> {
+                self.map(|a| a $sym other)
+            }
+        }
 
-    pub fn max(self, other: impl Into<Vec3d>) -> Vec3d {
-        let other = other.into();
-        Vec3d::new(
-            self.x.max(other.x),
-            self.y.max(other.y),
-            self.z.max(other.z),
-        )
-    }
+        impl<T: crate::vec3d::Float> core::ops::$assign for $name<T> {
+            fn $assign_method(&mut self, other: $name<T>) {
+                *self = *self $sym other;
+            }
+        }
 
-    pub fn as_slice(&self) -> [f64; 3] {
-        [self.x, self.y, self.z]
-    }
-}
+        impl<T: crate::vec3d::Float> core::ops::$assign<T> for $name<T> {
+            fn $assign_method(&mut self, other: T) {
+                *self = *self $sym other;
+            }
+        }
+    };
 
-pub fn eucl(x: impl Scalar, y: impl Scalar, z: impl Scalar) -> f64 {
-    let x = x.float();
-    let y = y.float();
-    let z = z.float();
-    (x * x + y * y + z * z).sqrt()
-}
+    // the scalar on the left, which has to be spelled out for every scalar type
+    (@scalar $name:ident, $t:ty) => {
+        impl core::ops::Add<$name<$t>> for $t {
+            type Output = $name<$t>;
 
-impl AddAssign for Vec3d {
-    fn add_assign(&mut self, other: Vec3d) {
-        self.x = self.x + other.x;
-        self.y = self.y + other.y;
-        self.z = self.z + other.z;
-    }
+            fn add(self, other: $name<$t>) -> $name<$t> {
+                other.map(|v| self + v)
+            }
+        }
+
+        impl core::ops::Sub<$name<$t>> for $t {
+            type Output = $name<$t>;
+
+            fn sub(self, other: $name<$t>) -> $name<$t> {
+                other.map(|v| self - v)
+            }
+        }
+
+        impl core::ops::Mul<$name<$t>> for $t {
+            type Output = $name<$t>;
+
+            fn mul(self, other: $name<$t>) -> $name<$t> {
+                other * self
+            }
+        }
+
+        impl core::ops::Div<$name<$t>> for $t {
+            type Output = $name<$t>;
+
+            fn div(self, other: $name<$t>) -> $name<$t> {
+                other.map(|v| self / v)
+            }
+        }
+    };
 }
 
-impl Add for Vec3d {
-    type Output = Vec3d;
+pub(crate) use vector;
+
+// `From` both ways between a vector and the vector or point type of another math library
+// with the same fields, behind the feature of that library.
+macro_rules! convert {
+    ($feature:literal, <T $(: $bound:path)?> $other:ty, $name:ident<T> { $($f:ident),+ }) => {
+        #[cfg(feature = $feature)]
+        impl<T: crate::vec3d::Float $(+ $bound)?> From<$other> for $name<T> {
+            fn from(other: $other) -> $name<T> {
+                $name::new($(other.$f),+)
+            }
+        }
 
-    fn add(self, other: Vec3d) -> Vec3d {
-        Vec3d::new(self.x + other.x, self.y + other.y, self.z + other.z)
-    }
+        #[cfg(feature = $feature)]
+        impl<T: crate::vec3d::Float $(+ $bound)?> From<$name<T>> for $other {
+            fn from(other: $name<T>) -> $other {
+                <$other>::from(other.as_slice())
+            }
+        }
+    };
+    ($feature:literal, $other:ty, $ours:ty { $($f:ident),+ }) => {
+        #[cfg(feature = $feature)]
+        impl From<$other> for $ours {
+            fn from(other: $other) -> $ours {
+                <$ours>::new($(other.$f),+)
+            }
+        }
+
+        #[cfg(feature = $feature)]
+        impl From<$ours> for $other {
+            fn from(other: $ours) -> $other {
+                <$other>::from(other.as_slice())
+            }
+        }
+    };
 }
 
-impl Add<f64> for Vec3d {
-    type Output = Vec3d;
+pub(crate) use convert;
 
-    fn add(self, other: f64) -> Vec3d {
-        Vec3d::new(self.x + other, self.y + other, self.z + other)
+vector!(Vec3, 3, { x: 0, y: 1, z: 2 });
+
+impl<T: Float> Vec3<T> {
+    pub fn x_comp(self) -> Vec3<T> {
+        Vec3::new(self.x, T::ZERO, T::ZERO)
     }
-}
 
-impl Sub for Vec3d {
-    type Output = Vec3d;
+    pub fn y_comp(self) -> Vec3<T> {
+        Vec3::new(T::ZERO, self.y, T::ZERO)
+    }
 
-    fn sub(self, other: Vec3d) -> Vec3d {
-        Vec3d::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    pub fn z_comp(self) -> Vec3<T> {
+        Vec3::new(T::ZERO, T::ZERO, self.z)
     }
-}
 
-impl Sub<f64> for Vec3d {
-    type Output = Vec3d;
+    // the x and y components
+    pub fn xy(self) -> Vec2<T> {
+        Vec2 {
+            x: self.x,
+            y: self.y,
+        }
+    }
 
-    fn sub(self, other: f64) -> Vec3d {
-        Vec3d::new(self.x - other, self.y - other, self.z - other)
+    // the point with the homogeneous coordinate `w`
+    pub fn extend(self, w: T) -> Vec4<T> {
+        Vec4 {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            w,
+        }
     }
-}
 
-impl Mul for Vec3d {
-    type Output = f64;
+    pub fn cross(self, vec: Vec3<T>) -> Vec3<T> {
+        Vec3::new(
+            self.y * vec.z - self.z * vec.y,
+            self.z * vec.x - self.x * vec.z,
+            self.x * vec.y - self.y * vec.x,
+        )
+    }
 
-    fn mul(self, other: Vec3d) -> f64 {
-        self.x * other.x + self.y * other.y + self.z * other.z
+    // the direction mirrored about the plane of the unit `normal`
+    pub fn reflect(self, normal: Vec3<T>) -> Vec3<T> {
+        self - normal * (T::TWO * self.dot(normal))
     }
-}
 
-impl Mul<f64> for Vec3d {
-    type Output = Vec3d;
+    // The unit direction bent through a surface whose unit `normal` faces against it,
+    // `eta` being the refractive index on the incoming side over the one on the other.
+    // Nothing on total internal reflection.
+    pub fn refract(self, normal: Vec3<T>, eta: T) -> Option<Vec3<T>> {
+        let cos_i = -self.dot(normal);
+        let k = T::ONE - eta * eta * (T::ONE - cos_i * cos_i);
+        if k < T::ZERO {
+            return None;
+        }
+        Some(self * eta + normal * (eta * cos_i - k.sqrt()))
+    }
 
-    fn mul(self, other: f64) -> Vec3d {
-        Vec3d::new(self.x * other, self.y * other, self.z * other)
+    // the normal flipped if needed to face against the incoming direction `i`, for
+    // two-sided shading
+    pub fn faceforward(self, i: Vec3<T>) -> Vec3<T> {
+        if i.dot(self) < T::ZERO {
+            self
+        } else {
+            -self
+        }
     }
-}
 
-impl Div for Vec3d {
-    type Output = Vec3d;
+    // Two unit vectors completing the unit `self` to a right-handed orthonormal basis,
+    // branchless after Duff et al., "Building an Orthonormal Basis, Revisited" (2017).
+    pub fn orthonormal_basis(self) -> (Vec3<T>, Vec3<T>) {
+        let sign = self.z.signum();
+        let a = -(sign + self.z).recip();
+        let b = self.x * self.y * a;
+        (
+            Vec3::new(
+                T::ONE + sign * self.x * self.x * a,
+                sign * b,
+                -sign * self.x,
+            ),
+            Vec3::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
 
-    fn div(self, other: Vec3d) -> Vec3d {
-        Vec3d::new(self.x / other.x, self.y / other.y, self.z / other.z)
+    // the unit vector `theta` radians off +z and `phi` around it counterclockwise from +x
+    pub fn from_spherical(theta: T, phi: T) -> Vec3<T> {
+        Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        )
     }
-}
 
-impl Div<f64> for Vec3d {
-    type Output = Vec3d;
+    // The polar angle in [0, pi] and the azimuth in (-pi, pi] of the direction,
+    // `from_spherical` inverted. The azimuth is 0 along the z axis and both are 0 for zero.
+    pub fn to_spherical(self) -> (T, T) {
+        let length = self.length();
+        if length == T::ZERO {
+            return (T::ZERO, T::ZERO);
+        }
+        (
+            (self.z / length).clamp(-T::ONE, T::ONE).acos(),
+            self.angle2d(),
+        )
+    }
 
-    fn div(self, other: f64) -> Vec3d {
-        Vec3d::new(self.x / other, self.y / other, self.z / other)
+    pub fn angle2d(self) -> T {
+        self.y.atan2(self.x)
     }
-}
 
-impl Neg for Vec3d {
-    type Output = Vec3d;
+    // the azimuth and the polar angle, the reverse of `to_spherical`
+    pub fn angle(self) -> (T, T) {
+        let (theta, phi) = self.to_spherical();
+        (phi, theta)
+    }
 
-    fn neg(self) -> Vec3d {
-        Vec3d::new(-self.x, -self.y, -self.z)
+    // relative luminance of a linear color with the Rec. 709 primaries
+    pub fn luminance(self) -> T {
+        T::from_f64(0.2126) * self.x + T::from_f64(0.7152) * self.y + T::from_f64(0.0722) * self.z
     }
 }
 
-impl cmp::PartialEq for Vec3d {
-    fn eq(&self, other: &Vec3d) -> bool {
-        self.x == other.x && self.y == other.y && self.z == other.z
-    }
+pub fn eucl(x: impl Scalar, y: impl Scalar, z: impl Scalar) -> f64 {
+    let x = x.float();
+    let y = y.float();
+    let z = z.float();
+    (x * x + y * y + z * z).sqrt()
 }
 
-impl fmt::Debug for Vec3d {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "({}, {}, {})", self.x, self.y, self.z)
+impl<T: Float, U: Scalar, V: Scalar> From<(U, V)> for Vec3<T> {
+    fn from(other: (U, V)) -> Vec3<T> {
+        Vec3::new(
+            T::from_f64(other.0.float()),
+            T::from_f64(other.1.float()),
+            T::ZERO,
+        )
     }
 }
 
-impl<T: Scalar, U: Scalar> From<(T, U)> for Vec3d {
-    fn from(other: (T, U)) -> Vec3d {
-        Vec3d::new(other.0, other.1, 0.)
+impl<T: Float, U: Scalar> From<[U; 2]> for Vec3<T> {
+    fn from(other: [U; 2]) -> Vec3<T> {
+        Vec3::new(
+            T::from_f64(other[0].float()),
+            T::from_f64(other[1].float()),
+            T::ZERO,
+        )
     }
 }
 
-impl<T: Scalar> From<[T; 2]> for Vec3d {
-    fn from(other: [T; 2]) -> Vec3d {
-        Vec3d::new(other[0], other[1], 0.)
+impl<T: Float, U: Scalar, V: Scalar, W: Scalar> From<(U, V, W)> for Vec3<T> {
+    fn from(other: (U, V, W)) -> Vec3<T> {
+        Vec3::new(
+            T::from_f64(other.0.float()),
+            T::from_f64(other.1.float()),
+            T::from_f64(other.2.float()),
+        )
     }
 }
 
-impl<T: Scalar, U: Scalar, Y: Scalar> From<(T, U, Y)> for Vec3d {
-    fn from(other: (T, U, Y)) -> Vec3d {
-        Vec3d::new(other.0, other.1, other.2)
-    }
+convert!("mint", <T> mint::Vector3<T>, Vec3<T> { x, y, z });
+convert!("mint", <T> mint::Point3<T>, Vec3<T> { x, y, z });
+convert!("glam", glam::DVec3, Vec3d { x, y, z });
+convert!("glam", glam::Vec3, Vec3f { x, y, z });
+convert!("nalgebra", <T: nalgebra::Scalar> nalgebra::Vector3<T>, Vec3<T> { x, y, z });
+convert!("nalgebra", <T: nalgebra::Scalar> nalgebra::Point3<T>, Vec3<T> { x, y, z });
+convert!("cgmath", <T> cgmath::Vector3<T>, Vec3<T> { x, y, z });
+convert!("cgmath", <T> cgmath::Point3<T>, Vec3<T> { x, y, z });
+
+#[cfg(feature = "mint")]
+impl<T: Float> mint::IntoMint for Vec3<T> {
+    type MintType = mint::Vector3<T>;
 }
 
-impl<T: Scalar> From<[T; 3]> for Vec3d {
-    fn from(other: [T; 3]) -> Vec3d {
-        Vec3d::new(other[0], other[1], other[2])
-    }
+// The scalar types vectors are made of, forwarding to the inherent methods of f32 and f64,
+// or to `libm` without `std`. In scope, it gives the core math those methods in `no_std`.
+pub trait Float:
+    Scalar
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+    + FromStr
+    + fmt::Display
+    + fmt::Debug
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const TWO: Self;
+
+    fn from_f64(v: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn floor(self) -> Self;
+    fn rem_euclid(self, rhs: Self) -> Self;
+    fn powf(self, exponent: Self) -> Self;
+    fn powi(self, exponent: i32) -> Self;
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn signum(self) -> Self;
+    fn recip(self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn clamp(self, min: Self, max: Self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn sin_cos(self) -> (Self, Self);
+    fn tan(self) -> Self;
+    fn acos(self) -> Self;
+    fn atan2(self, other: Self) -> Self;
+    fn is_finite(self) -> bool;
 }
 
-impl std::ops::Index<usize> for Vec3d {
-    type Output = f64;
+// The inherent method call on the left with `std`, the `libm` function on the right, in the
+// precision of `$t`, without it. The methods core has are called directly.
+#[cfg(feature = "std")]
+macro_rules! math {
+    ($t:ident::$method:ident($($arg:expr),*) / $($libm:tt)*) => {
+        $t::$method($($arg),*)
+    };
+}
+
+#[cfg(not(feature = "std"))]
+macro_rules! math {
+    ($t:ident::$method:ident($($arg:expr),*) / $libm:ident($($libm_arg:expr),*)) => {
+        libm::Libm::<$t>::$libm($($libm_arg),*)
+    };
+}
 
-    fn index(&self, index: usize) -> &Self::Output {
-        match index {
-            0 => &self.x,
-            1 => &self.y,
-            2 => &self.z,
-            _ => panic!("Out of bounds in Vec3d"),
+macro_rules! float {
+    ($t:ident) => {
+        impl Float for $t {
+            const ZERO: $t = 0.;
+            const ONE: $t = 1.;
+            const TWO: $t = 2.;
+
+            fn from_f64(v: f64) -> $t {
+                v as $t
+            }
+            fn to_f64(self) -> f64 {
+                f64::from(self)
+            }
+            fn sqrt(self) -> $t {
+                math!($t::sqrt(self) / sqrt(self))
+            }
+            fn abs(self) -> $t {
+                $t::abs(self)
+            }
+            fn floor(self) -> $t {
+                math!($t::floor(self) / floor(self))
+            }
+            // as std computes it, `%` is in core
+            fn rem_euclid(self, rhs: $t) -> $t {
+                let r = self % rhs;
+                if r < 0. {
+                    r + rhs.abs()
+                } else {
+                    r
+                }
+            }
+            fn powf(self, exponent: $t) -> $t {
+                math!($t::powf(self, exponent) / pow(self, exponent))
+            }
+            fn powi(self, exponent: i32) -> $t {
+                math!($t::powi(self, exponent) / pow(self, exponent as $t))
+            }
+            fn exp(self) -> $t {
+                math!($t::exp(self) / exp(self))
+            }
+            fn ln(self) -> $t {
+                math!($t::ln(self) / log(self))
+            }
+            fn signum(self) -> $t {
+                $t::signum(self)
+            }
+            fn recip(self) -> $t {
+                $t::recip(self)
+            }
+            fn min(self, other: $t) -> $t {
+                $t::min(self, other)
+            }
+            fn max(self, other: $t) -> $t {
+                $t::max(self, other)
+            }
+            fn clamp(self, min: $t, max: $t) -> $t {
+                $t::clamp(self, min, max)
+            }
+            fn sin(self) -> $t {
+                math!($t::sin(self) / sin(self))
+            }
+            fn cos(self) -> $t {
+                math!($t::cos(self) / cos(self))
+            }
+            fn sin_cos(self) -> ($t, $t) {
+                math!($t::sin_cos(self) / sincos(self))
+            }
+            fn tan(self) -> $t {
+                math!($t::tan(self) / tan(self))
+            }
+            fn acos(self) -> $t {
+                math!($t::acos(self) / acos(self))
+            }
+            fn atan2(self, other: $t) -> $t {
+                math!($t::atan2(self, other) / atan2(self, other))
+            }
+            fn is_finite(self) -> bool {
+                $t::is_finite(self)
+            }
         }
-    }
+    };
 }
 
-// impl<T: Scalar> From<cgmath::Point2<T>> for Vec3d {
-//     fn from(other: cgmath::Point2<T>) -> Vec3d {
-//         Vec3d::new2d(other[0], other[1])
-//     }
-// }
-
-// impl<T: Scalar> From<cgmath::Point3<T>> for Vec3d {
-//     fn from(other: cgmath::Point3<T>) -> Vec3d {
-//         Vec3d::new(other[0], other[1], other[2])
-//     }
-// }
-
-// impl<T: Scalar+std::fmt::Debug+std::cmp::PartialEq> From<nalgebra::geometry::Point2<T>> for Vec3d {
-//     fn from(other: nalgebra::geometry::Point3<T>) -> Vec3d {
-//         Vec3d::new2d(other[0], other[1])
-//     }
-// }
-
-// impl<T: Scalar> From<nalgebra::geometry::Point3<T>> for Vec3d {
-//     fn from(other: nalgebra::geometry::Point3<T>) -> Vec3d {
-//         Vec3d::new(other[0], other[1], other[2])
-//     }
-// }
+float!(f32);
+float!(f64);
 
 pub trait Scalar: Copy {
     fn float(self) -> f64;
@@ -351,3 +805,81 @@ impl Scalar for f64 {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Vec3d;
+
+    const EPSILON: f64 = 1e-12;
+
+    #[test]
+    fn reflect_mirrors_about_the_normal() {
+        let up = Vec3d::new(0., 1., 0.);
+        let reflected = Vec3d::new(1., -1., 0.).reflect(up);
+        assert!(reflected.abs_diff_eq(Vec3d::new(1., 1., 0.), EPSILON));
+        // grazing directions are left as they are
+        let grazing = Vec3d::new(0., 0., 1.);
+        assert!(grazing.reflect(up).abs_diff_eq(grazing, EPSILON));
+    }
+
+    #[test]
+    fn refract_follows_snells_law() {
+        let up = Vec3d::new(0., 1., 0.);
+        let eta = 1. / 1.5;
+        // straight through at normal incidence
+        let down = Vec3d::new(0., -1., 0.);
+        assert!(down.refract(up, eta).unwrap().abs_diff_eq(down, EPSILON));
+        // at 45 degrees into glass the sine of the angle shrinks by eta
+        let incoming = Vec3d::new(1., -1., 0.).normalized();
+        let refracted = incoming.refract(up, eta).unwrap();
+        assert!((refracted.length() - 1.).abs() < EPSILON);
+        assert!((refracted.x - incoming.x * eta).abs() < EPSILON);
+        assert!(refracted.y < 0.);
+    }
+
+    #[test]
+    fn refract_is_none_on_total_internal_reflection() {
+        let up = Vec3d::new(0., 1., 0.);
+        // out of glass at 45 degrees is past the critical angle of about 41.8
+        let incoming = Vec3d::new(1., -1., 0.).normalized();
+        assert!(incoming.refract(up, 1.5).is_none());
+        assert!(Vec3d::new(0., -1., 0.).refract(up, 1.5).is_some());
+    }
+
+    #[test]
+    fn faceforward_faces_against_the_incoming_direction() {
+        let normal = Vec3d::new(0., 1., 0.);
+        let down = Vec3d::new(0.3, -1., 0.);
+        assert_eq!(normal.faceforward(down), normal);
+        assert_eq!(normal.faceforward(-down), -normal);
+    }
+
+    #[test]
+    fn abs_diff_eq_allows_epsilon_per_component() {
+        let a = Vec3d::new(1., 2., 3.);
+        assert!(a.abs_diff_eq(a, 0.));
+        assert!(a.abs_diff_eq(Vec3d::new(1.001, 1.999, 3.), 0.002));
+        assert!(!a.abs_diff_eq(Vec3d::new(1., 2., 3.01), 0.002));
+    }
+
+    #[test]
+    fn relative_eq_scales_with_magnitude() {
+        let big = Vec3d::new(1e9, -1e9, 0.);
+        let close = Vec3d::new(1e9 + 1., -1e9 - 1., 0.);
+        assert!(!big.abs_diff_eq(close, 1e-6));
+        assert!(big.relative_eq(close, 1e-6, 1e-8));
+        assert!(!big.relative_eq(close, 1e-6, 1e-10));
+        // near zero only the epsilon applies
+        let tiny = Vec3d::new(1e-12, 0., 0.);
+        assert!(tiny.relative_eq(Vec3d::zero(), 1e-9, 1e-8));
+        assert!(!tiny.relative_eq(Vec3d::zero(), 0., 1e-8));
+    }
+
+    #[test]
+    fn nans_are_never_equal() {
+        let nan = Vec3d::new(f64::NAN, 0., 0.);
+        assert!(!nan.abs_diff_eq(nan, f64::INFINITY));
+        assert!(!nan.relative_eq(nan, f64::INFINITY, 1.));
+        assert!(!nan.abs_diff_eq(Vec3d::zero(), 1.));
+    }
+}
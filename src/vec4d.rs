@@ -0,0 +1,45 @@
+#![allow(dead_code)]
+use crate::vec3d::{convert, vector, Float, Vec3};
+
+// double precision, for homogeneous coordinates and colors with alpha
+pub type Vec4d = Vec4<f64>;
+pub type Vec4f = Vec4<f32>;
+
+// laid out as four consecutive scalars, like `[T; 4]`
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Vec4<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+    pub w: T,
+}
+
+vector!(Vec4, 4, { x: 0, y: 1, z: 2, w: 3 });
+
+impl<T: Float> Vec4<T> {
+    // the x, y and z components, dropping w
+    pub fn xyz(self) -> Vec3<T> {
+        Vec3 {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+        }
+    }
+
+    // the point of homogeneous coordinates, divided by w
+    pub fn project(self) -> Vec3<T> {
+        self.xyz() / self.w
+    }
+}
+
+convert!("mint", <T> mint::Vector4<T>, Vec4<T> { x, y, z, w });
+convert!("glam", glam::DVec4, Vec4d { x, y, z, w });
+convert!("glam", glam::Vec4, Vec4f { x, y, z, w });
+convert!("nalgebra", <T: nalgebra::Scalar> nalgebra::Vector4<T>, Vec4<T> { x, y, z, w });
+convert!("cgmath", <T> cgmath::Vector4<T>, Vec4<T> { x, y, z, w });
+
+#[cfg(feature = "mint")]
+impl<T: Float> mint::IntoMint for Vec4<T> {
+    type MintType = mint::Vector4<T>;
+}
@@ -0,0 +1,174 @@
+use std::f64::consts::PI;
+
+use crate::bench::count_evaluation;
+use crate::color::Color;
+use crate::integrator::{shade, Aov};
+use crate::options::Options;
+use crate::ray::Ray;
+use crate::sampler::PixelSampler;
+use crate::scene::{Object, Scene, Shape};
+use crate::sky::{background, fog};
+use crate::vec3d::Vec3d;
+
+const STEP: f64 = 0.04;
+const SHADOW_STEPS: usize = 8;
+// thickness of the soft shell over which the density ramps up below the SDF surface
+const FALLOFF: f64 = 0.15;
+const MIN_TRANSMITTANCE: f64 = 0.01;
+// radiance emitted by the hottest parts of the fireball per unit density
+const EMISSION: f64 = 0.6;
+
+fn fireballs(scene: &Scene) -> impl Iterator<Item = &Object> {
+    scene
+        .objects
+        .iter()
+        .filter(|object| object.shape == Shape::Fireball)
+}
+
+// Extinction coefficient of a fireball, its SDF is treated as an iso-surface of density.
+fn object_density(options: &Options, object: &Object, p: Vec3d) -> f64 {
+    count_evaluation();
+    (-object.distance(p) / FALLOFF).clamp(0., 1.) * options.density
+}
+
+pub fn density(options: &Options, scene: &Scene, p: Vec3d) -> f64 {
+    fireballs(scene)
+        .map(|object| object_density(options, object, p))
+        .sum()
+}
+
+// light emitted at `p` per unit density, the fireballs' palettes weighted by their density
+fn emission(options: &Options, scene: &Scene, p: Vec3d) -> Color {
+    let mut emitted = Color::BLACK;
+    for object in fireballs(scene) {
+        let sigma = object_density(options, object, p);
+        if sigma > 0. {
+            // 0 for cold smoke and 1 in the hot core
+            let heat = object.temperature(p);
+            let color = object.material.palette.color(heat);
+            // the smoke shell only scatters
+            let gain = match object.smoke(p) {
+                Some((cover, _)) => object.material.heat.gain * (1. - cover),
+                None => object.material.heat.gain,
+            };
+            emitted += color * (sigma * (heat - 0.25).max(0.) / 0.75 * EMISSION * gain);
+        }
+    }
+    emitted
+}
+
+fn henyey_greenstein(cos_theta: f64, g: f64) -> f64 {
+    let denom = 1. + g * g - 2. * g * cos_theta;
+    (1. - g * g) / (4. * PI * denom * denom.sqrt())
+}
+
+// the ray segment covering the bounding spheres of all fireballs
+fn bounds(scene: &Scene, orig: Vec3d, dir: Vec3d) -> Option<(f64, f64)> {
+    fireballs(scene)
+        .filter_map(|object| object.bounds()?.hit(Ray::new(orig, dir)))
+        .reduce(|(near, far), (t0, t1)| (near.min(t0), far.max(t1)))
+}
+
+// whether the ray passes through the bounding sphere of a fireball
+pub fn crosses(scene: &Scene, orig: Vec3d, dir: Vec3d) -> bool {
+    bounds(scene, orig, dir).is_some()
+}
+
+// Fraction of the light `distance` away from `p` towards `light_dir` that reaches it through
+// the volume, for points in the smoke as well as the surfaces it casts its shadow on.
+pub fn shadow_transmittance(
+    options: &Options,
+    scene: &Scene,
+    p: Vec3d,
+    light_dir: Vec3d,
+    distance: f64,
+) -> f64 {
+    if !options.light.shadows {
+        return 1.;
+    }
+    let (near, far) = match bounds(scene, p, light_dir) {
+        Some((near, far)) if near < far.min(distance) => (near, far.min(distance)),
+        _ => return 1.,
+    };
+    let step = (far - near) / SHADOW_STEPS as f64;
+    let mut optical_depth = 0.;
+    for i in 0..SHADOW_STEPS {
+        let t = near + (i as f64 + 0.5) * step;
+        optical_depth += density(options, scene, p + light_dir * t) * step;
+    }
+    (-optical_depth).exp()
+}
+
+// Ray marches the fireballs as a heterogeneous participating medium: the hot core
+// emits its palette color, the smoke absorbs and singly scatters the light with a
+// Henyey-Greenstein phase function, attenuated by shadow rays marched through the volume.
+pub fn volume(
+    options: &Options,
+    scene: &Scene,
+    orig: Vec3d,
+    dir: Vec3d,
+    sampler: &mut PixelSampler,
+    aov: &mut Aov,
+) -> Color {
+    // surfaces are shaded behind the volume, fireballs only exist as a medium here
+    let surface = scene.intersect_filtered(Ray::new(orig, dir), |object| {
+        object.shape != Shape::Fireball
+    });
+    let surface_distance = surface.map_or(f64::INFINITY, |hit| hit.distance);
+
+    let mut radiance = Color::BLACK;
+    let mut transmittance = 1.;
+    if let Some((near, far)) = bounds(scene, orig, dir) {
+        let far = far.min(surface_distance);
+        let light = &options.light;
+        let jitter = sampler.next_2d().x;
+        let mut t = near + jitter * STEP;
+        while t < far && transmittance > MIN_TRANSMITTANCE {
+            let p = orig + dir * t;
+            let sigma = density(options, scene, p);
+            if sigma > 0. {
+                if !aov.depth.is_finite() {
+                    aov.depth = t;
+                }
+                let to_light = light.position - p;
+                let light_dir = to_light.normalized();
+                let irradiance = light.color
+                    * (light.intensity / to_light.length_squared()
+                        * light.profile(p)
+                        * shadow_transmittance(options, scene, p, light_dir, to_light.length()));
+                let scattered = irradiance
+                    * henyey_greenstein(light_dir.dot(dir), options.anisotropy)
+                    * options.scattering_albedo;
+                let emitted = emission(options, scene, p) / sigma;
+                let step_transmittance = (-sigma * STEP).exp();
+                // analytic integration of the in-scattered and emitted light over the step
+                radiance += (scattered + emitted) * (transmittance * (1. - step_transmittance));
+                transmittance *= step_transmittance;
+            }
+            t += STEP;
+        }
+    }
+
+    let behind = match surface {
+        Some(hit) => {
+            let mut surface_aov = Aov::default();
+            let color = shade(options, scene, &hit, dir, sampler, &mut surface_aov);
+            if !aov.depth.is_finite() {
+                *aov = surface_aov;
+            }
+            aov.opacity = 1.;
+            color
+        }
+        None => {
+            aov.opacity = 1. - transmittance;
+            background(options, dir)
+        }
+    };
+    let color = radiance + behind * transmittance;
+    if aov.depth.is_finite() {
+        let (fog_transmittance, inscatter) = fog(options, aov.depth);
+        color * fog_transmittance + inscatter
+    } else {
+        color
+    }
+}
@@ -0,0 +1,32 @@
+use std::path::PathBuf;
+
+use rustaboom::diff::{assert_similar, compare, Tolerance};
+use rustaboom::render_reference;
+
+// a small scene file in the temporary directory, with `object` as its fireball's keys
+fn scene(name: &str, object: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rustaboom-golden-{}.toml", name));
+    let text = format!("width = 32\nheight = 24\n\n[[object]]\n{}\n", object);
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+#[test]
+fn reference_renders_are_deterministic() {
+    let path = scene("same", "radius = 1.5");
+    let path = path.to_str().unwrap();
+    let golden = render_reference(path, 7).unwrap();
+    let image = render_reference(path, 7).unwrap();
+    assert_eq!(compare(&image, &golden).unwrap().ssim, 1.);
+    assert_similar(&image, &golden, Tolerance::default()).unwrap();
+}
+
+#[test]
+fn changed_settings_trip_the_tolerance() {
+    let golden = render_reference(scene("golden", "radius = 1.5").to_str().unwrap(), 7).unwrap();
+    let smaller = render_reference(scene("smaller", "radius = 1").to_str().unwrap(), 7).unwrap();
+    assert!(assert_similar(&smaller, &golden, Tolerance::default()).is_err());
+    let reseeded =
+        render_reference(scene("reseeded", "radius = 1.5").to_str().unwrap(), 8).unwrap();
+    assert!(compare(&reseeded, &golden).unwrap().ssim < 1.);
+}